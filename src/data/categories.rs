@@ -0,0 +1,490 @@
+use std::{path::Path, time::Duration};
+
+use serde_json::json;
+
+use crate::{
+    models::error::{JsonFileErrorKind, StorageError, StorageErrorKind},
+    utils::{
+        file_utils::{open_json, save_json, JsonStructure},
+        lock_utils::FileLock,
+    },
+};
+
+// Archivos de la lista de categorias, dentro del directorio de datos
+pub const CATEGORIES_FILE: &str = "categories.json";
+const CATEGORIES_LOCK_FILE: &str = "categories.lock";
+
+// Separador entre los niveles de una categoria: "Transporte > Gasolina"
+pub const CATEGORY_SEPARATOR: &str = " > ";
+
+// Niveles de la categoria `name`, sin los espacios alrededor de cada uno.
+// Acepta el separador con o sin espacios ("Transporte>Gasolina").
+pub fn category_levels(name: &str) -> Vec<&str> {
+    name.split('>').map(|level| level.trim()).collect()
+}
+
+// La categoria `name` cortada en el nivel `depth`: con depth 1,
+// "Transporte > Gasolina" queda "Transporte"
+pub fn category_prefix(name: &str, depth: usize) -> String {
+    let levels = category_levels(name);
+    levels[..depth.min(levels.len())].join(CATEGORY_SEPARATOR)
+}
+
+// Escribe la categoria con el separador normalizado. Falla si esta vacia o
+// tiene un nivel vacio ("Transporte > ").
+fn normalize(name: &str, location: &str) -> Result<String, StorageError> {
+    let levels = category_levels(name);
+    if levels.iter().any(|level| level.is_empty()) {
+        let message = if name.trim().is_empty() {
+            "El nombre de la categoria no puede estar vacio".to_string()
+        } else {
+            format!("La categoria {} tiene un nivel vacio", name.trim())
+        };
+        return Err(StorageError::new(StorageErrorKind::WriteError, &message, location));
+    }
+    Ok(levels.join(CATEGORY_SEPARATOR))
+}
+
+// Arbol de categorias permitidas. Cada nodo se guarda con su ruta completa
+// ("Transporte > Gasolina") y su padre siempre esta en la lista. Los nombres se
+// comparan sin distinguir mayusculas ("Bus" y "bus" son la misma categoria) y
+// se conserva la forma con la que se creo cada una.
+#[derive(Debug, Default, Clone)]
+pub struct CategoryList {
+    names: Vec<String>,
+}
+
+fn not_found(name: &str, location: &str) -> StorageError {
+    StorageError::new(
+        StorageErrorKind::NotFound,
+        &format!("No existe la categoria {}", name),
+        location,
+    )
+}
+
+// `name` es `ancestor` o una de sus subcategorias (ambos en minusculas)
+fn is_within(name: &str, ancestor: &str) -> bool {
+    name == ancestor
+        || name
+            .strip_prefix(ancestor)
+            .is_some_and(|rest| rest.starts_with(CATEGORY_SEPARATOR))
+}
+
+// Nombre de `name`, que esta dentro de `from`, si `from` pasa a llamarse `to`:
+// "Auto > Gasolina" pasa de "Auto" a "Transporte" como "Transporte > Gasolina"
+fn move_to(name: &str, from: &str, to: &str) -> String {
+    let depth = category_levels(from).len();
+    let mut levels = vec![to];
+    levels.extend(category_levels(name).into_iter().skip(depth));
+    levels.join(CATEGORY_SEPARATOR)
+}
+
+impl CategoryList {
+    pub fn new() -> CategoryList {
+        CategoryList::default()
+    }
+
+    // Las categorias en orden alfabetico; cada una aparece antes que sus
+    // subcategorias
+    pub fn names(&self) -> &[String] {
+        &self.names
+    }
+
+    // Nombre guardado de la categoria `name`, sin importar mayusculas
+    pub fn find(&self, name: &str) -> Option<&str> {
+        let name = category_levels(name).join(CATEGORY_SEPARATOR).to_lowercase();
+        self.names
+            .iter()
+            .find(|current| current.to_lowercase() == name)
+            .map(|current| current.as_str())
+    }
+
+    // La categoria `name` y todas sus subcategorias, con el nombre guardado
+    pub fn subtree(&self, name: &str) -> Vec<String> {
+        let name = name.to_lowercase();
+        self.names
+            .iter()
+            .filter(|current| is_within(&current.to_lowercase(), &name))
+            .cloned()
+            .collect()
+    }
+
+    // Agrega una categoria nueva y devuelve su nombre tal como quedo guardado.
+    // Los niveles superiores que falten se crean tambien, y los que ya existen
+    // conservan su forma: con "Transporte" en la lista, "transporte > Taxi"
+    // queda "Transporte > Taxi".
+    pub fn add(&mut self, name: &str) -> Result<String, StorageError> {
+        let location = "CategoryList::add";
+        let name = normalize(name, location)?;
+        if let Some(current) = self.find(&name) {
+            return Err(StorageError::new(
+                StorageErrorKind::AlreadyExists,
+                &format!("La categoria {} ya existe", current),
+                location,
+            ));
+        }
+
+        let mut path = String::new();
+        for level in category_levels(&name) {
+            if !path.is_empty() {
+                path.push_str(CATEGORY_SEPARATOR);
+            }
+            path.push_str(level);
+            match self.find(&path) {
+                Some(current) => path = current.to_string(),
+                None => self.names.push(path.clone()),
+            }
+        }
+
+        self.names.sort_by_key(|name| name.to_lowercase());
+        Ok(path)
+    }
+
+    // Quita la categoria con todas sus subcategorias y devuelve sus nombres tal
+    // como estaban guardados, empezando por `name`
+    pub fn remove(&mut self, name: &str) -> Result<Vec<String>, StorageError> {
+        let current = self
+            .find(name)
+            .ok_or(not_found(name, "CategoryList::remove"))?
+            .to_string();
+        let removed = self.subtree(&current);
+        self.names.retain(|name| !removed.contains(name));
+        Ok(removed)
+    }
+
+    // Cambia el nombre de una categoria, o la mueve a otro lugar del arbol, con
+    // todas sus subcategorias. Devuelve cada nombre anterior con el nuevo,
+    // empezando por `name`. Si el nuevo nombre ya es otra categoria hay que
+    // combinarlas con `merge`.
+    pub fn rename(
+        &mut self,
+        name: &str,
+        new_name: &str,
+    ) -> Result<Vec<(String, String)>, StorageError> {
+        let location = "CategoryList::rename";
+        let current = self.find(name).ok_or(not_found(name, location))?.to_string();
+        let new_name = normalize(new_name, location)?;
+        let subtree = self.subtree(&current);
+
+        // solo cambian las mayusculas ("bus" -> "Bus") o es otra categoria
+        let case_only = new_name.to_lowercase() == current.to_lowercase();
+        if !case_only && is_within(&new_name.to_lowercase(), &current.to_lowercase()) {
+            return Err(StorageError::new(
+                StorageErrorKind::WriteError,
+                &format!("No se puede mover {} dentro de si misma", current),
+                location,
+            ));
+        }
+
+        let targets: Vec<String> = subtree
+            .iter()
+            .map(|old| move_to(old, &current, &new_name))
+            .collect();
+        for target in &targets {
+            if let Some(existing) = self.find(target) {
+                if !subtree.iter().any(|old| old == existing) {
+                    return Err(StorageError::new(
+                        StorageErrorKind::AlreadyExists,
+                        &format!(
+                            "La categoria {} ya existe, use merge para combinar {} con ella",
+                            existing, current
+                        ),
+                        location,
+                    ));
+                }
+            }
+        }
+
+        self.names.retain(|name| !subtree.contains(name));
+        let mut renamed = Vec::with_capacity(subtree.len());
+        for (old, target) in subtree.into_iter().zip(targets) {
+            let new = self.add(&target)?;
+            renamed.push((old, new));
+        }
+        Ok(renamed)
+    }
+
+    // Combina la categoria `name` con `into`: la quita de la lista y sus
+    // subcategorias pasan a estar debajo de `into`, uniendose con las que ya
+    // hay con el mismo nombre. Devuelve cada nombre anterior con su destino,
+    // empezando por `name`.
+    pub fn merge(&mut self, name: &str, into: &str) -> Result<Vec<(String, String)>, StorageError> {
+        let location = "CategoryList::merge";
+        let current = self.find(name).ok_or(not_found(name, location))?.to_string();
+        let into = self.find(into).ok_or(not_found(into, location))?.to_string();
+        if is_within(&into.to_lowercase(), &current.to_lowercase()) {
+            return Err(StorageError::new(
+                StorageErrorKind::AlreadyExists,
+                &format!("No se puede combinar {} con {}, que es parte de ella", current, into),
+                location,
+            ));
+        }
+
+        let subtree = self.remove(&current)?;
+        let mut merged = Vec::with_capacity(subtree.len());
+        for old in subtree {
+            let target = move_to(&old, &current, &into);
+            let new = match self.find(&target) {
+                Some(existing) => existing.to_string(),
+                None => self.add(&target)?,
+            };
+            merged.push((old, new));
+        }
+        Ok(merged)
+    }
+}
+
+// Acceso a `categories.json`, un array con la ruta de cada categoria del arbol
+// ("Transporte", "Transporte > Gasolina"). Cada modificacion toma el lock
+// `categories.lock` desde que lee hasta que escribe.
+#[derive(Debug)]
+pub struct CategoryStore {
+    path: String,
+    lock_path: String,
+    lock_timeout: Option<Duration>,
+}
+
+impl CategoryStore {
+    pub fn new(dir: &str) -> CategoryStore {
+        let dir = Path::new(dir);
+        CategoryStore {
+            path: dir.join(CATEGORIES_FILE).to_string_lossy().to_string(),
+            lock_path: dir.join(CATEGORIES_LOCK_FILE).to_string_lossy().to_string(),
+            lock_timeout: None,
+        }
+    }
+
+    pub fn with_lock_timeout(mut self, timeout: Option<Duration>) -> CategoryStore {
+        self.lock_timeout = timeout;
+        self
+    }
+
+    // Carga la lista. Si el archivo no existe la lista queda vacia.
+    pub fn load(&self) -> Result<CategoryList, StorageError> {
+        let location = "CategoryStore::load";
+        let entries = match open_json(&self.path) {
+            Ok(JsonStructure::Array(entries)) => entries,
+            Err(err) if matches!(err.kind(), JsonFileErrorKind::FileNotFound) => {
+                return Ok(CategoryList::new());
+            }
+            Ok(JsonStructure::Object(_)) => {
+                return Err(StorageError::new(
+                    StorageErrorKind::CorruptedData,
+                    "El archivo de categorias no contiene un array",
+                    location,
+                ));
+            }
+            Err(err) => {
+                return Err(StorageError::new(
+                    StorageErrorKind::ReadError,
+                    &format!("No se pudo leer el archivo de categorias: {}", err),
+                    location,
+                ));
+            }
+        };
+
+        // los nombres repetidos (por ejemplo editados a mano) se ignoran
+        let mut list = CategoryList::new();
+        for entry in entries {
+            let name = entry.as_str().ok_or(StorageError::new(
+                StorageErrorKind::CorruptedData,
+                &format!("La categoria {} no es un texto", entry),
+                location,
+            ))?;
+            if list.find(name).is_none() {
+                list.add(name).map_err(|err| {
+                    StorageError::new(StorageErrorKind::CorruptedData, err.message(), location)
+                })?;
+            }
+        }
+
+        Ok(list)
+    }
+
+    // Lee la lista, aplica `change` y la guarda, todo con el lock tomado
+    pub fn modify<T>(
+        &self,
+        change: impl FnOnce(&mut CategoryList) -> Result<T, StorageError>,
+    ) -> Result<T, StorageError> {
+        let location = "CategoryStore::modify";
+        let _lock = FileLock::acquire(&self.lock_path, self.lock_timeout).map_err(|err| {
+            let kind = match err.kind() {
+                JsonFileErrorKind::Locked => StorageErrorKind::Locked,
+                _ => StorageErrorKind::WriteError,
+            };
+            StorageError::new(kind, err.message(), location)
+        })?;
+
+        let mut list = self.load()?;
+        let result = change(&mut list)?;
+
+        let entries = list.names.iter().map(|name| json!(name)).collect();
+        save_json(&self.path, JsonStructure::Array(entries)).map_err(|err| {
+            StorageError::new(
+                StorageErrorKind::WriteError,
+                &format!("No se pudo escribir el archivo de categorias: {}", err),
+                location,
+            )
+        })?;
+
+        Ok(result)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn list(names: &[&str]) -> CategoryList {
+        let mut list = CategoryList::new();
+        for name in names {
+            list.add(name).unwrap();
+        }
+        list
+    }
+
+    fn pairs(changes: &[(&str, &str)]) -> Vec<(String, String)> {
+        changes
+            .iter()
+            .map(|(old, new)| (old.to_string(), new.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn add_creates_the_missing_parents_and_keeps_their_case() {
+        let list = list(&["Transporte>Gasolina", "transporte > Taxi", "Comida"]);
+        assert_eq!(
+            list.names(),
+            [
+                "Comida",
+                "Transporte",
+                "Transporte > Gasolina",
+                "Transporte > Taxi"
+            ]
+        );
+        assert_eq!(list.find("TRANSPORTE>taxi"), Some("Transporte > Taxi"));
+
+        let mut list = list;
+        let err = list.add("comida").unwrap_err();
+        assert!(matches!(err.kind(), StorageErrorKind::AlreadyExists));
+        let err = list.add("Comida > ").unwrap_err();
+        assert!(matches!(err.kind(), StorageErrorKind::WriteError));
+    }
+
+    #[test]
+    fn subtree_includes_only_the_category_and_its_descendants() {
+        let list = list(&[
+            "Comida > Cafe > Latte",
+            "Comida > Almuerzo",
+            "Comidas",
+            "Bus",
+        ]);
+        assert_eq!(
+            list.subtree("comida"),
+            [
+                "Comida",
+                "Comida > Almuerzo",
+                "Comida > Cafe",
+                "Comida > Cafe > Latte"
+            ]
+        );
+        assert_eq!(
+            list.subtree("Comida > Cafe"),
+            ["Comida > Cafe", "Comida > Cafe > Latte"]
+        );
+        assert_eq!(list.subtree("Comidas"), ["Comidas"]);
+        assert!(list.subtree("Com").is_empty());
+    }
+
+    #[test]
+    fn rename_of_a_parent_moves_its_descendants() {
+        let mut list = list(&["Auto > Gasolina > Premium", "Auto > Peajes", "Casa"]);
+        let renamed = list.rename("auto", "Transporte").unwrap();
+        assert_eq!(
+            renamed,
+            pairs(&[
+                ("Auto", "Transporte"),
+                ("Auto > Gasolina", "Transporte > Gasolina"),
+                (
+                    "Auto > Gasolina > Premium",
+                    "Transporte > Gasolina > Premium"
+                ),
+                ("Auto > Peajes", "Transporte > Peajes"),
+            ])
+        );
+        assert_eq!(
+            list.names(),
+            [
+                "Casa",
+                "Transporte",
+                "Transporte > Gasolina",
+                "Transporte > Gasolina > Premium",
+                "Transporte > Peajes",
+            ]
+        );
+
+        // moverla debajo de otra categoria
+        let renamed = list
+            .rename("Transporte > Gasolina", "Casa > Gasolina")
+            .unwrap();
+        assert_eq!(renamed[1].1, "Casa > Gasolina > Premium");
+        assert!(list.find("Transporte > Gasolina").is_none());
+
+        // solo cambian las mayusculas
+        let renamed = list.rename("casa", "CASA").unwrap();
+        assert_eq!(renamed[0], ("Casa".to_string(), "CASA".to_string()));
+        assert_eq!(list.find("casa > gasolina"), Some("CASA > Gasolina"));
+    }
+
+    #[test]
+    fn rename_rejects_existing_names_and_moving_into_itself() {
+        let mut list = list(&["Auto > Gasolina", "Transporte > Gasolina", "Casa"]);
+        let err = list.rename("Auto", "Transporte").unwrap_err();
+        assert!(matches!(err.kind(), StorageErrorKind::AlreadyExists));
+        let err = list.rename("Auto", "Auto > Viejo").unwrap_err();
+        assert!(matches!(err.kind(), StorageErrorKind::WriteError));
+        let err = list.rename("Moto", "Casa > Moto").unwrap_err();
+        assert!(matches!(err.kind(), StorageErrorKind::NotFound));
+        assert_eq!(list.names().len(), 5);
+    }
+
+    #[test]
+    fn merge_moves_the_subtree_and_joins_the_same_names() {
+        let mut list = list(&[
+            "Auto > Gasolina",
+            "Auto > Peajes > Norte",
+            "Transporte > Gasolina",
+        ]);
+        let merged = list.merge("Auto", "transporte").unwrap();
+        assert_eq!(
+            merged,
+            pairs(&[
+                ("Auto", "Transporte"),
+                ("Auto > Gasolina", "Transporte > Gasolina"),
+                ("Auto > Peajes", "Transporte > Peajes"),
+                ("Auto > Peajes > Norte", "Transporte > Peajes > Norte"),
+            ])
+        );
+        assert_eq!(
+            list.names(),
+            [
+                "Transporte",
+                "Transporte > Gasolina",
+                "Transporte > Peajes",
+                "Transporte > Peajes > Norte",
+            ]
+        );
+
+        let err = list.merge("Transporte", "Transporte > Peajes").unwrap_err();
+        assert!(matches!(err.kind(), StorageErrorKind::AlreadyExists));
+        let err = list.merge("Transporte", "Auto").unwrap_err();
+        assert!(matches!(err.kind(), StorageErrorKind::NotFound));
+    }
+
+    #[test]
+    fn remove_takes_out_the_subtree() {
+        let mut list = list(&["Auto > Gasolina", "Autos", "Casa"]);
+        assert_eq!(list.remove("auto").unwrap(), ["Auto", "Auto > Gasolina"]);
+        assert_eq!(list.names(), ["Autos", "Casa"]);
+    }
+}
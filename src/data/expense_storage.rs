@@ -1,2 +1,135 @@
+use std::{fs, time::Duration};
 
+use serde_json::Value;
 
+use super::{
+    json_storage::JsonExpenseStorage, migrations::MigrationReport, query::ExpenseQuery,
+    sqlite_storage::SqliteExpenseStorage,
+};
+use crate::models::{
+    error::{StorageError, StorageErrorKind},
+    expense::Expense,
+};
+
+// Version del formato en que se guardan los datos. Se escribe en la cabecera
+// de cada base de datos nueva.
+pub const SCHEMA_VERSION: u32 = 7;
+
+// Backends disponibles para guardar los gastos
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StorageKind {
+    Json,
+    Sqlite,
+}
+
+impl StorageKind {
+    pub fn parse(name: &str) -> Result<StorageKind, StorageError> {
+        match name.trim().to_lowercase().as_str() {
+            "json" => Ok(StorageKind::Json),
+            "sqlite" => Ok(StorageKind::Sqlite),
+            _ => Err(StorageError::new(
+                StorageErrorKind::UnknownBackend,
+                &format!(
+                    "Backend de almacenamiento desconocido: {} (use json o sqlite)",
+                    name
+                ),
+                "StorageKind::parse",
+            )),
+        }
+    }
+}
+
+// Crea el backend indicado usando los archivos del directorio `dir`. Si el
+// directorio no existe se crea; los archivos los crea `ExpenseStorage::init`.
+// `lock_timeout` es cuanto se espera si otro proceso esta modificando los datos.
+pub fn open_storage(
+    kind: StorageKind,
+    dir: &str,
+    lock_timeout: Option<Duration>,
+) -> Result<Box<dyn ExpenseStorage>, StorageError> {
+    fs::create_dir_all(dir).map_err(|err| {
+        StorageError::new(
+            StorageErrorKind::WriteError,
+            &format!("No se pudo crear el directorio de datos {}: {}", dir, err),
+            "open_storage",
+        )
+    })?;
+
+    match kind {
+        StorageKind::Json => Ok(Box::new(
+            JsonExpenseStorage::new(dir).with_lock_timeout(lock_timeout),
+        )),
+        StorageKind::Sqlite => Ok(Box::new(
+            SqliteExpenseStorage::new(dir)?.with_lock_timeout(lock_timeout)?,
+        )),
+    }
+}
+
+// Contrato que debe cumplir cualquier backend donde se guarden los gastos.
+// `Expense` solo habla con el almacenamiento a traves de este trait, asi se
+// puede cambiar el backend (JSON, memoria, ...) sin tocar la logica de negocio.
+pub trait ExpenseStorage {
+    // Prepara una base de datos vacia si todavia no existe (archivos, tablas,
+    // cabecera con la version del esquema). Se debe llamar antes que cualquier
+    // otra operacion; devuelve `true` si tuvo que crear la base de datos.
+    fn init(&mut self) -> Result<bool, StorageError>;
+
+    // Version del esquema guardada en la cabecera de la base de datos
+    fn schema_version(&self) -> Result<u32, StorageError>;
+
+    // Lleva una base de datos de una version anterior a `SCHEMA_VERSION`,
+    // guardando antes una copia de seguridad. Se debe llamar despues de `init`.
+    fn migrate(&mut self) -> Result<MigrationReport, StorageError> {
+        Ok(MigrationReport::up_to_date(self.schema_version()?))
+    }
+
+    // Devuelve todos los gastos guardados
+    fn load(&self) -> Result<Vec<Expense>, StorageError>;
+
+    // Devuelve los registros tal como estan guardados, sin validarlos, para
+    // poder revisar y reparar los que no se pueden convertir a `Expense`
+    fn raw_records(&self) -> Result<Vec<Value>, StorageError>;
+
+    // Reserva y devuelve el siguiente id disponible
+    fn next_id(&mut self) -> Result<String, StorageError>;
+
+    // Valor actual del contador de ids, sin reservarlo
+    fn id_counter(&self) -> Result<i64, StorageError>;
+
+    // Reemplaza todos los gastos y el contador de ids (lo usa la reparacion)
+    fn replace_all(&mut self, expenses: &[Expense], next_id: i64) -> Result<(), StorageError>;
+
+    // Guarda un gasto nuevo
+    fn insert(&mut self, expense: &Expense) -> Result<(), StorageError>;
+
+    // Reemplaza el gasto que tenga el mismo id
+    fn update(&mut self, expense: &Expense) -> Result<(), StorageError>;
+
+    // Elimina los gastos con los ids indicados y devuelve cuantos se borraron
+    fn delete(&mut self, ids: &[String]) -> Result<usize, StorageError>;
+
+    // Abre una transaccion: los cambios siguientes no se confirman hasta `commit`.
+    // Si ya hay una abierta, la nueva queda anidada dentro de ella.
+    fn begin(&mut self) -> Result<(), StorageError>;
+
+    // Confirma todos los cambios de la transaccion abierta. Los de una
+    // transaccion anidada pasan a la externa y se guardan cuando esta se confirma.
+    fn commit(&mut self) -> Result<(), StorageError>;
+
+    // Descarta todos los cambios de la transaccion abierta; en una anidada solo
+    // los hechos desde su `begin`
+    fn rollback(&mut self) -> Result<(), StorageError>;
+
+    // Termina o deshace una operacion que quedo a medias (por ejemplo por una
+    // caida del proceso). Devuelve `true` si tuvo que reparar algo.
+    fn recover(&mut self) -> Result<bool, StorageError> {
+        Ok(false)
+    }
+
+    // Devuelve los gastos que cumplen el filtro, en el orden y la pagina que
+    // pide. Por defecto se filtra en memoria; los backends que puedan hacerlo
+    // mejor deben sobreescribirlo.
+    fn query(&self, query: &ExpenseQuery) -> Result<Vec<Expense>, StorageError> {
+        Ok(query.apply(self.load()?))
+    }
+}
@@ -0,0 +1,1020 @@
+use std::{fs, path::Path, time::Duration};
+
+use chrono::{DateTime, Local, Utc};
+use serde_json::{json, Map, Value};
+
+use super::{
+    expense_storage::{ExpenseStorage, SCHEMA_VERSION},
+    migrations::{run_migrations, Migration, MigrationReport},
+};
+use crate::{
+    models::{
+        error::{JsonFileErrorKind, StorageError, StorageErrorKind},
+        expense::Expense,
+        money::{Money, DEFAULT_CURRENCY},
+    },
+    utils::{
+        file_utils::{open_json, save_json, JsonStructure},
+        lock_utils::FileLock,
+    },
+};
+
+// Backend original: un archivo `ids.json` con el contador de ids y un archivo
+// `expenses.json` con los gastos, ambos dentro del mismo directorio. Desde la
+// version 2 del esquema `expenses.json` es un sobre con la version, los
+// metadatos del ledger y el array de gastos:
+//
+//     {"schema_version": 7, "metadata": {...}, "expenses": [...]}
+//
+// Como son dos archivos, los cambios de una transaccion se escriben primero en
+// `journal.json` y luego se aplican a cada archivo. Si el proceso muere a mitad
+// de camino, `recover` vuelve a aplicar el journal al arrancar.
+//
+// Para que varios procesos puedan compartir el mismo directorio, cada
+// transaccion toma el lock `expenses.lock` desde que lee hasta que escribe.
+#[derive(Debug)]
+pub struct JsonExpenseStorage {
+    ids_path: String,
+    expenses_path: String,
+    journal_path: String,
+    lock_path: String,
+    lock_timeout: Option<Duration>,
+    transaction: Option<Transaction>,
+}
+
+// Copia en memoria de los dos archivos mientras dura una transaccion. El lock
+// se suelta al destruir la transaccion (commit o rollback).
+#[derive(Debug)]
+struct Transaction {
+    _lock: FileLock,
+    ids: Map<String, Value>,
+    metadata: Map<String, Value>,
+    expenses: Vec<Value>,
+    ids_changed: bool,
+    expenses_changed: bool,
+    // estado al abrir cada transaccion anidada, para poder deshacer solo esa
+    savepoints: Vec<Savepoint>,
+}
+
+#[derive(Debug)]
+struct Savepoint {
+    ids: Map<String, Value>,
+    metadata: Map<String, Value>,
+    expenses: Vec<Value>,
+    ids_changed: bool,
+    expenses_changed: bool,
+}
+
+impl Transaction {
+    fn savepoint(&self) -> Savepoint {
+        Savepoint {
+            ids: self.ids.clone(),
+            metadata: self.metadata.clone(),
+            expenses: self.expenses.clone(),
+            ids_changed: self.ids_changed,
+            expenses_changed: self.expenses_changed,
+        }
+    }
+
+    fn restore(&mut self, savepoint: Savepoint) {
+        self.ids = savepoint.ids;
+        self.metadata = savepoint.metadata;
+        self.expenses = savepoint.expenses;
+        self.ids_changed = savepoint.ids_changed;
+        self.expenses_changed = savepoint.expenses_changed;
+    }
+}
+
+// Los dos archivos tal como estan en disco, sobre los que trabajan las migraciones
+struct LedgerFiles {
+    ids: Map<String, Value>,
+    ledger: Value,
+}
+
+const MIGRATIONS: &[Migration<LedgerFiles>] = &[
+    Migration {
+        version: 2,
+        description: "expenses.json pasa de un array a un sobre con version y metadatos",
+        apply: wrap_in_envelope,
+    },
+    Migration {
+        version: 3,
+        description: "los montos pasan de numeros a texto decimal exacto con moneda",
+        apply: amounts_to_decimal,
+    },
+    Migration {
+        version: 4,
+        description: "los gastos pueden tener una categoria",
+        apply: add_category,
+    },
+    Migration {
+        version: 5,
+        description: "los gastos pueden tener etiquetas",
+        apply: add_tags,
+    },
+    Migration {
+        version: 6,
+        description: "los gastos guardan la fecha de su ultima modificacion",
+        apply: add_updated_at,
+    },
+    Migration {
+        version: 7,
+        description: "los gastos tienen una fecha del gasto aparte de la de creacion",
+        apply: add_spent_at,
+    },
+];
+
+fn wrap_in_envelope(files: &mut LedgerFiles) -> Result<(), StorageError> {
+    let expenses = match files.ledger.take() {
+        Value::Array(expenses) => expenses,
+        _ => {
+            return Err(StorageError::new(
+                StorageErrorKind::CorruptedData,
+                "Se esperaba un array de gastos",
+                "wrap_in_envelope",
+            ));
+        }
+    };
+
+    files.ledger = Value::Object(envelope(new_metadata(), expenses));
+    files.ledger["schema_version"] = json!(2);
+    // la version ahora vive en el sobre
+    files.ids.remove("schema_version");
+    Ok(())
+}
+
+// `"amount": 2700.0` pasa a `"amount": "2700.00", "currency": "COP"`. Los montos
+// que no se pueden convertir de forma exacta se dejan como estaban; `check`
+// los muestra y `repair` los mueve a la cuarentena.
+fn amounts_to_decimal(files: &mut LedgerFiles) -> Result<(), StorageError> {
+    let expenses = match files.ledger.get_mut("expenses") {
+        Some(Value::Array(expenses)) => expenses,
+        _ => {
+            return Err(StorageError::new(
+                StorageErrorKind::CorruptedData,
+                "El archivo de gastos no tiene un array de gastos",
+                "amounts_to_decimal",
+            ));
+        }
+    };
+
+    for expense in expenses.iter_mut().filter_map(|expense| expense.as_object_mut()) {
+        let money = expense
+            .get("amount")
+            .and_then(|amount| amount.as_f64())
+            .and_then(|amount| Money::from_f64(amount, DEFAULT_CURRENCY).ok());
+        if let Some(money) = money {
+            expense.insert("amount".to_string(), json!(money.amount_string()));
+            expense.insert("currency".to_string(), json!(money.currency()));
+        }
+    }
+
+    files.ledger["schema_version"] = json!(3);
+    Ok(())
+}
+
+// El campo `category` es opcional, los gastos existentes quedan sin categoria
+fn add_category(files: &mut LedgerFiles) -> Result<(), StorageError> {
+    files.ledger["schema_version"] = json!(4);
+    Ok(())
+}
+
+fn add_tags(files: &mut LedgerFiles) -> Result<(), StorageError> {
+    files.ledger["schema_version"] = json!(5);
+    Ok(())
+}
+
+fn add_updated_at(files: &mut LedgerFiles) -> Result<(), StorageError> {
+    files.ledger["schema_version"] = json!(6);
+    Ok(())
+}
+
+// La fecha del gasto de los registros existentes es el dia local en que se
+// crearon. Si `created_at` no se puede leer el registro queda sin `spent_at` y
+// `check` lo muestra.
+fn add_spent_at(files: &mut LedgerFiles) -> Result<(), StorageError> {
+    let expenses = match files.ledger.get_mut("expenses") {
+        Some(Value::Array(expenses)) => expenses,
+        _ => {
+            return Err(StorageError::new(
+                StorageErrorKind::CorruptedData,
+                "El archivo de gastos no tiene un array de gastos",
+                "add_spent_at",
+            ));
+        }
+    };
+
+    for expense in expenses.iter_mut().filter_map(|expense| expense.as_object_mut()) {
+        let spent_at = expense
+            .get("created_at")
+            .and_then(|date| date.as_str())
+            .and_then(|date| date.parse::<DateTime<Utc>>().ok())
+            .map(|date| date.with_timezone(&Local).date_naive());
+        if let Some(spent_at) = spent_at {
+            expense.insert("spent_at".to_string(), json!(spent_at));
+        }
+    }
+
+    files.ledger["schema_version"] = json!(7);
+    Ok(())
+}
+
+fn new_metadata() -> Map<String, Value> {
+    let mut metadata = Map::new();
+    metadata.insert("created_at".to_string(), json!(Utc::now()));
+    metadata
+}
+
+fn envelope(metadata: Map<String, Value>, expenses: Vec<Value>) -> Map<String, Value> {
+    let mut ledger = Map::new();
+    ledger.insert("schema_version".to_string(), json!(SCHEMA_VERSION));
+    ledger.insert("metadata".to_string(), Value::Object(metadata));
+    ledger.insert("expenses".to_string(), Value::Array(expenses));
+    ledger
+}
+
+// Version de un `expenses.json` en crudo: el array sin sobre es la version 1
+fn version_of(ledger: &Value) -> Result<u32, StorageError> {
+    match ledger {
+        Value::Array(_) => Ok(1),
+        _ => ledger
+            .get("schema_version")
+            .and_then(|version| version.as_u64())
+            .map(|version| version as u32)
+            .ok_or(StorageError::new(
+                StorageErrorKind::CorruptedData,
+                "El archivo de gastos no tiene schema_version",
+                "version_of",
+            )),
+    }
+}
+
+fn json_error(
+    kind: StorageErrorKind,
+    message: &str,
+    err: impl std::fmt::Display,
+    location: &str,
+) -> StorageError {
+    StorageError::new(kind, &format!("{} {}", message, err), location)
+}
+
+impl JsonExpenseStorage {
+    pub fn new(dir: &str) -> JsonExpenseStorage {
+        let dir = Path::new(dir);
+        JsonExpenseStorage {
+            ids_path: dir.join("ids.json").to_string_lossy().to_string(),
+            expenses_path: dir.join("expenses.json").to_string_lossy().to_string(),
+            journal_path: dir.join("journal.json").to_string_lossy().to_string(),
+            lock_path: dir.join("expenses.lock").to_string_lossy().to_string(),
+            lock_timeout: None,
+            transaction: None,
+        }
+    }
+
+    // Tiempo maximo que se espera a que otro proceso suelte el lock. Sin
+    // timeout se falla enseguida si la base de datos esta bloqueada.
+    pub fn with_lock_timeout(mut self, timeout: Option<Duration>) -> JsonExpenseStorage {
+        self.lock_timeout = timeout;
+        self
+    }
+
+    fn lock(&self, location: &str) -> Result<FileLock, StorageError> {
+        FileLock::acquire(&self.lock_path, self.lock_timeout).map_err(|err| {
+            let kind = match err.kind() {
+                JsonFileErrorKind::Locked => StorageErrorKind::Locked,
+                _ => StorageErrorKind::WriteError,
+            };
+            StorageError::new(kind, &err.to_string(), location)
+        })
+    }
+
+    fn read_ids(&self, location: &str) -> Result<Map<String, Value>, StorageError> {
+        match open_json(&self.ids_path) {
+            Ok(JsonStructure::Object(map)) => Ok(map),
+            Ok(JsonStructure::Array(_)) => Err(StorageError::new(
+                StorageErrorKind::IdsError,
+                "El archivo de ids debe contener un objeto",
+                location,
+            )),
+            Err(err) => Err(json_error(
+                StorageErrorKind::IdsError,
+                "Error en archivo JSON:",
+                err,
+                location,
+            )),
+        }
+    }
+
+    // Lee `expenses.json` tal cual esta en disco, sin importar su version
+    fn read_raw_ledger(&self, location: &str) -> Result<Value, StorageError> {
+        match open_json(&self.expenses_path) {
+            Ok(JsonStructure::Array(vec)) => Ok(Value::Array(vec)),
+            Ok(JsonStructure::Object(map)) => Ok(Value::Object(map)),
+            Err(err) => Err(json_error(
+                StorageErrorKind::ReadError,
+                "Error leyendo el archivo JSON",
+                err,
+                location,
+            )),
+        }
+    }
+
+    // Lee los metadatos y el array de gastos sin tiparlo, para poder modificarlo
+    // aunque existan registros que no se puedan convertir a `Expense`
+    fn read_ledger(
+        &self,
+        location: &str,
+    ) -> Result<(Map<String, Value>, Vec<Value>), StorageError> {
+        // dentro de una transaccion se leen los cambios aun no confirmados
+        if let Some(transaction) = &self.transaction {
+            return Ok((transaction.metadata.clone(), transaction.expenses.clone()));
+        }
+
+        let ledger = self.read_raw_ledger(location)?;
+        let version = version_of(&ledger)?;
+        if version != SCHEMA_VERSION {
+            return Err(StorageError::new(
+                StorageErrorKind::SchemaVersion,
+                &format!(
+                    "El archivo de gastos usa la version de esquema {} y se esperaba la {}",
+                    version, SCHEMA_VERSION
+                ),
+                location,
+            ));
+        }
+
+        let metadata = match ledger.get("metadata") {
+            Some(Value::Object(metadata)) => metadata.clone(),
+            _ => Map::new(),
+        };
+
+        match ledger.get("expenses") {
+            Some(Value::Array(expenses)) => Ok((metadata, expenses.clone())),
+            _ => Err(StorageError::new(
+                StorageErrorKind::CorruptedData,
+                "El archivo de gastos debe contener un array expenses",
+                location,
+            )),
+        }
+    }
+
+    fn read_expenses(&self, location: &str) -> Result<Vec<Value>, StorageError> {
+        Ok(self.read_ledger(location)?.1)
+    }
+
+    // Guarda el journal y lo aplica: primero se deja constancia de todos los
+    // cambios y despues se escribe cada archivo
+    fn write_through_journal(
+        &self,
+        journal: Map<String, Value>,
+        location: &str,
+    ) -> Result<(), StorageError> {
+        save_json(&self.journal_path, JsonStructure::Object(journal.clone())).map_err(|err| {
+            json_error(
+                StorageErrorKind::WriteError,
+                "Error escribiendo el journal",
+                err,
+                location,
+            )
+        })?;
+
+        self.apply_journal(&journal, location)
+    }
+
+    // Aplica `operation` sobre la transaccion abierta. Si no hay ninguna, la
+    // operacion se ejecuta en su propia transaccion y se confirma al terminar.
+    fn modify<T>(
+        &mut self,
+        operation: impl FnOnce(&mut Transaction) -> Result<T, StorageError>,
+    ) -> Result<T, StorageError> {
+        let autocommit = self.transaction.is_none();
+        if autocommit {
+            self.begin()?;
+        }
+
+        let result = match self.transaction.as_mut() {
+            Some(transaction) => operation(transaction),
+            None => Err(StorageError::new(
+                StorageErrorKind::TransactionError,
+                "No hay una transaccion activa",
+                "JsonExpenseStorage::modify",
+            )),
+        };
+
+        match result {
+            Ok(value) => {
+                if autocommit {
+                    self.commit()?;
+                }
+                Ok(value)
+            }
+            Err(err) => {
+                if autocommit {
+                    self.transaction = None;
+                }
+                Err(err)
+            }
+        }
+    }
+
+    // Escribe en los archivos finales el contenido de un journal
+    fn apply_journal(
+        &self,
+        journal: &Map<String, Value>,
+        location: &str,
+    ) -> Result<(), StorageError> {
+        if let Some(Value::Object(ids)) = journal.get("ids") {
+            save_json(&self.ids_path, JsonStructure::Object(ids.clone())).map_err(|err| {
+                json_error(
+                    StorageErrorKind::WriteError,
+                    "Error escribiendo el archivo de ids",
+                    err,
+                    location,
+                )
+            })?;
+        }
+
+        // los journals anteriores a la version 2 guardan el array sin sobre
+        let expenses = match journal.get("expenses") {
+            Some(Value::Object(ledger)) => Some(JsonStructure::Object(ledger.clone())),
+            Some(Value::Array(expenses)) => Some(JsonStructure::Array(expenses.clone())),
+            _ => None,
+        };
+        if let Some(expenses) = expenses {
+            save_json(&self.expenses_path, expenses).map_err(|err| {
+                json_error(
+                    StorageErrorKind::WriteError,
+                    "Error escribiendo el archivo JSON",
+                    err,
+                    location,
+                )
+            })?;
+        }
+
+        fs::remove_file(&self.journal_path).map_err(|err| {
+            json_error(
+                StorageErrorKind::WriteError,
+                "Error eliminando el journal",
+                err,
+                location,
+            )
+        })
+    }
+}
+
+impl ExpenseStorage for JsonExpenseStorage {
+    // Crea los archivos que falten con una base de datos vacia. Si solo falta el
+    // contador de ids, se calcula a partir de los gastos existentes.
+    fn init(&mut self) -> Result<bool, StorageError> {
+        let location = "JsonExpenseStorage::init";
+        if Path::new(&self.expenses_path).exists() && Path::new(&self.ids_path).exists() {
+            return Ok(false);
+        }
+
+        let _lock = self.lock(location)?;
+        let created = !Path::new(&self.expenses_path).exists();
+
+        if created {
+            let ledger = envelope(new_metadata(), Vec::new());
+            save_json(&self.expenses_path, JsonStructure::Object(ledger)).map_err(|err| {
+                json_error(
+                    StorageErrorKind::WriteError,
+                    "Error creando el archivo de gastos",
+                    err,
+                    location,
+                )
+            })?;
+        }
+
+        if !Path::new(&self.ids_path).exists() {
+            // el archivo de gastos puede estar en una version anterior
+            let expenses = match self.read_raw_ledger(location)? {
+                Value::Array(expenses) => expenses,
+                ledger => match ledger.get("expenses") {
+                    Some(Value::Array(expenses)) => expenses.clone(),
+                    _ => Vec::new(),
+                },
+            };
+            let max_id = expenses
+                .iter()
+                .filter_map(|expense| expense.get("id").and_then(|id| id.as_str()))
+                .filter_map(|id| id.parse::<i64>().ok())
+                .max()
+                .unwrap_or(0);
+
+            let mut ids = Map::new();
+            ids.insert("id".to_string(), json!(max_id + 1));
+            save_json(&self.ids_path, JsonStructure::Object(ids)).map_err(|err| {
+                json_error(
+                    StorageErrorKind::IdsError,
+                    "Error creando el archivo de ids",
+                    err,
+                    location,
+                )
+            })?;
+        }
+
+        Ok(created)
+    }
+
+    fn schema_version(&self) -> Result<u32, StorageError> {
+        version_of(&self.read_raw_ledger("JsonExpenseStorage::schema_version")?)
+    }
+
+    // Migra los dos archivos en crudo. Antes de tocarlos se guarda una copia de
+    // cada uno con la version de origen en el nombre, y los cambios se escriben
+    // a traves del journal para que queden los dos migrados o ninguno.
+    fn migrate(&mut self) -> Result<MigrationReport, StorageError> {
+        let location = "JsonExpenseStorage::migrate";
+        // los archivos se reemplazan de forma atomica, asi que la version se
+        // puede leer sin el lock; solo se toma si hay que migrar
+        let from_version = version_of(&self.read_raw_ledger(location)?)?;
+        if from_version == SCHEMA_VERSION {
+            return Ok(MigrationReport::up_to_date(from_version));
+        }
+
+        let _lock = self.lock(location)?;
+        // otro proceso pudo migrar los archivos mientras se esperaba el lock
+        let mut files = LedgerFiles {
+            ids: self.read_ids(location)?,
+            ledger: self.read_raw_ledger(location)?,
+        };
+        let from_version = version_of(&files.ledger)?;
+        if from_version == SCHEMA_VERSION {
+            return Ok(MigrationReport::up_to_date(from_version));
+        }
+
+        let backup_path = format!("{}.v{}.bak", self.expenses_path, from_version);
+        for (path, backup) in [
+            (&self.expenses_path, backup_path.clone()),
+            (
+                &self.ids_path,
+                format!("{}.v{}.bak", self.ids_path, from_version),
+            ),
+        ] {
+            fs::copy(path, &backup).map_err(|err| {
+                json_error(
+                    StorageErrorKind::WriteError,
+                    "No se pudo crear la copia de seguridad",
+                    err,
+                    location,
+                )
+            })?;
+        }
+
+        let applied = run_migrations(&mut files, from_version, SCHEMA_VERSION, MIGRATIONS)?;
+
+        let mut journal = Map::new();
+        journal.insert("ids".to_string(), Value::Object(files.ids));
+        journal.insert("expenses".to_string(), files.ledger);
+        self.write_through_journal(journal, location)?;
+
+        Ok(MigrationReport {
+            from_version,
+            to_version: SCHEMA_VERSION,
+            applied,
+            backup_path: Some(backup_path),
+        })
+    }
+
+    fn load(&self) -> Result<Vec<Expense>, StorageError> {
+        let records = self.read_expenses("JsonExpenseStorage::load")?;
+
+        // cada registro se convierte por separado para poder informar de todos
+        // los que estan mal y no solo del primero
+        let mut expenses = Vec::with_capacity(records.len());
+        let mut errors = Vec::new();
+        for (index, record) in records.iter().enumerate() {
+            match Expense::from_record(index, record) {
+                Ok(expense) => expenses.push(expense),
+                Err(err) => errors.push(err),
+            }
+        }
+
+        if !errors.is_empty() {
+            return Err(StorageError::new(
+                StorageErrorKind::InvalidRecords,
+                &format!("{} registros de gastos no se pudieron leer", errors.len()),
+                "JsonExpenseStorage::load",
+            )
+            .with_records(errors));
+        }
+
+        Ok(expenses)
+    }
+
+    fn raw_records(&self) -> Result<Vec<Value>, StorageError> {
+        self.read_expenses("JsonExpenseStorage::raw_records")
+    }
+
+    fn id_counter(&self) -> Result<i64, StorageError> {
+        let ids = match &self.transaction {
+            Some(transaction) => transaction.ids.clone(),
+            None => self.read_ids("JsonExpenseStorage::id_counter")?,
+        };
+        ids.get("id").and_then(|id| id.as_i64()).ok_or(StorageError::new(
+            StorageErrorKind::IdsError,
+            "La clave id no fue encontrada en el archivo json",
+            "JsonExpenseStorage::id_counter",
+        ))
+    }
+
+    fn replace_all(&mut self, expenses: &[Expense], next_id: i64) -> Result<(), StorageError> {
+        self.modify(|transaction| {
+            transaction.expenses = expenses.iter().map(|expense| json!(expense)).collect();
+            transaction.ids.insert("id".to_string(), json!(next_id));
+            transaction.expenses_changed = true;
+            transaction.ids_changed = true;
+            Ok(())
+        })
+    }
+
+    fn next_id(&mut self) -> Result<String, StorageError> {
+        self.modify(|transaction| {
+            let id = match transaction.ids.get("id").and_then(|id| id.as_i64()) {
+                Some(id_data) => id_data,
+                None => {
+                    return Err(StorageError::new(
+                        StorageErrorKind::IdsError,
+                        "La clave id no fue encontrada en el archivo json",
+                        "JsonExpenseStorage::next_id",
+                    ));
+                }
+            };
+
+            //se reserva el nuevo id
+            transaction.ids.insert("id".to_string(), json!(id + 1));
+            transaction.ids_changed = true;
+
+            Ok(id.to_string())
+        })
+    }
+
+    fn insert(&mut self, expense: &Expense) -> Result<(), StorageError> {
+        self.modify(|transaction| {
+            transaction.expenses.push(json!(expense));
+            transaction.expenses_changed = true;
+            Ok(())
+        })
+    }
+
+    fn update(&mut self, expense: &Expense) -> Result<(), StorageError> {
+        self.modify(|transaction| {
+            let position = transaction
+                .expenses
+                .iter()
+                .position(|item| item.get("id").and_then(|id| id.as_str()) == Some(&expense.id));
+
+            match position {
+                Some(index) => transaction.expenses[index] = json!(expense),
+                None => {
+                    return Err(StorageError::new(
+                        StorageErrorKind::NotFound,
+                        &format!("No existe un gasto con id {}", expense.id),
+                        "JsonExpenseStorage::update",
+                    ));
+                }
+            }
+
+            transaction.expenses_changed = true;
+            Ok(())
+        })
+    }
+
+    fn delete(&mut self, ids: &[String]) -> Result<usize, StorageError> {
+        self.modify(|transaction| {
+            let before = transaction.expenses.len();
+
+            transaction.expenses.retain(|expense| {
+                match expense.get("id").and_then(|id| id.as_str()) {
+                    Some(id) => !ids.iter().any(|item| item == id),
+                    None => true,
+                }
+            });
+
+            let deleted = before - transaction.expenses.len();
+            transaction.expenses_changed |= deleted > 0;
+            Ok(deleted)
+        })
+    }
+
+    fn begin(&mut self) -> Result<(), StorageError> {
+        // una transaccion anidada trabaja sobre la copia de la externa, que ya
+        // tiene el lock
+        if let Some(transaction) = self.transaction.as_mut() {
+            let savepoint = transaction.savepoint();
+            transaction.savepoints.push(savepoint);
+            return Ok(());
+        }
+
+        // se bloquea antes de leer para que nadie cambie los datos entre la
+        // lectura y la escritura
+        let lock = self.lock("JsonExpenseStorage::begin")?;
+        let ids = self.read_ids("JsonExpenseStorage::begin")?;
+        let (metadata, expenses) = self.read_ledger("JsonExpenseStorage::begin")?;
+        self.transaction = Some(Transaction {
+            _lock: lock,
+            ids,
+            metadata,
+            expenses,
+            ids_changed: false,
+            expenses_changed: false,
+            savepoints: Vec::new(),
+        });
+
+        Ok(())
+    }
+
+    fn commit(&mut self) -> Result<(), StorageError> {
+        let location = "JsonExpenseStorage::commit";
+        // los cambios de una anidada quedan en la externa
+        if let Some(transaction) = self.transaction.as_mut() {
+            if transaction.savepoints.pop().is_some() {
+                return Ok(());
+            }
+        }
+        let transaction = match self.transaction.take() {
+            Some(transaction) => transaction,
+            None => {
+                return Err(StorageError::new(
+                    StorageErrorKind::TransactionError,
+                    "No hay una transaccion activa",
+                    location,
+                ));
+            }
+        };
+
+        if !transaction.ids_changed && !transaction.expenses_changed {
+            return Ok(());
+        }
+
+        let mut journal = Map::new();
+        if transaction.ids_changed {
+            journal.insert("ids".to_string(), Value::Object(transaction.ids));
+        }
+        if transaction.expenses_changed {
+            journal.insert(
+                "expenses".to_string(),
+                Value::Object(envelope(transaction.metadata, transaction.expenses)),
+            );
+        }
+
+        //el lock de la transaccion se suelta al terminar
+        self.write_through_journal(journal, location)
+    }
+
+    fn rollback(&mut self) -> Result<(), StorageError> {
+        if let Some(transaction) = self.transaction.as_mut() {
+            if let Some(savepoint) = transaction.savepoints.pop() {
+                transaction.restore(savepoint);
+                return Ok(());
+            }
+        }
+        match self.transaction.take() {
+            Some(_) => Ok(()),
+            None => Err(StorageError::new(
+                StorageErrorKind::TransactionError,
+                "No hay una transaccion activa",
+                "JsonExpenseStorage::rollback",
+            )),
+        }
+    }
+
+    fn recover(&mut self) -> Result<bool, StorageError> {
+        let location = "JsonExpenseStorage::recover";
+        if !Path::new(&self.journal_path).exists() {
+            return Ok(false);
+        }
+
+        // si el lock lo tiene un proceso vivo, el journal es de la operacion
+        // que esta haciendo y la va a terminar el mismo; un lock abandonado se
+        // toma solo (ver `FileLock`)
+        let _lock = match self.lock(location) {
+            Ok(lock) => lock,
+            Err(err) if matches!(err.kind(), StorageErrorKind::Locked) => return Ok(false),
+            Err(err) => return Err(err),
+        };
+        // otro proceso pudo terminar la operacion mientras se esperaba el lock
+        if !Path::new(&self.journal_path).exists() {
+            return Ok(false);
+        }
+
+        // el journal se guarda de forma atomica, si existe esta completo y
+        // basta con volver a aplicarlo para terminar la operacion
+        let journal = match open_json(&self.journal_path) {
+            Ok(JsonStructure::Object(map)) => map,
+            Ok(JsonStructure::Array(_)) => {
+                return Err(StorageError::new(
+                    StorageErrorKind::CorruptedData,
+                    "El journal debe contener un objeto",
+                    location,
+                ));
+            }
+            Err(err) => {
+                return Err(json_error(
+                    StorageErrorKind::CorruptedData,
+                    "Error leyendo el journal",
+                    err,
+                    location,
+                ));
+            }
+        };
+
+        self.apply_journal(&journal, location)?;
+        Ok(true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::test_utils::TempDir;
+
+    fn write(dir: &TempDir, file: &str, value: Value) {
+        fs::write(dir.join(file), value.to_string()).unwrap();
+    }
+
+    fn read(dir: &TempDir, file: &str) -> Value {
+        serde_json::from_str(&fs::read_to_string(dir.join(file)).unwrap()).unwrap()
+    }
+
+    fn expense(id: &str, description: &str) -> Value {
+        json!({
+            "id": id,
+            "description": description,
+            "amount": "2700.00",
+            "currency": "COP",
+            "spent_at": "2024-03-10",
+            "created_at": "2024-03-10T12:00:00Z",
+        })
+    }
+
+    #[test]
+    fn recover_applies_a_pending_journal() {
+        let dir = TempDir::new("recover");
+        let mut storage = JsonExpenseStorage::new(dir.path());
+        storage.init().unwrap();
+
+        // el proceso murio despues de guardar el journal y antes de aplicarlo
+        let ledger = envelope(new_metadata(), vec![expense("1", "Cafe")]);
+        write(
+            &dir,
+            "journal.json",
+            json!({"ids": {"id": 2}, "expenses": ledger}),
+        );
+
+        assert!(storage.recover().unwrap());
+        assert!(!dir.join("journal.json").exists());
+        let expenses = storage.load().unwrap();
+        assert_eq!(expenses.len(), 1);
+        assert_eq!(expenses[0].description, "Cafe");
+        assert_eq!(storage.id_counter().unwrap(), 2);
+
+        // sin journal no hay nada que recuperar
+        assert!(!storage.recover().unwrap());
+    }
+
+    #[test]
+    fn recover_applies_only_the_files_in_the_journal() {
+        let dir = TempDir::new("recover_ids");
+        let mut storage = JsonExpenseStorage::new(dir.path());
+        storage.init().unwrap();
+        storage
+            .insert(&Expense::from_record(0, &expense("1", "Cafe")).unwrap())
+            .unwrap();
+
+        write(&dir, "journal.json", json!({"ids": {"id": 9}}));
+        assert!(storage.recover().unwrap());
+        assert_eq!(storage.id_counter().unwrap(), 9);
+        assert_eq!(storage.load().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn recover_leaves_the_journal_to_the_lock_holder() {
+        let dir = TempDir::new("recover_locked");
+        let mut storage = JsonExpenseStorage::new(dir.path());
+        storage.init().unwrap();
+        let ledger = envelope(new_metadata(), vec![expense("1", "Cafe")]);
+        write(&dir, "journal.json", json!({"expenses": ledger}));
+
+        // el lock lo tiene un proceso vivo (este)
+        let lock = FileLock::acquire(&storage.lock_path, None).unwrap();
+        assert!(!storage.recover().unwrap());
+        assert!(dir.join("journal.json").exists());
+        assert!(storage.load().unwrap().is_empty());
+
+        drop(lock);
+        assert!(storage.recover().unwrap());
+        assert_eq!(storage.load().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn recover_rejects_a_corrupted_journal() {
+        let dir = TempDir::new("recover_corrupted");
+        let mut storage = JsonExpenseStorage::new(dir.path());
+        storage.init().unwrap();
+        write(&dir, "journal.json", json!([1, 2]));
+
+        let err = storage.recover().unwrap_err();
+        assert!(matches!(err.kind(), StorageErrorKind::CorruptedData));
+    }
+
+    #[test]
+    fn committed_transaction_leaves_no_journal() {
+        let dir = TempDir::new("commit");
+        let mut storage = JsonExpenseStorage::new(dir.path());
+        storage.init().unwrap();
+
+        storage.begin().unwrap();
+        let id = storage.next_id().unwrap();
+        storage
+            .insert(&Expense::from_record(0, &expense(&id, "Cafe")).unwrap())
+            .unwrap();
+        storage.commit().unwrap();
+
+        assert!(!dir.join("journal.json").exists());
+        assert!(!Path::new(&storage.lock_path).exists());
+        assert_eq!(storage.load().unwrap().len(), 1);
+        assert_eq!(storage.id_counter().unwrap(), 2);
+    }
+
+    #[test]
+    fn migrate_from_version_1() {
+        let dir = TempDir::new("migrate");
+        // version 1: array sin sobre y montos como numeros
+        write(
+            &dir,
+            "expenses.json",
+            json!([
+                {
+                    "id": "1",
+                    "description": "Cafe",
+                    "amount": 2700.5,
+                    "created_at": "2024-03-10T12:00:00Z",
+                },
+                {
+                    "id": "2",
+                    "description": "Pan",
+                    "amount": 0.30000000000000004,
+                    "created_at": "2024-03-11T12:00:00Z",
+                },
+            ]),
+        );
+        write(&dir, "ids.json", json!({"id": 3, "schema_version": 1}));
+        let mut storage = JsonExpenseStorage::new(dir.path());
+        assert_eq!(storage.schema_version().unwrap(), 1);
+        assert!(matches!(
+            storage.load().unwrap_err().kind(),
+            StorageErrorKind::SchemaVersion
+        ));
+
+        let report = storage.migrate().unwrap();
+        assert_eq!(report.from_version, 1);
+        assert_eq!(report.to_version, SCHEMA_VERSION);
+        assert_eq!(report.applied.len(), 6);
+        assert!(report.applied[0].starts_with("v1 -> v2"));
+        assert!(Path::new(&report.backup_path.unwrap()).exists());
+        assert!(dir.join("ids.json.v1.bak").exists());
+        assert!(!dir.join("journal.json").exists());
+
+        assert_eq!(storage.schema_version().unwrap(), SCHEMA_VERSION);
+        assert_eq!(read(&dir, "ids.json"), json!({"id": 3}));
+        let records = storage.raw_records().unwrap();
+        assert_eq!(records[0]["amount"], json!("2700.50"));
+        assert_eq!(records[0]["currency"], json!(DEFAULT_CURRENCY));
+        assert!(records[0]["spent_at"].is_string());
+        // el monto que no se puede convertir exacto queda para `check`
+        assert_eq!(records[1]["amount"], json!(0.30000000000000004));
+        assert!(Expense::from_record(1, &records[1]).is_err());
+
+        let cafe = Expense::from_record(0, &records[0]).unwrap();
+        assert_eq!(
+            cafe.amount,
+            Money::parse("2700.5", DEFAULT_CURRENCY).unwrap()
+        );
+        let local = "2024-03-10T12:00:00Z"
+            .parse::<DateTime<Utc>>()
+            .unwrap()
+            .with_timezone(&Local);
+        assert_eq!(cafe.spent_at, local.date_naive());
+
+        // una segunda vez no hay nada que hacer
+        let report = storage.migrate().unwrap();
+        assert!(report.applied.is_empty());
+        assert!(report.backup_path.is_none());
+    }
+
+    #[test]
+    fn migrate_rejects_a_newer_version() {
+        let dir = TempDir::new("migrate_newer");
+        let mut ledger = Value::Object(envelope(new_metadata(), Vec::new()));
+        ledger["schema_version"] = json!(SCHEMA_VERSION + 1);
+        write(&dir, "expenses.json", ledger.clone());
+        write(&dir, "ids.json", json!({"id": 1}));
+
+        let mut storage = JsonExpenseStorage::new(dir.path());
+        let err = storage.migrate().unwrap_err();
+        assert!(matches!(err.kind(), StorageErrorKind::SchemaVersion));
+        assert_eq!(read(&dir, "expenses.json"), ledger);
+    }
+}
@@ -0,0 +1,576 @@
+use std::{collections::HashSet, fmt};
+
+use chrono::{DateTime, SecondsFormat, Utc};
+use serde_json::{json, Value};
+
+use super::{
+    categories::{CategoryList, CategoryStore},
+    expense_storage::ExpenseStorage,
+};
+use crate::{
+    models::{
+        error::{JsonFileErrorKind, StorageError, StorageErrorKind},
+        expense::Expense,
+    },
+    utils::file_utils::{open_json, save_json, JsonStructure},
+};
+
+// Tipos de problema que puede encontrar la revision del ledger
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum IssueKind {
+    // el registro no se puede convertir a un gasto (falta un campo, tipo incorrecto...)
+    InvalidRecord,
+    // la fecha se puede leer pero no esta en formato RFC 3339
+    InvalidDate,
+    // el monto es cero, negativo o no es un numero finito
+    InvalidAmount,
+    // el id no es numerico
+    InvalidId,
+    DuplicateId,
+    // el contador de ids no esta por delante del mayor id usado
+    IdCounterBehind,
+    // la categoria del gasto no esta en el arbol de categorias
+    UnknownCategory,
+}
+
+// Un problema concreto. `fix` describe la correccion que aplica `repair`; si
+// es `None` el registro no se puede corregir y se mueve a la cuarentena.
+#[derive(Debug, Clone)]
+pub struct Issue {
+    pub index: Option<usize>,
+    pub id: Option<String>,
+    pub kind: IssueKind,
+    pub message: String,
+    pub fix: Option<String>,
+}
+
+impl Issue {
+    pub fn is_fixable(&self) -> bool {
+        self.fix.is_some()
+    }
+}
+
+impl fmt::Display for Issue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (self.index, &self.id) {
+            (Some(index), Some(id)) => write!(f, "registro {} (id {}): ", index, id)?,
+            (Some(index), None) => write!(f, "registro {}: ", index)?,
+            _ => {}
+        }
+        write!(f, "{}", self.message)
+    }
+}
+
+// Resultado de `check`
+#[derive(Debug)]
+pub struct CheckReport {
+    pub records: usize,
+    pub issues: Vec<Issue>,
+}
+
+impl CheckReport {
+    pub fn is_ok(&self) -> bool {
+        self.issues.is_empty()
+    }
+}
+
+impl fmt::Display for CheckReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.is_ok() {
+            return write!(f, "Se revisaron {} registros: sin problemas", self.records);
+        }
+
+        write!(
+            f,
+            "Se revisaron {} registros y se encontraron {} problemas:",
+            self.records,
+            self.issues.len()
+        )?;
+        for issue in &self.issues {
+            match &issue.fix {
+                Some(fix) => write!(f, "\n  - {} [reparable: {}]", issue, fix)?,
+                None => write!(f, "\n  - {} [va a la cuarentena]", issue)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+// Resultado de `repair`
+#[derive(Debug)]
+pub struct RepairReport {
+    pub fixed: Vec<Issue>,
+    pub quarantined: Vec<Issue>,
+    pub quarantine_path: String,
+}
+
+impl fmt::Display for RepairReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.fixed.is_empty() && self.quarantined.is_empty() {
+            return write!(f, "No habia nada que reparar");
+        }
+
+        write!(f, "Se corrigieron {} problemas", self.fixed.len())?;
+        for issue in &self.fixed {
+            // `fixed` solo contiene problemas con correccion
+            write!(f, "\n  - {}: {}", issue, issue.fix.as_deref().unwrap_or(""))?;
+        }
+
+        if !self.quarantined.is_empty() {
+            write!(
+                f,
+                "\nSe movieron {} registros a la cuarentena ({}):",
+                self.quarantined.len(),
+                self.quarantine_path
+            )?;
+            for issue in &self.quarantined {
+                write!(f, "\n  - {}", issue)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+// Lo que haria la reparacion: los gastos que quedan (ya corregidos), los
+// registros que van a la cuarentena con su motivo, el nuevo contador de ids y
+// las categorias que faltan en el arbol
+struct Plan {
+    report: CheckReport,
+    expenses: Vec<Expense>,
+    quarantine: Vec<(Value, String)>,
+    next_id: i64,
+    missing_categories: Vec<String>,
+}
+
+fn plan(records: Vec<Value>, counter: i64, categories: &CategoryList) -> Plan {
+    let mut issues = Vec::new();
+    let mut quarantine = Vec::new();
+    let mut candidates: Vec<(usize, Expense)> = Vec::new();
+    let mut missing_categories = CategoryList::new();
+    let total = records.len();
+
+    // el contador tiene que quedar por delante de cualquier id usado, incluso
+    // de los registros que terminan en la cuarentena
+    let max_id = records
+        .iter()
+        .filter_map(|record| record.get("id").and_then(|id| id.as_str()))
+        .filter_map(|id| id.parse::<i64>().ok())
+        .max()
+        .unwrap_or(0);
+
+    for (index, record) in records.into_iter().enumerate() {
+        let expense = match Expense::from_record(index, &record) {
+            Ok(expense) => expense,
+            Err(err) => {
+                let message = format!("campo '{}': {}", err.field, err.reason);
+                issues.push(Issue {
+                    index: Some(index),
+                    id: err.id,
+                    kind: IssueKind::InvalidRecord,
+                    message: message.clone(),
+                    fix: None,
+                });
+                quarantine.push((record, message));
+                continue;
+            }
+        };
+
+        if !expense.amount.is_positive() {
+            let message = format!("el monto {} debe ser mayor a 0", expense.amount);
+            issues.push(Issue {
+                index: Some(index),
+                id: Some(expense.id.clone()),
+                kind: IssueKind::InvalidAmount,
+                message: message.clone(),
+                fix: None,
+            });
+            quarantine.push((record, message));
+            continue;
+        }
+
+        // serde acepta fechas sin ceros a la izquierda ("2024-9-24"), pero el
+        // formato del ledger es RFC 3339 estricto; se reescribe normalizada
+        let raw_date = record
+            .get("created_at")
+            .and_then(|date| date.as_str())
+            .unwrap_or_default();
+        if DateTime::parse_from_rfc3339(raw_date).is_err() {
+            issues.push(Issue {
+                index: Some(index),
+                id: Some(expense.id.clone()),
+                kind: IssueKind::InvalidDate,
+                message: format!("la fecha '{}' no esta en formato RFC 3339", raw_date),
+                fix: Some(format!(
+                    "se guarda como {}",
+                    expense.created_at.to_rfc3339_opts(SecondsFormat::AutoSi, true)
+                )),
+            });
+        }
+
+        // la categoria se agrega al arbol en vez de quitarsela al gasto
+        if let Some(category) = &expense.category {
+            if categories.find(category).is_none() {
+                // un nombre que no se puede agregar (con un nivel vacio) no
+                // tiene arreglo; si ya existe es que otro gasto la agrego
+                let valid = match missing_categories.add(category) {
+                    Ok(_) => true,
+                    Err(err) => matches!(err.kind(), StorageErrorKind::AlreadyExists),
+                };
+                let message = format!("la categoria {} no existe", category);
+                issues.push(Issue {
+                    index: Some(index),
+                    id: Some(expense.id.clone()),
+                    kind: IssueKind::UnknownCategory,
+                    message: message.clone(),
+                    fix: valid.then(|| format!("se agrega {} a las categorias", category)),
+                });
+                if !valid {
+                    quarantine.push((record, message));
+                    continue;
+                }
+            }
+        }
+
+        candidates.push((index, expense));
+    }
+
+    let mut next_id = counter;
+    if counter <= max_id {
+        next_id = max_id + 1;
+        issues.push(Issue {
+            index: None,
+            id: None,
+            kind: IssueKind::IdCounterBehind,
+            message: format!(
+                "el contador de ids vale {} y el mayor id usado es {}",
+                counter, max_id
+            ),
+            fix: Some(format!("el contador pasa a {}", next_id)),
+        });
+    }
+
+    // los ids repetidos o no numericos reciben un id nuevo del contador; el
+    // primer registro con cada id conserva el suyo
+    let mut seen = HashSet::new();
+    let mut expenses = Vec::with_capacity(candidates.len());
+    for (index, mut expense) in candidates {
+        let kind = match expense.id.parse::<i64>() {
+            Ok(id) if seen.insert(id) => None,
+            Ok(_) => Some((IssueKind::DuplicateId, "id repetido")),
+            Err(_) => Some((IssueKind::InvalidId, "el id no es numerico")),
+        };
+
+        if let Some((kind, message)) = kind {
+            issues.push(Issue {
+                index: Some(index),
+                id: Some(expense.id.clone()),
+                kind,
+                message: message.to_string(),
+                fix: Some(format!("se le asigna el id {}", next_id)),
+            });
+            expense.id = next_id.to_string();
+            seen.insert(next_id);
+            next_id += 1;
+        }
+        expenses.push(expense);
+    }
+
+    // los problemas de un mismo registro quedan juntos y el del contador al final
+    issues.sort_by_key(|issue| issue.index.unwrap_or(usize::MAX));
+
+    Plan {
+        report: CheckReport {
+            records: total,
+            issues,
+        },
+        expenses,
+        quarantine,
+        next_id,
+        missing_categories: missing_categories.names().to_vec(),
+    }
+}
+
+// Revisa todos los registros del ledger sin modificar nada
+pub fn check(
+    storage: &dyn ExpenseStorage,
+    categories: &CategoryList,
+) -> Result<CheckReport, StorageError> {
+    let records = storage.raw_records()?;
+    let counter = storage.id_counter()?;
+    Ok(plan(records, counter, categories).report)
+}
+
+// Corrige lo que se puede y mueve a `quarantine_path` los registros que no. Los
+// registros en cuarentena se agregan al archivo junto con el motivo, de modo
+// que nunca se pierde un dato. Las categorias que usan los gastos y no estan en
+// el arbol se agregan. Todo ocurre dentro de una transaccion, con el lock de
+// las categorias tomado.
+pub fn repair(
+    storage: &mut dyn ExpenseStorage,
+    quarantine_path: &str,
+    categories: &CategoryStore,
+) -> Result<RepairReport, StorageError> {
+    categories.modify(|categories| {
+        storage.begin()?;
+        match apply_repair(storage, quarantine_path, categories) {
+            Ok(report) => {
+                storage.commit()?;
+                Ok(report)
+            }
+            Err(err) => {
+                let _ = storage.rollback();
+                Err(err)
+            }
+        }
+    })
+}
+
+fn apply_repair(
+    storage: &mut dyn ExpenseStorage,
+    quarantine_path: &str,
+    categories: &mut CategoryList,
+) -> Result<RepairReport, StorageError> {
+    let records = storage.raw_records()?;
+    let counter = storage.id_counter()?;
+    let plan = plan(records, counter, categories);
+    for category in &plan.missing_categories {
+        if categories.find(category).is_none() {
+            categories.add(category)?;
+        }
+    }
+
+    let (fixed, quarantined): (Vec<Issue>, Vec<Issue>) = plan
+        .report
+        .issues
+        .into_iter()
+        .partition(|issue| issue.is_fixable());
+
+    if !fixed.is_empty() || !quarantined.is_empty() {
+        // la cuarentena se escribe antes que el ledger: si algo falla despues,
+        // el registro queda repetido pero no se pierde
+        if !plan.quarantine.is_empty() {
+            append_quarantine(quarantine_path, plan.quarantine)?;
+        }
+        storage.replace_all(&plan.expenses, plan.next_id)?;
+    }
+
+    Ok(RepairReport {
+        fixed,
+        quarantined,
+        quarantine_path: quarantine_path.to_string(),
+    })
+}
+
+fn append_quarantine(path: &str, records: Vec<(Value, String)>) -> Result<(), StorageError> {
+    let location = "ledger_check::append_quarantine";
+    let mut entries = match open_json(path) {
+        Ok(JsonStructure::Array(entries)) => entries,
+        Err(err) if matches!(err.kind(), JsonFileErrorKind::FileNotFound) => Vec::new(),
+        Ok(JsonStructure::Object(_)) => {
+            return Err(StorageError::new(
+                StorageErrorKind::CorruptedData,
+                "El archivo de cuarentena no contiene un array",
+                location,
+            ));
+        }
+        Err(err) => {
+            return Err(StorageError::new(
+                StorageErrorKind::ReadError,
+                &format!("No se pudo leer el archivo de cuarentena: {}", err),
+                location,
+            ));
+        }
+    };
+
+    let quarantined_at = Utc::now().to_rfc3339();
+    entries.extend(records.into_iter().map(|(record, reason)| {
+        json!({
+            "quarantined_at": quarantined_at,
+            "reason": reason,
+            "record": record,
+        })
+    }));
+
+    save_json(path, JsonStructure::Array(entries)).map_err(|err| {
+        StorageError::new(
+            StorageErrorKind::WriteError,
+            &format!("No se pudo escribir el archivo de cuarentena: {}", err),
+            location,
+        )
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+    use crate::{data::json_storage::JsonExpenseStorage, utils::test_utils::TempDir};
+
+    fn record(id: &str, amount: &str, category: Option<&str>) -> Value {
+        let mut record = json!({
+            "id": id,
+            "description": format!("Gasto {}", id),
+            "amount": amount,
+            "currency": "COP",
+            "spent_at": "2024-03-10",
+            "created_at": "2024-03-10T12:00:00Z",
+        });
+        if let Some(category) = category {
+            record["category"] = json!(category);
+        }
+        record
+    }
+
+    fn write(dir: &TempDir, file: &str, value: Value) {
+        fs::write(dir.join(file), value.to_string()).unwrap();
+    }
+
+    fn read(dir: &TempDir, file: &str) -> Value {
+        serde_json::from_str(&fs::read_to_string(dir.join(file)).unwrap()).unwrap()
+    }
+
+    // Ledger con un registro por cada problema y el contador atrasado
+    fn damaged_ledger(dir: &TempDir) -> Vec<Value> {
+        let mut missing_description = record("4", "10", None);
+        missing_description
+            .as_object_mut()
+            .unwrap()
+            .remove("description");
+        let records = vec![
+            record("1", "10", Some("Comida")),
+            record("1", "20", None),
+            record("abc", "30", None),
+            record("3", "-5", None),
+            missing_description,
+            record("9", "60", Some("Viajes > Playa")),
+            record("7", "70", Some("Viajes >  ")),
+        ];
+        write(
+            dir,
+            "expenses.json",
+            json!({"schema_version": 7, "metadata": {}, "expenses": records}),
+        );
+        write(dir, "ids.json", json!({"id": 3}));
+        write(dir, "categories.json", json!(["Comida"]));
+        records
+    }
+
+    fn kinds(issues: &[Issue]) -> Vec<(Option<usize>, IssueKind)> {
+        issues
+            .iter()
+            .map(|issue| (issue.index, issue.kind))
+            .collect()
+    }
+
+    #[test]
+    fn check_reports_every_problem_without_changing_anything() {
+        let dir = TempDir::new("check");
+        let records = damaged_ledger(&dir);
+        let storage = JsonExpenseStorage::new(dir.path());
+        let categories = CategoryStore::new(dir.path()).load().unwrap();
+
+        let report = check(&storage, &categories).unwrap();
+        assert_eq!(report.records, 7);
+        assert!(!report.is_ok());
+        assert_eq!(
+            kinds(&report.issues),
+            [
+                (Some(1), IssueKind::DuplicateId),
+                (Some(2), IssueKind::InvalidId),
+                (Some(3), IssueKind::InvalidAmount),
+                (Some(4), IssueKind::InvalidRecord),
+                (Some(5), IssueKind::UnknownCategory),
+                (Some(6), IssueKind::UnknownCategory),
+                (None, IssueKind::IdCounterBehind),
+            ]
+        );
+        let fixable: Vec<bool> = report.issues.iter().map(Issue::is_fixable).collect();
+        assert_eq!(fixable, [true, true, false, false, true, false, true]);
+
+        assert_eq!(read(&dir, "expenses.json")["expenses"], json!(records));
+        assert_eq!(read(&dir, "ids.json"), json!({"id": 3}));
+        assert!(!dir.join("quarantine.json").exists());
+    }
+
+    #[test]
+    fn repair_renumbers_fixes_and_quarantines() {
+        let dir = TempDir::new("repair");
+        let records = damaged_ledger(&dir);
+        let previous = json!({"quarantined_at": "ayer", "reason": "antes", "record": {}});
+        write(&dir, "quarantine.json", json!([previous]));
+        let mut storage = JsonExpenseStorage::new(dir.path());
+        let categories = CategoryStore::new(dir.path());
+        let quarantine = dir.join("quarantine.json");
+
+        let report = repair(&mut storage, quarantine.to_str().unwrap(), &categories).unwrap();
+        assert_eq!(report.fixed.len(), 4);
+        assert_eq!(report.quarantined.len(), 3);
+
+        // el contador pasa por delante del 9 y los ids malos toman los siguientes
+        let ids: Vec<(String, String)> = storage
+            .load()
+            .unwrap()
+            .into_iter()
+            .map(|expense| (expense.id, expense.description))
+            .collect();
+        let expected = [
+            ("1", "Gasto 1"),
+            ("10", "Gasto 1"),
+            ("11", "Gasto abc"),
+            ("9", "Gasto 9"),
+        ];
+        let expected: Vec<(String, String)> = expected
+            .iter()
+            .map(|(id, description)| (id.to_string(), description.to_string()))
+            .collect();
+        assert_eq!(ids, expected);
+        assert_eq!(storage.id_counter().unwrap(), 12);
+
+        // la categoria desconocida se agrega al arbol con su padre
+        assert_eq!(
+            categories.load().unwrap().names(),
+            ["Comida", "Viajes", "Viajes > Playa"]
+        );
+
+        // la cuarentena conserva lo que habia y agrega los registros tal cual
+        let entries = read(&dir, "quarantine.json");
+        let entries = entries.as_array().unwrap();
+        assert_eq!(entries.len(), 4);
+        assert_eq!(entries[0], previous);
+        let quarantined: Vec<&Value> = entries[1..].iter().map(|entry| &entry["record"]).collect();
+        assert_eq!(quarantined, [&records[3], &records[4], &records[6]]);
+        assert!(entries[1..].iter().all(|entry| entry["reason"].is_string()));
+
+        let categories = categories.load().unwrap();
+        assert!(check(&storage, &categories).unwrap().is_ok());
+        let report = repair(
+            &mut storage,
+            quarantine.to_str().unwrap(),
+            &CategoryStore::new(dir.path()),
+        )
+        .unwrap();
+        assert!(report.fixed.is_empty() && report.quarantined.is_empty());
+        assert_eq!(read(&dir, "quarantine.json").as_array().unwrap().len(), 4);
+    }
+
+    #[test]
+    fn repair_keeps_the_ledger_when_the_quarantine_is_unreadable() {
+        let dir = TempDir::new("repair_quarantine");
+        let records = damaged_ledger(&dir);
+        write(&dir, "quarantine.json", json!({"no": "es un array"}));
+        let mut storage = JsonExpenseStorage::new(dir.path());
+        let quarantine = dir.join("quarantine.json");
+
+        let err = repair(
+            &mut storage,
+            quarantine.to_str().unwrap(),
+            &CategoryStore::new(dir.path()),
+        )
+        .unwrap_err();
+        assert!(matches!(err.kind(), StorageErrorKind::CorruptedData));
+        assert_eq!(read(&dir, "expenses.json")["expenses"], json!(records));
+        assert_eq!(read(&dir, "ids.json"), json!({"id": 3}));
+        assert_eq!(read(&dir, "categories.json"), json!(["Comida"]));
+    }
+}
@@ -0,0 +1,139 @@
+use serde_json::{json, Value};
+
+use super::expense_storage::{ExpenseStorage, SCHEMA_VERSION};
+use crate::models::{
+    error::{StorageError, StorageErrorKind},
+    expense::Expense,
+};
+
+// Almacenamiento en memoria, pensado para pruebas o usos donde no se quiere
+// tocar el disco. Los datos se pierden al destruir la estructura.
+#[derive(Debug)]
+pub struct MemoryExpenseStorage {
+    expenses: Vec<Expense>,
+    next_id: i64,
+    // estado previo a cada transaccion abierta (la ultima es la mas interna),
+    // se restaura en el rollback
+    snapshots: Vec<(Vec<Expense>, i64)>,
+}
+
+impl MemoryExpenseStorage {
+    pub fn new() -> MemoryExpenseStorage {
+        MemoryExpenseStorage {
+            expenses: Vec::new(),
+            next_id: 1,
+            snapshots: Vec::new(),
+        }
+    }
+
+    pub fn with_expenses(expenses: Vec<Expense>) -> MemoryExpenseStorage {
+        // el contador arranca despues del mayor id numerico existente
+        let max_id = expenses
+            .iter()
+            .filter_map(|expense| expense.id.parse::<i64>().ok())
+            .max()
+            .unwrap_or(0);
+
+        MemoryExpenseStorage {
+            expenses,
+            next_id: max_id + 1,
+            snapshots: Vec::new(),
+        }
+    }
+}
+
+impl Default for MemoryExpenseStorage {
+    fn default() -> Self {
+        MemoryExpenseStorage::new()
+    }
+}
+
+impl ExpenseStorage for MemoryExpenseStorage {
+    fn init(&mut self) -> Result<bool, StorageError> {
+        Ok(false)
+    }
+
+    fn schema_version(&self) -> Result<u32, StorageError> {
+        Ok(SCHEMA_VERSION)
+    }
+
+    fn load(&self) -> Result<Vec<Expense>, StorageError> {
+        Ok(self.expenses.clone())
+    }
+
+    fn raw_records(&self) -> Result<Vec<Value>, StorageError> {
+        Ok(self.expenses.iter().map(|expense| json!(expense)).collect())
+    }
+
+    fn id_counter(&self) -> Result<i64, StorageError> {
+        Ok(self.next_id)
+    }
+
+    fn replace_all(&mut self, expenses: &[Expense], next_id: i64) -> Result<(), StorageError> {
+        self.expenses = expenses.to_vec();
+        self.next_id = next_id;
+        Ok(())
+    }
+
+    fn next_id(&mut self) -> Result<String, StorageError> {
+        let id = self.next_id;
+        self.next_id += 1;
+        Ok(id.to_string())
+    }
+
+    fn insert(&mut self, expense: &Expense) -> Result<(), StorageError> {
+        self.expenses.push(expense.clone());
+        Ok(())
+    }
+
+    fn update(&mut self, expense: &Expense) -> Result<(), StorageError> {
+        match self.expenses.iter_mut().find(|item| item.id == expense.id) {
+            Some(item) => {
+                *item = expense.clone();
+                Ok(())
+            }
+            None => Err(StorageError::new(
+                StorageErrorKind::NotFound,
+                &format!("No existe un gasto con id {}", expense.id),
+                "MemoryExpenseStorage::update",
+            )),
+        }
+    }
+
+    fn delete(&mut self, ids: &[String]) -> Result<usize, StorageError> {
+        let before = self.expenses.len();
+        self.expenses.retain(|expense| !ids.contains(&expense.id));
+        Ok(before - self.expenses.len())
+    }
+
+    fn begin(&mut self) -> Result<(), StorageError> {
+        self.snapshots.push((self.expenses.clone(), self.next_id));
+        Ok(())
+    }
+
+    fn commit(&mut self) -> Result<(), StorageError> {
+        match self.snapshots.pop() {
+            Some(_) => Ok(()),
+            None => Err(StorageError::new(
+                StorageErrorKind::TransactionError,
+                "No hay una transaccion activa",
+                "MemoryExpenseStorage::commit",
+            )),
+        }
+    }
+
+    fn rollback(&mut self) -> Result<(), StorageError> {
+        match self.snapshots.pop() {
+            Some((expenses, next_id)) => {
+                self.expenses = expenses;
+                self.next_id = next_id;
+                Ok(())
+            }
+            None => Err(StorageError::new(
+                StorageErrorKind::TransactionError,
+                "No hay una transaccion activa",
+                "MemoryExpenseStorage::rollback",
+            )),
+        }
+    }
+}
@@ -0,0 +1,84 @@
+use crate::models::error::{StorageError, StorageErrorKind};
+
+// Un paso de migracion: lleva los datos `T` de un backend desde la version
+// `version - 1` hasta `version`. Cada backend define su propia lista.
+pub struct Migration<T> {
+    pub version: u32,
+    pub description: &'static str,
+    pub apply: fn(&mut T) -> Result<(), StorageError>,
+}
+
+// Resultado de poner una base de datos al dia
+#[derive(Debug)]
+pub struct MigrationReport {
+    pub from_version: u32,
+    pub to_version: u32,
+    // descripcion de cada migracion aplicada, en orden
+    pub applied: Vec<String>,
+    // copia de los datos tal como estaban antes de migrar
+    pub backup_path: Option<String>,
+}
+
+impl MigrationReport {
+    pub fn up_to_date(version: u32) -> MigrationReport {
+        MigrationReport {
+            from_version: version,
+            to_version: version,
+            applied: Vec::new(),
+            backup_path: None,
+        }
+    }
+}
+
+// Aplica en orden las migraciones posteriores a `from` hasta `to`. Devuelve la
+// descripcion de cada paso aplicado; si uno falla se detiene y devuelve el error.
+pub fn run_migrations<T>(
+    data: &mut T,
+    from: u32,
+    to: u32,
+    migrations: &[Migration<T>],
+) -> Result<Vec<String>, StorageError> {
+    if from > to {
+        return Err(StorageError::new(
+            StorageErrorKind::SchemaVersion,
+            &format!(
+                "La base de datos usa la version de esquema {} y esta version del programa solo soporta hasta la {}",
+                from, to
+            ),
+            "run_migrations",
+        ));
+    }
+
+    let mut applied = Vec::new();
+    for version in (from + 1)..=to {
+        let migration = match migrations
+            .iter()
+            .find(|migration| migration.version == version)
+        {
+            Some(migration) => migration,
+            None => {
+                return Err(StorageError::new(
+                    StorageErrorKind::SchemaVersion,
+                    &format!("No existe una migracion a la version {}", version),
+                    "run_migrations",
+                ));
+            }
+        };
+
+        (migration.apply)(data).map_err(|err| {
+            StorageError::new(
+                StorageErrorKind::SchemaVersion,
+                &format!("Fallo la migracion a la version {}: {}", version, err),
+                "run_migrations",
+            )
+        })?;
+        applied.push(format!(
+            "v{} -> v{}: {}",
+            version - 1,
+            version,
+            migration.description
+        ));
+    }
+
+    Ok(applied)
+}
@@ -1 +1,9 @@
-pub mod expense_storage;
\ No newline at end of file
+pub mod categories;
+pub mod expense_storage;
+pub mod json_storage;
+pub mod ledger_check;
+pub mod memory_storage;
+pub mod migrations;
+pub mod query;
+pub mod rates;
+pub mod sqlite_storage;
@@ -0,0 +1,482 @@
+use std::cmp::Ordering;
+
+use chrono::{Datelike, NaiveDate};
+use regex::Regex;
+
+use super::categories::CATEGORY_SEPARATOR;
+use crate::models::{expense::Expense, money::Amount};
+
+// Filtro que se le pasa al backend de almacenamiento para obtener un subconjunto
+// de los gastos. Un campo en `None` significa que no se filtra por ese criterio.
+// Ademas del filtro lleva el orden y la pagina de resultados que se quiere.
+#[derive(Debug, Default, Clone)]
+pub struct ExpenseQuery {
+    pub ids: Option<Vec<String>>,
+    pub month: Option<u32>,
+    // etiquetas normalizadas que tiene que tener el gasto, todas
+    pub tags: Vec<String>,
+    // dias del gasto, los dos incluidos
+    pub from: Option<NaiveDate>,
+    pub to: Option<NaiveDate>,
+    // montos en la moneda de cada gasto, los dos incluidos
+    pub min_amount: Option<Amount>,
+    pub max_amount: Option<Amount>,
+    // texto que tiene que aparecer en la descripcion, en minusculas
+    pub text: Option<String>,
+    // expresion regular que tiene que encontrarse en la descripcion
+    pub pattern: Option<Regex>,
+    // categoria del gasto; incluye sus subcategorias
+    pub category: Option<String>,
+    pub currency: Option<String>,
+    pub sort: SortKey,
+    pub descending: bool,
+    pub limit: Option<usize>,
+    pub offset: usize,
+}
+
+// Campo por el que se ordenan los resultados
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub enum SortKey {
+    #[default]
+    Id,
+    // dia del gasto
+    Date,
+    // valor del monto, sin convertir entre monedas
+    Amount,
+    Description,
+    Category,
+}
+
+impl SortKey {
+    pub const NAMES: &'static str = "id, date, amount, description, category";
+
+    pub fn parse(name: &str) -> Option<SortKey> {
+        match name.to_lowercase().as_str() {
+            "id" => Some(SortKey::Id),
+            "date" => Some(SortKey::Date),
+            "amount" => Some(SortKey::Amount),
+            "description" => Some(SortKey::Description),
+            "category" => Some(SortKey::Category),
+            _ => None,
+        }
+    }
+
+    fn compare(&self, a: &Expense, b: &Expense) -> Ordering {
+        match self {
+            SortKey::Id => id_order(a).cmp(&id_order(b)),
+            SortKey::Date => a.spent_at.cmp(&b.spent_at),
+            SortKey::Amount => a.amount.value().cmp(&b.amount.value()),
+            SortKey::Description => a.description.to_lowercase().cmp(&b.description.to_lowercase()),
+            SortKey::Category => lowercase(&a.category).cmp(&lowercase(&b.category)),
+        }
+    }
+}
+
+// Los ids son numeros guardados como texto: "10" va despues de "9"
+fn id_order(expense: &Expense) -> (i64, &str) {
+    (expense.id.parse().unwrap_or(i64::MAX), &expense.id)
+}
+
+fn lowercase(category: &Option<String>) -> Option<String> {
+    category.as_ref().map(|category| category.to_lowercase())
+}
+
+impl ExpenseQuery {
+    pub fn new() -> ExpenseQuery {
+        ExpenseQuery::default()
+    }
+
+    pub fn with_ids(mut self, ids: Vec<String>) -> ExpenseQuery {
+        self.ids = Some(ids);
+        self
+    }
+
+    pub fn with_month(mut self, month: u32) -> ExpenseQuery {
+        self.month = Some(month);
+        self
+    }
+
+    pub fn with_tag(mut self, tag: &str) -> ExpenseQuery {
+        self.tags.push(tag.to_string());
+        self
+    }
+
+    pub fn with_from(mut self, from: NaiveDate) -> ExpenseQuery {
+        self.from = Some(from);
+        self
+    }
+
+    pub fn with_to(mut self, to: NaiveDate) -> ExpenseQuery {
+        self.to = Some(to);
+        self
+    }
+
+    pub fn with_min_amount(mut self, amount: Amount) -> ExpenseQuery {
+        self.min_amount = Some(amount);
+        self
+    }
+
+    pub fn with_max_amount(mut self, amount: Amount) -> ExpenseQuery {
+        self.max_amount = Some(amount);
+        self
+    }
+
+    pub fn with_text(mut self, text: &str) -> ExpenseQuery {
+        self.text = Some(text.to_lowercase());
+        self
+    }
+
+    pub fn with_pattern(mut self, pattern: Regex) -> ExpenseQuery {
+        self.pattern = Some(pattern);
+        self
+    }
+
+    pub fn with_category(mut self, category: &str) -> ExpenseQuery {
+        self.category = Some(category.to_string());
+        self
+    }
+
+    pub fn with_currency(mut self, currency: &str) -> ExpenseQuery {
+        self.currency = Some(currency.to_string());
+        self
+    }
+
+    pub fn sorted_by(mut self, sort: SortKey, descending: bool) -> ExpenseQuery {
+        self.sort = sort;
+        self.descending = descending;
+        self
+    }
+
+    pub fn with_limit(mut self, limit: usize) -> ExpenseQuery {
+        self.limit = Some(limit);
+        self
+    }
+
+    pub fn with_offset(mut self, offset: usize) -> ExpenseQuery {
+        self.offset = offset;
+        self
+    }
+
+    // `true` si la consulta devuelve todos los gastos: no tiene ningun filtro
+    // ni limite
+    pub fn selects_all(&self) -> bool {
+        self.ids.is_none()
+            && self.month.is_none()
+            && self.tags.is_empty()
+            && self.from.is_none()
+            && self.to.is_none()
+            && self.min_amount.is_none()
+            && self.max_amount.is_none()
+            && self.text.is_none()
+            && self.pattern.is_none()
+            && self.category.is_none()
+            && self.currency.is_none()
+            && self.limit.is_none()
+            && self.offset == 0
+    }
+
+    // Evalua el filtro sobre un gasto ya cargado en memoria
+    pub fn matches(&self, expense: &Expense) -> bool {
+        if let Some(ids) = &self.ids {
+            if !ids.contains(&expense.id) {
+                return false;
+            }
+        }
+
+        if let Some(month) = self.month {
+            if expense.spent_at.month() != month {
+                return false;
+            }
+        }
+
+        if !self.tags.iter().all(|tag| expense.tags.contains(tag)) {
+            return false;
+        }
+
+        if self.from.is_some_and(|from| expense.spent_at < from)
+            || self.to.is_some_and(|to| expense.spent_at > to)
+        {
+            return false;
+        }
+
+        let amount = expense.amount.value();
+        if self.min_amount.is_some_and(|min| amount < min)
+            || self.max_amount.is_some_and(|max| amount > max)
+        {
+            return false;
+        }
+
+        if let Some(text) = &self.text {
+            if !expense.description.to_lowercase().contains(text) {
+                return false;
+            }
+        }
+
+        if let Some(pattern) = &self.pattern {
+            if !pattern.is_match(&expense.description) {
+                return false;
+            }
+        }
+
+        if let Some(category) = &self.category {
+            let category = category.to_lowercase();
+            let subcategories = format!("{}{}", category, CATEGORY_SEPARATOR);
+            match lowercase(&expense.category) {
+                Some(name) if name == category || name.starts_with(&subcategories) => {}
+                _ => return false,
+            }
+        }
+
+        if let Some(currency) = &self.currency {
+            if expense.amount.currency() != currency {
+                return false;
+            }
+        }
+
+        true
+    }
+
+    // Filtra, ordena y pagina gastos ya cargados en memoria. Los empates en el
+    // campo de orden se ordenan por id, en el mismo sentido.
+    pub fn apply(&self, mut expenses: Vec<Expense>) -> Vec<Expense> {
+        expenses.retain(|expense| self.matches(expense));
+        expenses.sort_by(|a, b| SortKey::Id.compare(a, b));
+        expenses.sort_by(|a, b| self.sort.compare(a, b));
+        if self.descending {
+            expenses.reverse();
+        }
+
+        expenses
+            .into_iter()
+            .skip(self.offset)
+            .take(self.limit.unwrap_or(usize::MAX))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::money::Money;
+    use chrono::Utc;
+
+    fn expense(id: i64, description: &str, amount: &str, currency: &str, day: &str) -> Expense {
+        Expense {
+            id: id.to_string(),
+            description: description.to_string(),
+            amount: Money::parse(amount, currency).unwrap(),
+            category: None,
+            tags: Vec::new(),
+            spent_at: day.parse::<NaiveDate>().unwrap(),
+            created_at: Utc::now(),
+            updated_at: None,
+        }
+    }
+
+    fn sample() -> Vec<Expense> {
+        let mut expenses = vec![
+            expense(1, "Cafe", "2700", "COP", "2024-03-05"),
+            expense(2, "Taxi al aeropuerto", "12.5", "USD", "2024-03-31"),
+            expense(3, "almuerzo", "30000", "COP", "2024-04-01"),
+            expense(4, "Cafe con leche", "3000", "COP", "2024-02-28"),
+            expense(5, "Gasolina", "50", "USD", "2024-03-15"),
+            expense(10, "Bus", "3000", "COP", "2024-01-10"),
+        ];
+        expenses[0].category = Some("Comida > Cafe".to_string());
+        expenses[1].category = Some("Transporte".to_string());
+        expenses[2].category = Some("comida".to_string());
+        expenses[3].category = Some("Comidas".to_string());
+        expenses[4].category = Some("Transporte > Gasolina".to_string());
+        expenses[0].tags = vec!["oficina".to_string()];
+        expenses[2].tags = vec!["oficina".to_string(), "viaje".to_string()];
+        expenses[4].tags = vec!["viaje".to_string()];
+        expenses
+    }
+
+    fn ids(query: &ExpenseQuery) -> Vec<String> {
+        query
+            .apply(sample())
+            .into_iter()
+            .map(|expense| expense.id)
+            .collect()
+    }
+
+    fn amount(text: &str) -> Amount {
+        Amount::parse(text).unwrap()
+    }
+
+    fn day(text: &str) -> NaiveDate {
+        text.parse().unwrap()
+    }
+
+    #[test]
+    fn filters_by_amount_range_including_the_limits() {
+        let query = ExpenseQuery::new()
+            .with_min_amount(amount("50"))
+            .with_max_amount(amount("3000"));
+        assert_eq!(ids(&query), vec!["1", "4", "5", "10"]);
+        assert_eq!(
+            ids(&ExpenseQuery::new().with_max_amount(amount("12.5"))),
+            vec!["2"]
+        );
+        assert_eq!(
+            ids(&ExpenseQuery::new().with_min_amount(amount("3000.01"))),
+            vec!["3"]
+        );
+    }
+
+    #[test]
+    fn filters_by_substring_ignoring_case() {
+        assert_eq!(ids(&ExpenseQuery::new().with_text("CAFE")), vec!["1", "4"]);
+        assert_eq!(ids(&ExpenseQuery::new().with_text("con le")), vec!["4"]);
+        assert!(ids(&ExpenseQuery::new().with_text("cafes")).is_empty());
+    }
+
+    #[test]
+    fn filters_by_regex_on_the_original_description() {
+        let query = ExpenseQuery::new().with_pattern(Regex::new("^[A-Z][a-z]+$").unwrap());
+        assert_eq!(ids(&query), vec!["1", "5", "10"]);
+        let query = ExpenseQuery::new().with_pattern(Regex::new("(?i)^CAFE").unwrap());
+        assert_eq!(ids(&query), vec!["1", "4"]);
+    }
+
+    #[test]
+    fn filters_by_category_and_its_subcategories() {
+        assert_eq!(
+            ids(&ExpenseQuery::new().with_category("Comida")),
+            vec!["1", "3"]
+        );
+        assert_eq!(
+            ids(&ExpenseQuery::new().with_category("transporte")),
+            vec!["2", "5"]
+        );
+        assert_eq!(
+            ids(&ExpenseQuery::new().with_category("Comida > Cafe")),
+            vec!["1"]
+        );
+        assert!(ids(&ExpenseQuery::new().with_category("Comida > Ca")).is_empty());
+    }
+
+    #[test]
+    fn filters_by_all_the_tags() {
+        assert_eq!(
+            ids(&ExpenseQuery::new().with_tag("oficina")),
+            vec!["1", "3"]
+        );
+        assert_eq!(ids(&ExpenseQuery::new().with_tag("viaje")), vec!["3", "5"]);
+        let query = ExpenseQuery::new().with_tag("oficina").with_tag("viaje");
+        assert_eq!(ids(&query), vec!["3"]);
+    }
+
+    #[test]
+    fn filters_by_currency() {
+        assert_eq!(
+            ids(&ExpenseQuery::new().with_currency("USD")),
+            vec!["2", "5"]
+        );
+        assert!(ids(&ExpenseQuery::new().with_currency("EUR")).is_empty());
+    }
+
+    #[test]
+    fn filters_by_dates_and_month() {
+        let query = ExpenseQuery::new()
+            .with_from(day("2024-02-28"))
+            .with_to(day("2024-03-31"));
+        assert_eq!(ids(&query), vec!["1", "2", "4", "5"]);
+        assert_eq!(ids(&ExpenseQuery::new().with_month(3)), vec!["1", "2", "5"]);
+    }
+
+    #[test]
+    fn sorts_by_each_key_in_both_directions() {
+        let sorted =
+            |key: SortKey, descending: bool| ids(&ExpenseQuery::new().sorted_by(key, descending));
+
+        assert_eq!(
+            sorted(SortKey::Id, false),
+            vec!["1", "2", "3", "4", "5", "10"]
+        );
+        assert_eq!(
+            sorted(SortKey::Id, true),
+            vec!["10", "5", "4", "3", "2", "1"]
+        );
+        assert_eq!(
+            sorted(SortKey::Date, false),
+            vec!["10", "4", "1", "5", "2", "3"]
+        );
+        assert_eq!(
+            sorted(SortKey::Date, true),
+            vec!["3", "2", "5", "1", "4", "10"]
+        );
+        // sin convertir entre monedas; los empates van por id
+        assert_eq!(
+            sorted(SortKey::Amount, false),
+            vec!["2", "5", "1", "4", "10", "3"]
+        );
+        assert_eq!(
+            sorted(SortKey::Amount, true),
+            vec!["3", "10", "4", "1", "5", "2"]
+        );
+        assert_eq!(
+            sorted(SortKey::Description, false),
+            vec!["3", "10", "1", "4", "5", "2"]
+        );
+        assert_eq!(
+            sorted(SortKey::Description, true),
+            vec!["2", "5", "4", "1", "10", "3"]
+        );
+        // los gastos sin categoria van primero
+        assert_eq!(
+            sorted(SortKey::Category, false),
+            vec!["10", "3", "1", "4", "2", "5"]
+        );
+        assert_eq!(
+            sorted(SortKey::Category, true),
+            vec!["5", "2", "4", "1", "3", "10"]
+        );
+    }
+
+    #[test]
+    fn pages_after_filtering_and_sorting() {
+        let query = ExpenseQuery::new().sorted_by(SortKey::Date, false);
+        assert_eq!(ids(&query.clone().with_limit(2)), vec!["10", "4"]);
+        assert_eq!(
+            ids(&query.clone().with_offset(2).with_limit(3)),
+            vec!["1", "5", "2"]
+        );
+        assert_eq!(ids(&query.clone().with_offset(5).with_limit(3)), vec!["3"]);
+        assert!(ids(&query.clone().with_offset(6)).is_empty());
+        assert!(ids(&query.with_limit(0)).is_empty());
+
+        let query = ExpenseQuery::new()
+            .with_currency("COP")
+            .sorted_by(SortKey::Amount, true);
+        assert_eq!(ids(&query.with_offset(1).with_limit(2)), vec!["10", "4"]);
+    }
+
+    #[test]
+    fn selects_all_only_without_filters_or_paging() {
+        assert!(ExpenseQuery::new().selects_all());
+        assert!(ExpenseQuery::new()
+            .sorted_by(SortKey::Amount, true)
+            .selects_all());
+
+        let queries = [
+            ExpenseQuery::new().with_ids(vec!["1".to_string()]),
+            ExpenseQuery::new().with_month(3),
+            ExpenseQuery::new().with_tag("viaje"),
+            ExpenseQuery::new().with_from(day("2024-01-01")),
+            ExpenseQuery::new().with_to(day("2024-01-01")),
+            ExpenseQuery::new().with_min_amount(amount("0")),
+            ExpenseQuery::new().with_max_amount(amount("0")),
+            ExpenseQuery::new().with_text(""),
+            ExpenseQuery::new().with_pattern(Regex::new("").unwrap()),
+            ExpenseQuery::new().with_category("Comida"),
+            ExpenseQuery::new().with_currency("COP"),
+            ExpenseQuery::new().with_limit(10),
+            ExpenseQuery::new().with_offset(1),
+        ];
+        for query in queries {
+            assert!(!query.selects_all(), "{:?}", query);
+        }
+    }
+}
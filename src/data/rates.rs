@@ -0,0 +1,494 @@
+use std::{fmt, fs, path::Path, time::Duration};
+
+use chrono::NaiveDate;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+use crate::{
+    models::{
+        error::{JsonFileErrorKind, MoneyError, RecordError, StorageError, StorageErrorKind},
+        money::{parse_currency, Money, Rate},
+    },
+    utils::{
+        file_utils::{open_json, save_json, JsonStructure},
+        lock_utils::FileLock,
+    },
+};
+
+// Archivos de las tasas de cambio, dentro del directorio de datos
+pub const RATES_FILE: &str = "rates.json";
+const RATES_LOCK_FILE: &str = "rates.lock";
+
+// Formato de las fechas de las tasas, en `rates.json` y en el CSV
+pub const RATE_DATE_FORMAT: &str = "%Y-%m-%d";
+
+// Tasa de cambio de un dia: una unidad de `from` vale `rate` unidades de `to`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ExchangeRate {
+    pub date: NaiveDate,
+    pub from: String,
+    pub to: String,
+    pub rate: Rate,
+}
+
+impl ExchangeRate {
+    // Crea la tasa normalizando los codigos de moneda ("usd" -> "USD")
+    pub fn new(date: NaiveDate, from: &str, to: &str, rate: Rate) -> Result<ExchangeRate, MoneyError> {
+        Ok(ExchangeRate {
+            date,
+            from: parse_currency(from)?,
+            to: parse_currency(to)?,
+            rate,
+        })
+    }
+
+    fn same_key(&self, other: &ExchangeRate) -> bool {
+        self.date == other.date && self.from == other.from && self.to == other.to
+    }
+}
+
+impl fmt::Display for ExchangeRate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "1 {} = {} {} ({})",
+            self.from,
+            self.rate,
+            self.to,
+            self.date.format(RATE_DATE_FORMAT)
+        )
+    }
+}
+
+// Tabla de tasas de cambio por fecha. Hay como mucho una tasa por dia para cada
+// par de monedas y se mantiene ordenada por par y fecha.
+#[derive(Debug, Default, Clone)]
+pub struct RateTable {
+    rates: Vec<ExchangeRate>,
+}
+
+// Tasa encontrada para convertir un par de monedas: la del par pedido o la del
+// par contrario
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RateMatch<'a> {
+    Direct(&'a ExchangeRate),
+    Inverse(&'a ExchangeRate),
+}
+
+// Resultado de guardar varias tasas
+#[derive(Debug, Default)]
+pub struct RateImport {
+    pub added: usize,
+    pub updated: usize,
+}
+
+// Convierte una entrada de `rates.json`, normalizando los codigos de moneda
+fn read_rate(index: usize, entry: &Value) -> Result<ExchangeRate, RecordError> {
+    let record_error = |field: &str, reason: String| RecordError {
+        index,
+        id: None,
+        field: field.to_string(),
+        reason,
+    };
+
+    let rate: ExchangeRate = serde_path_to_error::deserialize(entry)
+        .map_err(|err| record_error(&err.path().to_string(), err.inner().to_string()))?;
+    let from = parse_currency(&rate.from).map_err(|err| record_error("from", err.to_string()))?;
+    let to = parse_currency(&rate.to).map_err(|err| record_error("to", err.to_string()))?;
+    Ok(ExchangeRate { from, to, ..rate })
+}
+
+// Convierte una linea `fecha,desde,hacia,tasa` del CSV
+fn read_csv_line(line_number: usize, line: &str) -> Result<ExchangeRate, RecordError> {
+    let record_error = |field: &str, reason: String| RecordError {
+        index: line_number,
+        id: None,
+        field: field.to_string(),
+        reason,
+    };
+
+    let fields: Vec<&str> = line.split(',').map(|field| field.trim()).collect();
+    let [date, from, to, rate] = fields[..] else {
+        return Err(record_error(
+            "(linea)",
+            format!(
+                "se esperaban 4 columnas (date,from,to,rate) y hay {}",
+                fields.len()
+            ),
+        ));
+    };
+
+    let date = NaiveDate::parse_from_str(date, RATE_DATE_FORMAT)
+        .map_err(|err| record_error("date", format!("{}: {}", date, err)))?;
+    let from = parse_currency(from).map_err(|err| record_error("from", err.to_string()))?;
+    let to = parse_currency(to).map_err(|err| record_error("to", err.to_string()))?;
+    let rate = Rate::parse(rate).map_err(|err| record_error("rate", err.to_string()))?;
+
+    Ok(ExchangeRate {
+        date,
+        from,
+        to,
+        rate,
+    })
+}
+
+impl RateTable {
+    pub fn new() -> RateTable {
+        RateTable::default()
+    }
+
+    pub fn with_rates(rates: Vec<ExchangeRate>) -> RateTable {
+        let mut table = RateTable::new();
+        for rate in rates {
+            table.set(rate);
+        }
+        table
+    }
+
+    pub fn rates(&self) -> &[ExchangeRate] {
+        &self.rates
+    }
+
+    // Agrega la tasa o reemplaza la del mismo dia y par de monedas. Devuelve la
+    // tasa reemplazada, si habia una.
+    pub fn set(&mut self, rate: ExchangeRate) -> Option<ExchangeRate> {
+        if let Some(current) = self.rates.iter_mut().find(|current| current.same_key(&rate)) {
+            return Some(std::mem::replace(current, rate));
+        }
+
+        let position = self.rates.partition_point(|current| {
+            (&current.from, &current.to, current.date) < (&rate.from, &rate.to, rate.date)
+        });
+        self.rates.insert(position, rate);
+        None
+    }
+
+    // Guarda todas las tasas indicadas y cuenta cuantas son nuevas
+    pub fn set_all(&mut self, rates: Vec<ExchangeRate>) -> RateImport {
+        let mut import = RateImport::default();
+        for rate in rates {
+            match self.set(rate) {
+                Some(_) => import.updated += 1,
+                None => import.added += 1,
+            }
+        }
+        import
+    }
+
+    // Tasa para convertir de `from` a `to` el dia `date`: la de ese dia o, si no
+    // hay, la mas reciente anterior. Si no hay ninguna de `from` a `to` se usa
+    // la de `to` a `from`, con la misma regla, dividiendo por ella.
+    pub fn find(&self, from: &str, to: &str, date: NaiveDate) -> Option<RateMatch<'_>> {
+        self.latest(from, to, date)
+            .map(RateMatch::Direct)
+            .or_else(|| self.latest(to, from, date).map(RateMatch::Inverse))
+    }
+
+    fn latest(&self, from: &str, to: &str, date: NaiveDate) -> Option<&ExchangeRate> {
+        self.rates
+            .iter()
+            .filter(|rate| rate.from == from && rate.to == to && rate.date <= date)
+            .max_by_key(|rate| rate.date)
+    }
+
+    // Convierte `amount` a `currency` con la tasa del dia `date`. Devuelve `None`
+    // si no hay una tasa para ese dia o alguno anterior en ningun sentido.
+    pub fn convert(
+        &self,
+        amount: &Money,
+        currency: &str,
+        date: NaiveDate,
+    ) -> Result<Option<Money>, MoneyError> {
+        if amount.currency() == currency {
+            return Ok(Some(amount.clone()));
+        }
+
+        match self.find(amount.currency(), currency, date) {
+            Some(RateMatch::Direct(rate)) => amount.convert(&rate.rate, currency).map(Some),
+            Some(RateMatch::Inverse(rate)) => {
+                amount.convert_inverse(&rate.rate, currency).map(Some)
+            }
+            None => Ok(None),
+        }
+    }
+
+    // Lee tasas de un CSV con columnas `date,from,to,rate` (la fila de
+    // encabezado es opcional). Si alguna linea esta mal no se importa nada y el
+    // error indica cada linea con problemas.
+    pub fn parse_csv(content: &str) -> Result<Vec<ExchangeRate>, StorageError> {
+        let mut rates = Vec::new();
+        let mut errors = Vec::new();
+
+        for (index, line) in content.lines().enumerate() {
+            let line = line.trim();
+            let is_header = index == 0 && line.to_ascii_lowercase().starts_with("date");
+            if line.is_empty() || line.starts_with('#') || is_header {
+                continue;
+            }
+
+            match read_csv_line(index + 1, line) {
+                Ok(rate) => rates.push(rate),
+                Err(err) => errors.push(err),
+            }
+        }
+
+        if !errors.is_empty() {
+            return Err(StorageError::new(
+                StorageErrorKind::InvalidRecords,
+                &format!("{} lineas del CSV no se pudieron leer", errors.len()),
+                "RateTable::parse_csv",
+            )
+            .with_records(errors));
+        }
+
+        Ok(rates)
+    }
+}
+
+// Acceso a `rates.json`. Cada modificacion toma el lock `rates.lock` desde que
+// lee el archivo hasta que lo escribe, igual que el ledger JSON.
+#[derive(Debug)]
+pub struct RateStore {
+    path: String,
+    lock_path: String,
+    lock_timeout: Option<Duration>,
+}
+
+impl RateStore {
+    pub fn new(dir: &str) -> RateStore {
+        let dir = Path::new(dir);
+        RateStore {
+            path: dir.join(RATES_FILE).to_string_lossy().to_string(),
+            lock_path: dir.join(RATES_LOCK_FILE).to_string_lossy().to_string(),
+            lock_timeout: None,
+        }
+    }
+
+    pub fn with_lock_timeout(mut self, timeout: Option<Duration>) -> RateStore {
+        self.lock_timeout = timeout;
+        self
+    }
+
+    // Carga la tabla. Si el archivo no existe la tabla queda vacia.
+    pub fn load(&self) -> Result<RateTable, StorageError> {
+        let location = "RateStore::load";
+        let entries = match open_json(&self.path) {
+            Ok(JsonStructure::Array(entries)) => entries,
+            Err(err) if matches!(err.kind(), JsonFileErrorKind::FileNotFound) => {
+                return Ok(RateTable::new());
+            }
+            Ok(JsonStructure::Object(_)) => {
+                return Err(StorageError::new(
+                    StorageErrorKind::CorruptedData,
+                    "El archivo de tasas de cambio no contiene un array",
+                    location,
+                ));
+            }
+            Err(err) => {
+                return Err(StorageError::new(
+                    StorageErrorKind::ReadError,
+                    &format!("No se pudo leer el archivo de tasas de cambio: {}", err),
+                    location,
+                ));
+            }
+        };
+
+        let mut rates = Vec::with_capacity(entries.len());
+        let mut errors = Vec::new();
+        for (index, entry) in entries.iter().enumerate() {
+            match read_rate(index, entry) {
+                Ok(rate) => rates.push(rate),
+                Err(err) => errors.push(err),
+            }
+        }
+
+        if !errors.is_empty() {
+            return Err(StorageError::new(
+                StorageErrorKind::InvalidRecords,
+                &format!("{} tasas de cambio no se pudieron leer", errors.len()),
+                location,
+            )
+            .with_records(errors));
+        }
+
+        Ok(RateTable::with_rates(rates))
+    }
+
+    // Lee la tabla, aplica `change` y la guarda, todo con el lock tomado
+    pub fn modify<T>(
+        &self,
+        change: impl FnOnce(&mut RateTable) -> Result<T, StorageError>,
+    ) -> Result<T, StorageError> {
+        let location = "RateStore::modify";
+        let _lock = FileLock::acquire(&self.lock_path, self.lock_timeout).map_err(|err| {
+            let kind = match err.kind() {
+                JsonFileErrorKind::Locked => StorageErrorKind::Locked,
+                _ => StorageErrorKind::WriteError,
+            };
+            StorageError::new(kind, err.message(), location)
+        })?;
+
+        let mut table = self.load()?;
+        let result = change(&mut table)?;
+
+        let entries = table.rates.iter().map(|rate| json!(rate)).collect();
+        save_json(&self.path, JsonStructure::Array(entries)).map_err(|err| {
+            StorageError::new(
+                StorageErrorKind::WriteError,
+                &format!("No se pudo escribir el archivo de tasas de cambio: {}", err),
+                location,
+            )
+        })?;
+
+        Ok(result)
+    }
+
+    // Agrega o corrige la tasa de un dia. Devuelve la tasa anterior, si habia.
+    pub fn set(&self, rate: ExchangeRate) -> Result<Option<ExchangeRate>, StorageError> {
+        self.modify(|table| Ok(table.set(rate)))
+    }
+
+    // Importa las tasas de un archivo CSV (ver `RateTable::parse_csv`)
+    pub fn import_csv(&self, csv_path: &str) -> Result<RateImport, StorageError> {
+        let content = fs::read_to_string(csv_path).map_err(|err| {
+            StorageError::new(
+                StorageErrorKind::ReadError,
+                &format!("No se pudo leer el archivo {}: {}", csv_path, err),
+                "RateStore::import_csv",
+            )
+        })?;
+        let rates = RateTable::parse_csv(&content)?;
+        self.modify(|table| Ok(table.set_all(rates)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn day(text: &str) -> NaiveDate {
+        NaiveDate::parse_from_str(text, RATE_DATE_FORMAT).unwrap()
+    }
+
+    fn rate(date: &str, from: &str, to: &str, value: &str) -> ExchangeRate {
+        ExchangeRate::new(day(date), from, to, Rate::parse(value).unwrap()).unwrap()
+    }
+
+    fn table() -> RateTable {
+        RateTable::with_rates(vec![
+            rate("2024-03-10", "USD", "COP", "4000"),
+            rate("2024-03-01", "USD", "COP", "3900"),
+            rate("2024-03-20", "USD", "COP", "4100"),
+            rate("2024-03-05", "USD", "EUR", "0.9"),
+        ])
+    }
+
+    #[test]
+    fn find_uses_the_rate_of_the_day_or_the_nearest_earlier() {
+        let table = table();
+        let found = |date: &str| match table.find("USD", "COP", day(date)) {
+            Some(RateMatch::Direct(rate)) => rate.rate.to_string(),
+            other => panic!("{:?}", other),
+        };
+        assert_eq!(found("2024-03-01"), "3900");
+        assert_eq!(found("2024-03-09"), "3900");
+        assert_eq!(found("2024-03-10"), "4000");
+        assert_eq!(found("2024-03-19"), "4000");
+        assert_eq!(found("2024-12-31"), "4100");
+        assert_eq!(table.find("USD", "COP", day("2024-02-29")), None);
+        assert_eq!(table.find("USD", "JPY", day("2024-03-10")), None);
+    }
+
+    #[test]
+    fn find_falls_back_to_the_opposite_pair() {
+        let table = table();
+        assert_eq!(
+            table.find("EUR", "USD", day("2024-03-05")),
+            Some(RateMatch::Inverse(&rate("2024-03-05", "USD", "EUR", "0.9")))
+        );
+        assert_eq!(table.find("EUR", "USD", day("2024-03-04")), None);
+
+        // con tasas en los dos sentidos se usa la del par pedido
+        let mut table = table;
+        table.set(rate("2024-03-01", "EUR", "USD", "1.1"));
+        assert!(matches!(
+            table.find("EUR", "USD", day("2024-03-05")),
+            Some(RateMatch::Direct(_))
+        ));
+    }
+
+    #[test]
+    fn convert_divides_by_the_opposite_rate() {
+        let table = table();
+        let convert = |amount: &str, from: &str, to: &str, date: &str| {
+            table
+                .convert(&Money::parse(amount, from).unwrap(), to, day(date))
+                .unwrap()
+        };
+        assert_eq!(
+            convert("10", "USD", "COP", "2024-03-10"),
+            Some(Money::parse("40000", "COP").unwrap())
+        );
+        assert_eq!(
+            convert("41000", "COP", "USD", "2024-03-25"),
+            Some(Money::parse("10", "USD").unwrap())
+        );
+        assert_eq!(
+            convert("9", "EUR", "USD", "2024-03-05"),
+            Some(Money::parse("10", "USD").unwrap())
+        );
+        assert_eq!(
+            convert("5", "EUR", "EUR", "2020-01-01"),
+            Some(Money::parse("5", "EUR").unwrap())
+        );
+        assert_eq!(convert("5", "EUR", "COP", "2024-03-10"), None);
+    }
+
+    #[test]
+    fn parse_csv_skips_the_header_comments_and_blank_lines() {
+        let content = "date,from,to,rate\n\
+                       # tasas de marzo\n\
+                       \n\
+                       2024-03-01, usd ,cop,3900\n\
+                       2024-03-02,USD,EUR,0.91\n";
+        let rates = RateTable::parse_csv(content).unwrap();
+        assert_eq!(
+            rates,
+            vec![
+                rate("2024-03-01", "USD", "COP", "3900"),
+                rate("2024-03-02", "USD", "EUR", "0.91"),
+            ]
+        );
+    }
+
+    #[test]
+    fn parse_csv_reports_every_invalid_line() {
+        let content = "date,from,to,rate\n\
+                       2024-03-01,USD,COP,3900\n\
+                       2024-03-02,USD,COP\n\
+                       2024-02-30,USD,COP,3900\n\
+                       2024-03-04,DOLAR,COP,3900\n\
+                       2024-03-05,USD,PESO,3900\n\
+                       2024-03-06,USD,COP,0\n\
+                       2024-03-07,USD,COP,abc\n";
+        let err = RateTable::parse_csv(content).unwrap_err();
+        assert!(matches!(err.kind(), StorageErrorKind::InvalidRecords));
+        let lines: Vec<(usize, &str)> = err
+            .records()
+            .iter()
+            .map(|record| (record.index, record.field.as_str()))
+            .collect();
+        assert_eq!(
+            lines,
+            vec![
+                (3, "(linea)"),
+                (4, "date"),
+                (5, "from"),
+                (6, "to"),
+                (7, "rate"),
+                (8, "rate"),
+            ]
+        );
+        assert!(err.records()[0].reason.contains("hay 3"));
+    }
+}
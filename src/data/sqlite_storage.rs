@@ -0,0 +1,1166 @@
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use chrono::{DateTime, Utc};
+use serde_json::json;
+use rusqlite::{
+    params, params_from_iter,
+    types::{Type, Value, ValueRef},
+    Connection, ErrorCode, OptionalExtension, Row,
+};
+
+use super::{
+    expense_storage::{ExpenseStorage, SCHEMA_VERSION},
+    migrations::{run_migrations, Migration, MigrationReport},
+    categories::CATEGORY_SEPARATOR,
+    query::{ExpenseQuery, SortKey},
+};
+use crate::models::{
+    error::{RecordError, StorageError, StorageErrorKind},
+    expense::Expense,
+    money::{
+        currency_exponent, Money, CURRENCY_EXPONENTS, DEFAULT_CURRENCY, DEFAULT_EXPONENT,
+        MAX_AMOUNT_DECIMALS,
+    },
+};
+
+// Backend SQLite: todos los gastos viven en un unico archivo `expenses.db`.
+// A diferencia del backend JSON, las consultas se resuelven en la base de datos
+// y no hace falta reescribir todo el archivo en cada operacion.
+#[derive(Debug)]
+pub struct SqliteExpenseStorage {
+    path: PathBuf,
+    conn: Connection,
+    // transacciones abiertas; las anidadas se hacen con savepoints
+    depth: usize,
+}
+
+// Esquema de la version 1; las versiones siguientes se alcanzan con `MIGRATIONS`
+const SCHEMA: &str = "
+    CREATE TABLE IF NOT EXISTS expenses (
+        id          INTEGER PRIMARY KEY,
+        description TEXT NOT NULL,
+        amount      REAL NOT NULL,
+        created_at  TEXT NOT NULL
+    );
+    CREATE INDEX IF NOT EXISTS idx_expenses_created_at ON expenses (created_at);
+    CREATE TABLE IF NOT EXISTS meta (
+        key   TEXT PRIMARY KEY,
+        value TEXT NOT NULL
+    );
+    INSERT OR IGNORE INTO meta (key, value) VALUES ('next_id', '1');
+";
+
+const MIGRATIONS: &[Migration<Connection>] = &[
+    Migration {
+        version: 2,
+        description: "la tabla meta guarda la fecha de creacion del ledger",
+        apply: add_created_at,
+    },
+    Migration {
+        version: 3,
+        description: "los montos pasan de REAL a unidades menores enteras con moneda",
+        apply: amounts_to_minor_units,
+    },
+    Migration {
+        version: 4,
+        description: "los gastos pueden tener una categoria",
+        apply: add_category,
+    },
+    Migration {
+        version: 5,
+        description: "los gastos pueden tener etiquetas, en la tabla expense_tags",
+        apply: add_tags,
+    },
+    Migration {
+        version: 6,
+        description: "los gastos guardan la fecha de su ultima modificacion",
+        apply: add_updated_at,
+    },
+    Migration {
+        version: 7,
+        description: "los gastos tienen una fecha del gasto aparte de la de creacion",
+        apply: add_spent_at,
+    },
+];
+
+// Cantidad maxima de años que abarca el filtro de mes como rangos de fechas
+const MAX_MONTH_RANGES: i64 = 200;
+
+// Columnas de un gasto en las consultas
+const EXPENSE_COLUMNS: &str =
+    "id, description, amount_minor, currency, category, spent_at, created_at, updated_at";
+
+fn add_created_at(conn: &mut Connection) -> Result<(), StorageError> {
+    conn.execute(
+        "INSERT OR IGNORE INTO meta (key, value) VALUES ('created_at', ?1)",
+        params![Utc::now().to_rfc3339()],
+    )
+    .map_err(|err| sql_error(StorageErrorKind::WriteError, err, "add_created_at"))?;
+    Ok(())
+}
+
+// Reconstruye la tabla de gastos con `amount_minor` y `currency`. Los montos que
+// no se pueden convertir de forma exacta se copian tal cual; `check` los
+// muestra y `repair` los mueve a la cuarentena.
+fn amounts_to_minor_units(conn: &mut Connection) -> Result<(), StorageError> {
+    let location = "amounts_to_minor_units";
+    let write_error = |err| sql_error(StorageErrorKind::WriteError, err, location);
+
+    conn.execute_batch(
+        "CREATE TABLE expenses_v3 (
+            id           INTEGER PRIMARY KEY,
+            description  TEXT NOT NULL,
+            amount_minor INTEGER NOT NULL,
+            currency     TEXT NOT NULL,
+            created_at   TEXT NOT NULL
+        );",
+    )
+    .map_err(write_error)?;
+
+    let rows: Vec<(i64, Value, Value, Value)> = {
+        let mut stmt = conn
+            .prepare("SELECT id, description, amount, created_at FROM expenses")
+            .map_err(write_error)?;
+        let rows = stmt
+            .query_map([], |row| {
+                Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?))
+            })
+            .map_err(write_error)?;
+        rows.collect::<rusqlite::Result<_>>().map_err(write_error)?
+    };
+
+    for (id, description, amount, created_at) in rows {
+        let amount_minor = match &amount {
+            Value::Real(value) => Money::from_f64(*value, DEFAULT_CURRENCY).ok(),
+            Value::Integer(value) => Money::from_f64(*value as f64, DEFAULT_CURRENCY).ok(),
+            _ => None,
+        }
+        .map(|money| Value::Integer(money.minor_units()))
+        .unwrap_or(amount);
+
+        conn.execute(
+            "INSERT INTO expenses_v3 (id, description, amount_minor, currency, created_at)
+             VALUES (?1, ?2, ?3, ?4, ?5)",
+            params![id, description, amount_minor, DEFAULT_CURRENCY, created_at],
+        )
+        .map_err(write_error)?;
+    }
+
+    conn.execute_batch(
+        "DROP TABLE expenses;
+         ALTER TABLE expenses_v3 RENAME TO expenses;
+         CREATE INDEX IF NOT EXISTS idx_expenses_created_at ON expenses (created_at);",
+    )
+    .map_err(write_error)?;
+
+    Ok(())
+}
+
+fn add_category(conn: &mut Connection) -> Result<(), StorageError> {
+    conn.execute_batch("ALTER TABLE expenses ADD COLUMN category TEXT")
+        .map_err(|err| sql_error(StorageErrorKind::WriteError, err, "add_category"))
+}
+
+fn add_tags(conn: &mut Connection) -> Result<(), StorageError> {
+    conn.execute_batch(
+        "CREATE TABLE IF NOT EXISTS expense_tags (
+            expense_id INTEGER NOT NULL,
+            tag        TEXT NOT NULL,
+            PRIMARY KEY (expense_id, tag)
+        );
+        CREATE INDEX IF NOT EXISTS idx_expense_tags_tag ON expense_tags (tag);",
+    )
+    .map_err(|err| sql_error(StorageErrorKind::WriteError, err, "add_tags"))
+}
+
+fn add_updated_at(conn: &mut Connection) -> Result<(), StorageError> {
+    conn.execute_batch("ALTER TABLE expenses ADD COLUMN updated_at TEXT")
+        .map_err(|err| sql_error(StorageErrorKind::WriteError, err, "add_updated_at"))
+}
+
+// La fecha del gasto de las filas existentes es el dia local en que se crearon
+fn add_spent_at(conn: &mut Connection) -> Result<(), StorageError> {
+    conn.execute_batch(
+        "ALTER TABLE expenses ADD COLUMN spent_at TEXT;
+         UPDATE expenses SET spent_at = date(created_at, 'localtime');
+         CREATE INDEX IF NOT EXISTS idx_expenses_spent_at ON expenses (spent_at);",
+    )
+    .map_err(|err| sql_error(StorageErrorKind::WriteError, err, "add_spent_at"))
+}
+
+// Monto de la fila con `MAX_AMOUNT_DECIMALS` decimales sea cual sea su
+// moneda, para comparar y ordenar montos de monedas distintas por su valor
+fn scaled_amount() -> String {
+    let factor = |exponent: u32| 10i64.pow(MAX_AMOUNT_DECIMALS - exponent);
+    let cases: String = CURRENCY_EXPONENTS
+        .iter()
+        .map(|(code, exponent)| format!(" WHEN '{}' THEN {}", code, factor(*exponent)))
+        .collect();
+    format!(
+        "amount_minor * CASE currency{} ELSE {} END",
+        cases,
+        factor(DEFAULT_EXPONENT)
+    )
+}
+
+// Escapa los comodines de `LIKE` para buscar `text` tal cual
+fn escape_like(text: &str) -> String {
+    text.replace('\\', "\\\\")
+        .replace('%', "\\%")
+        .replace('_', "\\_")
+}
+
+fn sql_error(kind: StorageErrorKind, err: rusqlite::Error, location: &str) -> StorageError {
+    StorageError::new(
+        kind,
+        &format!("Error en la base de datos SQLite: {}", err),
+        location,
+    )
+}
+
+// SQLite responde `SQLITE_BUSY` cuando otro proceso tiene la base de datos bloqueada
+fn lock_error(err: rusqlite::Error, location: &str) -> StorageError {
+    match err.sqlite_error_code() {
+        Some(ErrorCode::DatabaseBusy) | Some(ErrorCode::DatabaseLocked) => StorageError::new(
+            StorageErrorKind::Locked,
+            "La base de datos esta bloqueada por otro proceso",
+            location,
+        ),
+        _ => sql_error(StorageErrorKind::TransactionError, err, location),
+    }
+}
+
+fn row_to_expense(row: &Row) -> rusqlite::Result<Expense> {
+    let id: i64 = row.get("id")?;
+    let created_at: DateTime<Utc> = row.get("created_at")?;
+    let currency: String = row.get("currency")?;
+    let amount = Money::new(row.get("amount_minor")?, &currency).map_err(|err| {
+        rusqlite::Error::FromSqlConversionFailure(
+            row.as_ref().column_index("currency").unwrap_or_default(),
+            Type::Text,
+            Box::new(err),
+        )
+    })?;
+    Ok(Expense {
+        id: id.to_string(),
+        description: row.get("description")?,
+        amount,
+        category: row.get("category")?,
+        // las etiquetas estan en otra tabla; `select` las completa
+        tags: Vec::new(),
+        spent_at: row.get("spent_at")?,
+        created_at,
+        updated_at: row.get("updated_at")?,
+    })
+}
+
+// Columna que provoco el error al convertir una fila
+fn failed_column(err: &rusqlite::Error, columns: &[String]) -> String {
+    let column = match err {
+        rusqlite::Error::InvalidColumnType(_, name, _) => Some(name.clone()),
+        rusqlite::Error::FromSqlConversionFailure(index, _, _)
+        | rusqlite::Error::IntegralValueOutOfRange(index, _) => columns.get(*index).cloned(),
+        _ => None,
+    };
+    column.unwrap_or("(registro)".to_string())
+}
+
+fn parse_id(id: &str, location: &str) -> Result<i64, StorageError> {
+    id.parse::<i64>().map_err(|_| {
+        StorageError::new(
+            StorageErrorKind::IdsError,
+            &format!("El id {} no es numerico", id),
+            location,
+        )
+    })
+}
+
+impl SqliteExpenseStorage {
+    // Abre (o crea) la base de datos `expenses.db` dentro del directorio indicado
+    pub fn new(dir: &str) -> Result<SqliteExpenseStorage, StorageError> {
+        let path = Path::new(dir).join("expenses.db");
+        let conn = Connection::open(&path).map_err(|err| {
+            sql_error(
+                StorageErrorKind::ReadError,
+                err,
+                "SqliteExpenseStorage::new",
+            )
+        })?;
+
+        Ok(SqliteExpenseStorage {
+            path,
+            conn,
+            depth: 0,
+        })
+    }
+
+    // Tiempo maximo que SQLite espera a que otro proceso suelte la base de
+    // datos. Sin timeout se falla enseguida si esta bloqueada.
+    pub fn with_lock_timeout(
+        self,
+        timeout: Option<Duration>,
+    ) -> Result<SqliteExpenseStorage, StorageError> {
+        self.conn
+            .busy_timeout(timeout.unwrap_or(Duration::ZERO))
+            .map_err(|err| {
+                sql_error(
+                    StorageErrorKind::WriteError,
+                    err,
+                    "SqliteExpenseStorage::with_lock_timeout",
+                )
+            })?;
+        Ok(self)
+    }
+
+    // Aplica dentro de una transaccion las migraciones pendientes y actualiza
+    // `user_version`. La version se lee despues de `BEGIN IMMEDIATE`, asi si
+    // otro proceso migro la base de datos mientras tanto no se hace nada. Con
+    // `backup` se copia el archivo antes de migrar, ya con el lock tomado. Si
+    // alguna migracion falla la base de datos queda como estaba.
+    fn upgrade(&mut self, backup: bool) -> Result<MigrationReport, StorageError> {
+        self.begin()?;
+        match self.upgrade_locked(backup) {
+            Ok(report) => {
+                self.commit()?;
+                Ok(report)
+            }
+            Err(err) => {
+                let _ = self.rollback();
+                Err(err)
+            }
+        }
+    }
+
+    fn upgrade_locked(&mut self, backup: bool) -> Result<MigrationReport, StorageError> {
+        let location = "SqliteExpenseStorage::upgrade";
+        let from_version = self.schema_version()?;
+        if from_version == SCHEMA_VERSION {
+            return Ok(MigrationReport::up_to_date(from_version));
+        }
+
+        // copia del archivo completo antes de tocar el esquema
+        let backup_path = match backup {
+            true => {
+                let path = format!("{}.v{}.bak", self.path.to_string_lossy(), from_version);
+                fs::copy(&self.path, &path).map_err(|err| {
+                    StorageError::new(
+                        StorageErrorKind::WriteError,
+                        &format!("No se pudo crear la copia de seguridad: {}", err),
+                        location,
+                    )
+                })?;
+                Some(path)
+            }
+            false => None,
+        };
+
+        let applied = run_migrations(&mut self.conn, from_version, SCHEMA_VERSION, MIGRATIONS)?;
+        self.conn
+            .pragma_update(None, "user_version", SCHEMA_VERSION)
+            .map_err(|err| sql_error(StorageErrorKind::WriteError, err, location))?;
+
+        Ok(MigrationReport {
+            from_version,
+            to_version: SCHEMA_VERSION,
+            applied,
+            backup_path,
+        })
+    }
+
+    // Ejecuta la consulta y convierte cada fila por separado, para informar de
+    // todas las filas que no se pudieron leer y no solo de la primera. Los
+    // filtros, el orden y la pagina se resuelven en SQLite salvo la expresion
+    // regular y el texto con letras no ASCII (`LIKE` solo ignora las mayusculas
+    // ASCII), que se evaluan despues sobre las filas ya reducidas.
+    fn select(&self, query: &ExpenseQuery, location: &str) -> Result<Vec<Expense>, StorageError> {
+        let mut conditions: Vec<String> = Vec::new();
+        let mut values: Vec<Value> = Vec::new();
+        let in_memory =
+            query.pattern.is_some() || query.text.as_ref().is_some_and(|text| !text.is_ascii());
+
+        if let Some(ids) = &query.ids {
+            // los ids no numericos no pueden existir en la tabla
+            let ids: Vec<i64> = ids.iter().filter_map(|id| id.parse().ok()).collect();
+            let placeholders = vec!["?"; ids.len()].join(", ");
+            conditions.push(format!("id IN ({})", placeholders));
+            values.extend(ids.into_iter().map(Value::Integer));
+        }
+
+        if let Some(month) = query.month {
+            conditions.push(self.month_condition(month, &mut values, location)?);
+        }
+
+        for tag in &query.tags {
+            conditions.push("id IN (SELECT expense_id FROM expense_tags WHERE tag = ?)".to_string());
+            values.push(Value::Text(tag.clone()));
+        }
+
+        if let Some(from) = query.from {
+            conditions.push("spent_at >= ?".to_string());
+            values.push(Value::Text(from.to_string()));
+        }
+        if let Some(to) = query.to {
+            conditions.push("spent_at <= ?".to_string());
+            values.push(Value::Text(to.to_string()));
+        }
+
+        // con una sola moneda los montos se comparan en sus unidades menores; si
+        // no, cada fila se lleva a la escala de `Amount`
+        let (amount, exponent) = match &query.currency {
+            Some(currency) => ("amount_minor".to_string(), currency_exponent(currency)),
+            None => (scaled_amount(), MAX_AMOUNT_DECIMALS),
+        };
+        if let Some(min) = query.min_amount {
+            conditions.push(format!("{} >= ?", amount));
+            values.push(Value::Integer(min.minor_units(exponent, true)));
+        }
+        if let Some(max) = query.max_amount {
+            conditions.push(format!("{} <= ?", amount));
+            values.push(Value::Integer(max.minor_units(exponent, false)));
+        }
+
+        if let Some(text) = query.text.as_ref().filter(|text| text.is_ascii()) {
+            conditions.push("description LIKE ? ESCAPE '\\'".to_string());
+            values.push(Value::Text(format!("%{}%", escape_like(text))));
+        }
+
+        // la categoria y sus subcategorias, sin distinguir mayusculas
+        if let Some(category) = &query.category {
+            conditions
+                .push("(category = ? COLLATE NOCASE OR category LIKE ? ESCAPE '\\')".to_string());
+            values.push(Value::Text(category.clone()));
+            values.push(Value::Text(format!(
+                "{}{}%",
+                escape_like(category),
+                CATEGORY_SEPARATOR
+            )));
+        }
+
+        if let Some(currency) = &query.currency {
+            conditions.push("currency = ?".to_string());
+            values.push(Value::Text(currency.clone()));
+        }
+
+        let mut clause = match conditions.is_empty() {
+            true => String::new(),
+            false => format!(" WHERE {}", conditions.join(" AND ")),
+        };
+
+        // los empates en el campo de orden se ordenan por id, en el mismo sentido
+        let direction = if query.descending { "DESC" } else { "ASC" };
+        let key = match query.sort {
+            SortKey::Id => None,
+            SortKey::Date => Some("spent_at".to_string()),
+            SortKey::Amount => Some(scaled_amount()),
+            SortKey::Description => Some("lower(description)".to_string()),
+            SortKey::Category => Some("lower(category)".to_string()),
+        };
+        let mut order: Vec<String> = key
+            .into_iter()
+            .map(|key| format!("{} {}", key, direction))
+            .collect();
+        order.push(format!("id {}", direction));
+        clause.push_str(&format!(" ORDER BY {}", order.join(", ")));
+
+        if !in_memory && (query.limit.is_some() || query.offset > 0) {
+            // un LIMIT negativo no limita
+            let limit = query.limit.map_or(-1, |limit| i64::try_from(limit).unwrap_or(i64::MAX));
+            clause.push_str(" LIMIT ? OFFSET ?");
+            values.push(Value::Integer(limit));
+            values.push(Value::Integer(i64::try_from(query.offset).unwrap_or(i64::MAX)));
+        }
+
+        let read_error = |err| sql_error(StorageErrorKind::ReadError, err, location);
+        let mut tags = self.tags_by_expense(&clause, &values, location)?;
+        let sql = format!("SELECT {} FROM expenses{}", EXPENSE_COLUMNS, clause);
+        let mut stmt = self.conn.prepare(&sql).map_err(read_error)?;
+        let columns: Vec<String> = stmt.column_names().into_iter().map(String::from).collect();
+        let mut rows = stmt.query(params_from_iter(values)).map_err(read_error)?;
+
+        let mut expenses = Vec::new();
+        let mut errors = Vec::new();
+        let mut index = 0;
+        while let Some(row) = rows.next().map_err(read_error)? {
+            match row_to_expense(row) {
+                Ok(mut expense) => {
+                    let id = row.get::<_, i64>("id").unwrap_or_default();
+                    expense.tags = tags.remove(&id).unwrap_or_default();
+                    expenses.push(expense);
+                }
+                Err(err) => errors.push(RecordError {
+                    index,
+                    id: row.get::<_, i64>("id").ok().map(|id| id.to_string()),
+                    field: failed_column(&err, &columns),
+                    reason: err.to_string(),
+                }),
+            }
+            index += 1;
+        }
+
+        if !errors.is_empty() {
+            return Err(StorageError::new(
+                StorageErrorKind::InvalidRecords,
+                &format!("{} registros de gastos no se pudieron leer", errors.len()),
+                location,
+            )
+            .with_records(errors));
+        }
+
+        if !in_memory {
+            return Ok(expenses);
+        }
+        // las filas ya vienen ordenadas, falta filtrar y tomar la pagina
+        Ok(expenses
+            .into_iter()
+            .filter(|expense| query.matches(expense))
+            .skip(query.offset)
+            .take(query.limit.unwrap_or(usize::MAX))
+            .collect())
+    }
+
+    // Condicion de los gastos del mes `month` de cualquier año. Se escribe
+    // como un rango de `spent_at` por cada año entre el primer y el ultimo
+    // gasto, para que se use su indice. Si las fechas guardadas no permiten
+    // calcular los años se compara el mes de cada fila.
+    fn month_condition(
+        &self,
+        month: u32,
+        values: &mut Vec<Value>,
+        location: &str,
+    ) -> Result<String, StorageError> {
+        let years: (Option<i64>, Option<i64>) = self
+            .conn
+            .query_row(
+                "SELECT CAST(substr(MIN(spent_at), 1, 4) AS INTEGER),
+                        CAST(substr(MAX(spent_at), 1, 4) AS INTEGER)
+                 FROM expenses",
+                [],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )
+            .map_err(|err| sql_error(StorageErrorKind::ReadError, err, location))?;
+
+        match years {
+            // no hay gastos con fecha
+            (None, _) | (_, None) => Ok("0".to_string()),
+            (Some(first), Some(last))
+                if 1 <= first && first <= last && last <= 9999 && last - first < MAX_MONTH_RANGES =>
+            {
+                // "2024-03" <= "2024-03-10" < "2024-04"; diciembre termina en "2024-13"
+                let ranges = vec!["(spent_at >= ? AND spent_at < ?)"; (last - first + 1) as usize];
+                for year in first..=last {
+                    values.push(Value::Text(format!("{:04}-{:02}", year, month)));
+                    values.push(Value::Text(format!("{:04}-{:02}", year, month + 1)));
+                }
+                Ok(format!("({})", ranges.join(" OR ")))
+            }
+            _ => {
+                values.push(Value::Integer(month.into()));
+                Ok("CAST(strftime('%m', spent_at) AS INTEGER) = ?".to_string())
+            }
+        }
+    }
+
+    // Etiquetas ordenadas de cada gasto que selecciona `clause` (el WHERE, el
+    // orden y la pagina de la consulta de gastos, con sus `values`; vacia para
+    // todos)
+    fn tags_by_expense(
+        &self,
+        clause: &str,
+        values: &[Value],
+        location: &str,
+    ) -> Result<HashMap<i64, Vec<String>>, StorageError> {
+        let read_error = |err| sql_error(StorageErrorKind::ReadError, err, location);
+        let mut stmt = self
+            .conn
+            .prepare(&format!(
+                "SELECT expense_id, tag FROM expense_tags
+                 WHERE expense_id IN (SELECT id FROM expenses{})
+                 ORDER BY expense_id, tag",
+                clause
+            ))
+            .map_err(read_error)?;
+        let rows = stmt
+            .query_map(params_from_iter(values), |row| {
+                Ok((row.get::<_, i64>(0)?, row.get::<_, String>(1)?))
+            })
+            .map_err(read_error)?;
+
+        let mut tags: HashMap<i64, Vec<String>> = HashMap::new();
+        for row in rows {
+            let (id, tag) = row.map_err(read_error)?;
+            tags.entry(id).or_default().push(tag);
+        }
+        Ok(tags)
+    }
+
+    // Ejecuta varias sentencias de una escritura dentro de un savepoint propio,
+    // para que se apliquen todas o ninguna aunque no haya una transaccion
+    // abierta con `begin`
+    fn atomically<T>(
+        &mut self,
+        location: &str,
+        write: impl FnOnce(&mut Self) -> Result<T, StorageError>,
+    ) -> Result<T, StorageError> {
+        self.conn
+            .execute_batch("SAVEPOINT escritura")
+            .map_err(|err| lock_error(err, location))?;
+        match write(self) {
+            Ok(value) => {
+                self.conn
+                    .execute_batch("RELEASE escritura")
+                    .map_err(|err| sql_error(StorageErrorKind::TransactionError, err, location))?;
+                Ok(value)
+            }
+            Err(err) => {
+                let _ = self
+                    .conn
+                    .execute_batch("ROLLBACK TO escritura; RELEASE escritura");
+                Err(err)
+            }
+        }
+    }
+
+    // Reemplaza las etiquetas del gasto `id` por `tags`
+    fn save_tags(&self, id: i64, tags: &[String], location: &str) -> Result<(), StorageError> {
+        let write_error = |err| sql_error(StorageErrorKind::WriteError, err, location);
+        self.conn
+            .execute("DELETE FROM expense_tags WHERE expense_id = ?1", params![id])
+            .map_err(write_error)?;
+        for tag in tags {
+            self.conn
+                .execute(
+                    "INSERT OR IGNORE INTO expense_tags (expense_id, tag) VALUES (?1, ?2)",
+                    params![id, tag],
+                )
+                .map_err(write_error)?;
+        }
+        Ok(())
+    }
+}
+
+impl ExpenseStorage for SqliteExpenseStorage {
+    // Crea las tablas si la base de datos esta vacia. La version del esquema
+    // se guarda en `PRAGMA user_version`.
+    fn init(&mut self) -> Result<bool, StorageError> {
+        let location = "SqliteExpenseStorage::init";
+        let existed: bool = self
+            .conn
+            .query_row(
+                "SELECT EXISTS (SELECT 1 FROM sqlite_master WHERE type = 'table' AND name = 'expenses')",
+                [],
+                |row| row.get(0),
+            )
+            .map_err(|err| sql_error(StorageErrorKind::ReadError, err, location))?;
+
+        self.conn
+            .execute_batch(SCHEMA)
+            .map_err(|err| sql_error(StorageErrorKind::WriteError, err, location))?;
+
+        // las bases de datos creadas antes de la cabecera quedan en la version 1
+        if self.schema_version()? == 0 {
+            self.conn
+                .pragma_update(None, "user_version", 1)
+                .map_err(|err| sql_error(StorageErrorKind::WriteError, err, location))?;
+        }
+
+        // una base de datos nueva queda directamente en la ultima version
+        if !existed {
+            self.upgrade(false)?;
+        }
+
+        Ok(!existed)
+    }
+
+    fn migrate(&mut self) -> Result<MigrationReport, StorageError> {
+        // casi siempre esta al dia; el lock solo se toma si hay que migrar
+        let version = self.schema_version()?;
+        if version == SCHEMA_VERSION {
+            return Ok(MigrationReport::up_to_date(version));
+        }
+        self.upgrade(true)
+    }
+
+    fn schema_version(&self) -> Result<u32, StorageError> {
+        self.conn
+            .query_row("PRAGMA user_version", [], |row| row.get(0))
+            .map_err(|err| {
+                sql_error(
+                    StorageErrorKind::ReadError,
+                    err,
+                    "SqliteExpenseStorage::schema_version",
+                )
+            })
+    }
+
+    fn load(&self) -> Result<Vec<Expense>, StorageError> {
+        self.select(&ExpenseQuery::new(), "SqliteExpenseStorage::load")
+    }
+
+    // Las filas se convierten columna a columna a JSON sin interpretar los
+    // valores, asi una fecha o un importe con un tipo incorrecto se ven tal cual
+    fn raw_records(&self) -> Result<Vec<serde_json::Value>, StorageError> {
+        let location = "SqliteExpenseStorage::raw_records";
+        let read_error = |err| sql_error(StorageErrorKind::ReadError, err, location);
+        let mut stmt = self
+            .conn
+            .prepare(&format!("SELECT {} FROM expenses ORDER BY id", EXPENSE_COLUMNS))
+            .map_err(read_error)?;
+        let columns: Vec<String> = stmt.column_names().into_iter().map(String::from).collect();
+        let mut rows = stmt.query([]).map_err(read_error)?;
+        let mut tags = self.tags_by_expense("", &[], location)?;
+
+        let mut records = Vec::new();
+        while let Some(row) = rows.next().map_err(read_error)? {
+            let mut record = serde_json::Map::new();
+            for (index, column) in columns.iter().enumerate() {
+                let value = match row.get_ref(index).map_err(read_error)? {
+                    ValueRef::Null => serde_json::Value::Null,
+                    // los ids se guardan como texto en el resto de backends
+                    ValueRef::Integer(value) if column == "id" => json!(value.to_string()),
+                    ValueRef::Integer(value) => json!(value),
+                    ValueRef::Real(value) => json!(value),
+                    ValueRef::Text(value) | ValueRef::Blob(value) => {
+                        json!(String::from_utf8_lossy(value))
+                    }
+                };
+                record.insert(column.clone(), value);
+            }
+
+            // el monto se muestra igual que en el resto de backends; si la
+            // columna no tiene un entero se deja el valor original
+            let amount_minor = record.remove("amount_minor").unwrap_or_default();
+            let currency = record
+                .get("currency")
+                .and_then(|currency| currency.as_str())
+                .unwrap_or_default();
+            let amount = match amount_minor.as_i64().map(|minor| Money::new(minor, currency)) {
+                Some(Ok(money)) => json!(money.amount_string()),
+                _ => amount_minor,
+            };
+            record.insert("amount".to_string(), amount);
+
+            if let Some(tags) = row.get::<_, i64>("id").ok().and_then(|id| tags.remove(&id)) {
+                record.insert("tags".to_string(), json!(tags));
+            }
+            records.push(serde_json::Value::Object(record));
+        }
+
+        Ok(records)
+    }
+
+    fn id_counter(&self) -> Result<i64, StorageError> {
+        let location = "SqliteExpenseStorage::id_counter";
+        let id: Option<String> = self
+            .conn
+            .query_row("SELECT value FROM meta WHERE key = 'next_id'", [], |row| {
+                row.get(0)
+            })
+            .optional()
+            .map_err(|err| sql_error(StorageErrorKind::IdsError, err, location))?;
+
+        id.and_then(|id| id.parse::<i64>().ok())
+            .ok_or(StorageError::new(
+                StorageErrorKind::IdsError,
+                "La clave next_id no fue encontrada en la tabla meta",
+                location,
+            ))
+    }
+
+    fn replace_all(&mut self, expenses: &[Expense], next_id: i64) -> Result<(), StorageError> {
+        let location = "SqliteExpenseStorage::replace_all";
+        self.atomically(location, |storage| {
+            storage
+                .conn
+                .execute_batch("DELETE FROM expenses; DELETE FROM expense_tags;")
+                .map_err(|err| sql_error(StorageErrorKind::WriteError, err, location))?;
+            for expense in expenses {
+                storage.insert(expense)?;
+            }
+            storage
+                .conn
+                .execute(
+                    "UPDATE meta SET value = ?1 WHERE key = 'next_id'",
+                    params![next_id.to_string()],
+                )
+                .map_err(|err| sql_error(StorageErrorKind::IdsError, err, location))?;
+
+            Ok(())
+        })
+    }
+
+    fn next_id(&mut self) -> Result<String, StorageError> {
+        let location = "SqliteExpenseStorage::next_id";
+        let id = self.id_counter()?;
+
+        self.conn
+            .execute(
+                "UPDATE meta SET value = ?1 WHERE key = 'next_id'",
+                params![(id + 1).to_string()],
+            )
+            .map_err(|err| sql_error(StorageErrorKind::IdsError, err, location))?;
+
+        Ok(id.to_string())
+    }
+
+    fn insert(&mut self, expense: &Expense) -> Result<(), StorageError> {
+        let location = "SqliteExpenseStorage::insert";
+        let id = parse_id(&expense.id, location)?;
+
+        // la fila y sus etiquetas se guardan juntas
+        self.atomically(location, |storage| {
+            storage
+                .conn
+                .execute(
+                    "INSERT INTO expenses
+                         (id, description, amount_minor, currency, category, spent_at,
+                          created_at, updated_at)
+                     VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+                    params![
+                        id,
+                        expense.description,
+                        expense.amount.minor_units(),
+                        expense.amount.currency(),
+                        expense.category,
+                        expense.spent_at,
+                        expense.created_at,
+                        expense.updated_at
+                    ],
+                )
+                .map_err(|err| sql_error(StorageErrorKind::WriteError, err, location))?;
+            storage.save_tags(id, &expense.tags, location)
+        })
+    }
+
+    fn update(&mut self, expense: &Expense) -> Result<(), StorageError> {
+        let location = "SqliteExpenseStorage::update";
+        let id = parse_id(&expense.id, location)?;
+
+        self.atomically(location, |storage| {
+            let changed = storage
+                .conn
+                .execute(
+                    "UPDATE expenses
+                     SET description = ?2, amount_minor = ?3, currency = ?4, category = ?5,
+                         spent_at = ?6, created_at = ?7, updated_at = ?8
+                     WHERE id = ?1",
+                    params![
+                        id,
+                        expense.description,
+                        expense.amount.minor_units(),
+                        expense.amount.currency(),
+                        expense.category,
+                        expense.spent_at,
+                        expense.created_at,
+                        expense.updated_at
+                    ],
+                )
+                .map_err(|err| sql_error(StorageErrorKind::WriteError, err, location))?;
+
+            if changed == 0 {
+                return Err(StorageError::new(
+                    StorageErrorKind::NotFound,
+                    &format!("No existe un gasto con id {}", expense.id),
+                    location,
+                ));
+            }
+            storage.save_tags(id, &expense.tags, location)
+        })
+    }
+
+    fn delete(&mut self, ids: &[String]) -> Result<usize, StorageError> {
+        let location = "SqliteExpenseStorage::delete";
+
+        self.atomically(location, |storage| {
+            let mut deleted = 0;
+            for id in ids {
+                // un id no numerico no puede existir en la tabla
+                let Ok(id) = id.parse::<i64>() else {
+                    continue;
+                };
+                deleted += storage
+                    .conn
+                    .execute("DELETE FROM expenses WHERE id = ?1", params![id])
+                    .map_err(|err| sql_error(StorageErrorKind::WriteError, err, location))?;
+                storage
+                    .conn
+                    .execute("DELETE FROM expense_tags WHERE expense_id = ?1", params![id])
+                    .map_err(|err| sql_error(StorageErrorKind::WriteError, err, location))?;
+            }
+            Ok(deleted)
+        })
+    }
+
+    // SQLite ya mantiene su propio journal, asi que una transaccion de la base
+    // de datos basta para que el id y el gasto se confirmen juntos
+    fn begin(&mut self) -> Result<(), StorageError> {
+        let sql = match self.depth {
+            0 => "BEGIN IMMEDIATE".to_string(),
+            depth => format!("SAVEPOINT nivel_{}", depth),
+        };
+        self.conn
+            .execute_batch(&sql)
+            .map_err(|err| lock_error(err, "SqliteExpenseStorage::begin"))?;
+        self.depth += 1;
+        Ok(())
+    }
+
+    fn commit(&mut self) -> Result<(), StorageError> {
+        let sql = match self.depth {
+            0 | 1 => "COMMIT".to_string(),
+            depth => format!("RELEASE nivel_{}", depth - 1),
+        };
+        self.conn.execute_batch(&sql).map_err(|err| {
+            sql_error(
+                StorageErrorKind::TransactionError,
+                err,
+                "SqliteExpenseStorage::commit",
+            )
+        })?;
+        self.depth = self.depth.saturating_sub(1);
+        Ok(())
+    }
+
+    fn rollback(&mut self) -> Result<(), StorageError> {
+        let sql = match self.depth {
+            0 | 1 => "ROLLBACK".to_string(),
+            depth => format!("ROLLBACK TO nivel_{0}; RELEASE nivel_{0}", depth - 1),
+        };
+        self.conn.execute_batch(&sql).map_err(|err| {
+            sql_error(
+                StorageErrorKind::TransactionError,
+                err,
+                "SqliteExpenseStorage::rollback",
+            )
+        })?;
+        self.depth = self.depth.saturating_sub(1);
+        Ok(())
+    }
+
+    fn query(&self, query: &ExpenseQuery) -> Result<Vec<Expense>, StorageError> {
+        self.select(query, "SqliteExpenseStorage::query")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::NaiveDate;
+    use regex::Regex;
+
+    use super::*;
+    use crate::models::money::Amount;
+    use crate::utils::test_utils::TempDir;
+
+    // Base de datos de la version 1: montos REAL sin moneda
+    fn create_version_1(dir: &TempDir) {
+        let conn = Connection::open(dir.join("expenses.db")).unwrap();
+        conn.execute_batch(SCHEMA).unwrap();
+        conn.execute_batch(
+            "INSERT INTO expenses VALUES
+                 (1, 'Cafe', 2700.5, '2024-03-10T12:00:00+00:00'),
+                 (2, 'Pan', 0.30000000000000004, '2024-03-11T12:00:00+00:00');
+             UPDATE meta SET value = '3' WHERE key = 'next_id';
+             PRAGMA user_version = 1;",
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn migrate_from_version_1() {
+        let dir = TempDir::new("migrate");
+        create_version_1(&dir);
+
+        let mut storage = SqliteExpenseStorage::new(dir.path()).unwrap();
+        assert!(!storage.init().unwrap());
+        assert_eq!(storage.schema_version().unwrap(), 1);
+
+        let report = storage.migrate().unwrap();
+        assert_eq!(report.from_version, 1);
+        assert_eq!(report.to_version, SCHEMA_VERSION);
+        assert_eq!(report.applied.len(), 6);
+        assert!(Path::new(&report.backup_path.unwrap()).exists());
+        assert_eq!(storage.schema_version().unwrap(), SCHEMA_VERSION);
+        assert_eq!(storage.id_counter().unwrap(), 3);
+
+        // el monto que no se puede convertir exacto queda para `check`
+        let err = storage.load().unwrap_err();
+        assert!(matches!(err.kind(), StorageErrorKind::InvalidRecords));
+        assert_eq!(err.records().len(), 1);
+        assert_eq!(err.records()[0].id.as_deref(), Some("2"));
+
+        let cafe = storage
+            .query(&ExpenseQuery::new().with_ids(vec!["1".to_string()]))
+            .unwrap();
+        assert_eq!(
+            cafe[0].amount,
+            Money::parse("2700.5", DEFAULT_CURRENCY).unwrap()
+        );
+        assert_eq!(cafe[0].category, None);
+        assert!(cafe[0].tags.is_empty());
+        assert!(cafe[0].updated_at.is_none());
+
+        let report = storage.migrate().unwrap();
+        assert!(report.applied.is_empty());
+    }
+
+    #[test]
+    fn upgrade_skips_a_database_migrated_by_another_process() {
+        let dir = TempDir::new("migrate_race");
+        create_version_1(&dir);
+        // los dos procesos vieron la version 1 antes de tomar el lock
+        let mut first = SqliteExpenseStorage::new(dir.path()).unwrap();
+        let mut second = SqliteExpenseStorage::new(dir.path()).unwrap();
+        assert_eq!(first.schema_version().unwrap(), 1);
+        assert_eq!(second.schema_version().unwrap(), 1);
+
+        assert_eq!(first.migrate().unwrap().applied.len(), 6);
+        let report = second.upgrade(true).unwrap();
+        assert_eq!(report.from_version, SCHEMA_VERSION);
+        assert!(report.applied.is_empty());
+        assert!(report.backup_path.is_none());
+        assert_eq!(second.id_counter().unwrap(), 3);
+        assert_eq!(second.raw_records().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn new_database_starts_at_the_current_version() {
+        let dir = TempDir::new("init");
+        let mut storage = SqliteExpenseStorage::new(dir.path()).unwrap();
+        assert!(storage.init().unwrap());
+        assert_eq!(storage.schema_version().unwrap(), SCHEMA_VERSION);
+        assert!(storage.migrate().unwrap().backup_path.is_none());
+    }
+
+    fn expense(id: i64, description: &str, amount: &str, currency: &str, day: &str) -> Expense {
+        Expense {
+            id: id.to_string(),
+            description: description.to_string(),
+            amount: Money::parse(amount, currency).unwrap(),
+            category: None,
+            tags: Vec::new(),
+            spent_at: day.parse::<NaiveDate>().unwrap(),
+            created_at: Utc::now(),
+            updated_at: None,
+        }
+    }
+
+    // Gastos con montos en monedas de 0, 2 y 3 decimales, varios años y
+    // descripciones con comodines de `LIKE` y letras no ASCII
+    fn sample() -> Vec<Expense> {
+        let mut expenses = vec![
+            expense(1, "Cafe", "2700", "COP", "2023-03-05"),
+            expense(2, "Taxi al 50%", "12.5", "USD", "2024-03-31"),
+            expense(3, "Ñandú de peluche", "1500", "JPY", "2024-04-01"),
+            expense(4, "pan_integral", "0.125", "KWD", "2024-12-31"),
+            expense(5, "Almuerzo", "12.49", "USD", "2025-03-01"),
+            expense(6, "cafe con leche", "3000", "COP", "2022-02-28"),
+            expense(7, "Gasolina", "50", "USD", "2024-03-15"),
+        ];
+        let categories = [
+            (0, "Comida > Cafe"),
+            (1, "Transporte"),
+            (3, "Comida"),
+            (4, "comida > almuerzos"),
+            (5, "Comidas"),
+            (6, "Transporte > Gasolina"),
+        ];
+        for (index, category) in categories {
+            expenses[index].category = Some(category.to_string());
+        }
+        expenses[0].tags = vec!["trabajo".to_string()];
+        expenses[4].tags = vec!["casa".to_string(), "trabajo".to_string()];
+        expenses[6].tags = vec!["viaje".to_string()];
+        expenses
+    }
+
+    fn ids(expenses: &[Expense]) -> Vec<String> {
+        expenses.iter().map(|expense| expense.id.clone()).collect()
+    }
+
+    #[test]
+    fn query_matches_the_in_memory_filter() {
+        let dir = TempDir::new("query");
+        let mut storage = SqliteExpenseStorage::new(dir.path()).unwrap();
+        storage.init().unwrap();
+        let expenses = sample();
+        for expense in &expenses {
+            storage.insert(expense).unwrap();
+        }
+
+        let amount = |text: &str| Amount::parse(text).unwrap();
+        let queries = [
+            ExpenseQuery::new(),
+            ExpenseQuery::new().with_month(3),
+            ExpenseQuery::new().with_month(12),
+            ExpenseQuery::new().with_month(4).with_currency("JPY"),
+            ExpenseQuery::new().with_tag("trabajo"),
+            ExpenseQuery::new().with_tag("trabajo").with_tag("casa"),
+            ExpenseQuery::new().with_from("2024-03-31".parse().unwrap()),
+            ExpenseQuery::new().with_to("2024-03-31".parse().unwrap()),
+            ExpenseQuery::new().with_min_amount(amount("12.5")),
+            ExpenseQuery::new().with_max_amount(amount("12.495")),
+            ExpenseQuery::new()
+                .with_min_amount(amount("0.125"))
+                .with_max_amount(amount("50")),
+            ExpenseQuery::new()
+                .with_currency("USD")
+                .with_min_amount(amount("12.491")),
+            ExpenseQuery::new()
+                .with_currency("USD")
+                .with_max_amount(amount("12.499")),
+            ExpenseQuery::new().with_text("CAFE"),
+            ExpenseQuery::new().with_text("50%"),
+            ExpenseQuery::new().with_text("n_i"),
+            ExpenseQuery::new().with_text("_"),
+            ExpenseQuery::new().with_text("ñandú"),
+            ExpenseQuery::new().with_pattern(Regex::new("^[A-Z]").unwrap()),
+            ExpenseQuery::new().with_category("comida"),
+            ExpenseQuery::new().with_category("Transporte"),
+            ExpenseQuery::new().with_category("Comida > Cafe"),
+            ExpenseQuery::new().with_ids(vec!["2".to_string(), "x".to_string(), "5".to_string()]),
+            ExpenseQuery::new().sorted_by(SortKey::Amount, false),
+            ExpenseQuery::new().sorted_by(SortKey::Amount, true),
+            ExpenseQuery::new().sorted_by(SortKey::Date, true),
+            ExpenseQuery::new().sorted_by(SortKey::Description, false),
+            ExpenseQuery::new().sorted_by(SortKey::Category, false),
+            ExpenseQuery::new().sorted_by(SortKey::Category, true),
+            ExpenseQuery::new()
+                .sorted_by(SortKey::Date, false)
+                .with_limit(2),
+            ExpenseQuery::new().with_offset(5),
+            ExpenseQuery::new()
+                .with_limit(2)
+                .with_offset(1)
+                .sorted_by(SortKey::Id, true),
+            ExpenseQuery::new()
+                .with_pattern(Regex::new("a").unwrap())
+                .sorted_by(SortKey::Amount, true)
+                .with_limit(2)
+                .with_offset(1),
+            ExpenseQuery::new().with_text("ñ").with_limit(1),
+        ];
+        for query in queries {
+            assert_eq!(
+                ids(&storage.query(&query).unwrap()),
+                ids(&query.apply(expenses.clone())),
+                "{:?}",
+                query
+            );
+        }
+    }
+
+    #[test]
+    fn query_by_month_without_expenses() {
+        let dir = TempDir::new("query_empty");
+        let mut storage = SqliteExpenseStorage::new(dir.path()).unwrap();
+        storage.init().unwrap();
+        assert!(storage
+            .query(&ExpenseQuery::new().with_month(3))
+            .unwrap()
+            .is_empty());
+    }
+}
@@ -1,85 +1,487 @@
 use std::{
+    env,
     error::Error,
-    io::{self, Write},
+    io::{self, IsTerminal},
+    path::Path,
     process,
 };
 
+use chrono::{Local, NaiveDate};
 use expense_tracker::{
+    data::{
+        categories::CategoryStore,
+        expense_storage::{open_storage, ExpenseStorage},
+        ledger_check,
+        query::{ExpenseQuery, SortKey},
+        rates::{ExchangeRate, RateMatch, RateStore, RATE_DATE_FORMAT},
+    },
     models::{
-        config::Config,
-        expense::Expense,
+        batch::{self, LineResult, STDIN_SCRIPT},
+        category,
+        config::{Config, FilterArgs, Token},
+        error::{ConfigError, ConfigErrorKind, ExpenseError, ExpenseErrorKind, InputError},
+        expense::{Expense, ExpenseUpdate},
+        help::help,
+        money::{parse_currency, Amount, Rate},
+        output::{
+            render_categories, render_expenses, render_line_result, render_rates, render_summary,
+            render_tags,
+        },
+        repl::Repl,
+        settings::Settings,
+        tag::{self, format_tags, parse_tags},
     },
-    utils::io_utils::leer_data,
+    utils::date_utils::parse_date,
 };
+use regex::Regex;
+
+// Codigos de salida del modo de un solo comando
+const EXIT_ERROR: i32 = 1; // el comando fallo (datos, almacenamiento, etc.)
+const EXIT_USAGE: i32 = 2; // el comando o sus argumentos no son validos
 
 fn main() {
-    if let Err(e) = run() {
-        eprintln!("Error: {e}");
-        if let Some(source) = e.source() {
-            eprintln!("Caused by:{source}")
+    match run() {
+        Ok(code) => process::exit(code),
+        Err(e) => {
+            eprintln!("Error: {e}");
+            if let Some(source) = e.source() {
+                eprintln!("Caused by:{source}")
+            }
+            process::exit(exit_code(e.as_ref()));
         }
-        process::exit(1);
     }
 }
 
-fn run() -> Result<(), Box<dyn Error>> {
-    loop {
-        print!("expense-tracker ");
-        io::stdout().flush()?;
-        let input = leer_data()?;
-        let config = match Config::build(&input) {
-            Ok(config) => config,
+// Con un comando en los argumentos se ejecuta una sola vez y se devuelve el
+// codigo de salida; sin comando se abre el modo interactivo
+fn run() -> Result<i32, Box<dyn Error>> {
+    // donde estan los datos y con que backend se abren
+    let args: Vec<String> = env::args().skip(1).collect();
+    let settings = Settings::build(&args)?;
+    let mut storage = open_storage(settings.storage, &settings.data_dir, settings.lock_timeout)?;
+
+    // si una operacion anterior quedo a medias se termina antes de empezar.
+    // Los avisos de arranque van a stderr para no mezclarse con la salida del
+    // comando (por ejemplo `list --format json`)
+    if storage.recover()? {
+        eprintln!("Se completo una operacion pendiente de la ejecucion anterior");
+    }
+
+    // la primera vez se crea una base de datos vacia
+    if storage.init()? {
+        eprintln!("Se creo una base de datos nueva en {}", settings.data_dir);
+    }
+
+    // los datos de versiones anteriores se actualizan al formato actual
+    let report = storage.migrate()?;
+    for migration in &report.applied {
+        eprintln!("Migracion aplicada {}", migration);
+    }
+    if let Some(backup) = &report.backup_path {
+        eprintln!("Copia de seguridad de la version {}: {}", report.from_version, backup);
+    }
+
+    // con la entrada redirigida (`expense-tracker < comandos.txt`) se ejecuta
+    // como un script en vez de abrir el modo interactivo
+    let command = if settings.command.is_empty() && !io::stdin().is_terminal() {
+        vec!["run".to_string(), STDIN_SCRIPT.to_string()]
+    } else {
+        settings.command.clone()
+    };
+
+    if !command.is_empty() {
+        // la shell ya separo las palabras, no se vuelven a interpretar comillas
+        let tokens: Vec<Token> = command.iter().map(|arg| Token::plain(arg)).collect();
+        let result = Config::from_tokens(&tokens)
+            .map_err(Box::from)
+            .and_then(|config| execute(&config, &settings, storage.as_mut()));
+        return Ok(match result {
+            Ok(()) => 0,
             Err(err) => {
-                // Imprimir el error si Config::build falla
                 eprintln!("{}", err);
-                continue; // Continuar con el siguiente ciclo del bucle
+                exit_code(err.as_ref())
             }
+        });
+    }
+
+    let mut repl = Repl::new(&settings.data_dir)?;
+    // el historial se guarda aunque el editor falle, para no perder la sesion
+    let session = loop {
+        let input = match repl.read_command() {
+            Ok(Some(input)) => input,
+            Ok(None) => break Ok(0),
+            Err(err) => break Err(err),
         };
-        match config.comando.as_str() {
-            "add" => {
-                match Expense::build(&config.descripcion, config.amount) {
-                    Ok(_) => {
-                        println!("Expense added successfully")
-                    },
-                    Err(err) => {
-                        eprintln!("{}", err);
-                        continue;
-                    }
-                }
-            },
-            "list" => {
-                match Expense::list() {
-                    Ok(_) => {},
-                    Err(err) => {
-                        eprintln!("{}", err);
-                        continue;
-                    }
-                }
-            },
-            "summary" => {
-                match Expense::summary(&config.descripcion) {
-                    Ok(_) => {},
-                    Err(err) => {
-                        eprintln!("{}", err);
-                        continue;
-                    }
-                }
-            },
-            "delete" => {
-                match Expense::delete(&config.descripcion) {
-                    Ok(_) => {
-                        println!("Expense deleted successfully")
-                    },
-                    Err(err) => {
-                        eprintln!("{}", err);
-                        continue;
-                    }
-                }
+        let result = Config::build(&input)
+            .map_err(Box::from)
+            .and_then(|config| execute(&config, &settings, storage.as_mut()));
+        if let Err(err) = result {
+            eprintln!("{}", err);
+        }
+    };
+    repl.save_history()?;
+    Ok(session?)
+}
+
+// Los errores del comando escrito son de uso; el resto, fallos al ejecutarlo
+fn exit_code(err: &(dyn Error + 'static)) -> i32 {
+    if err.is::<ConfigError>() {
+        EXIT_USAGE
+    } else {
+        EXIT_ERROR
+    }
+}
+
+// Ejecuta un comando ya interpretado, igual en el modo interactivo y en el de
+// un solo comando
+fn execute(
+    config: &Config,
+    settings: &Settings,
+    storage: &mut dyn ExpenseStorage,
+) -> Result<(), Box<dyn Error>> {
+    match config.comando.as_str() {
+        "add" => {
+            // sin --currency el gasto queda en la moneda base
+            let currency = if config.currency.is_empty() {
+                &settings.base_currency
+            } else {
+                &config.currency
+            };
+            // sin --date el gasto es de hoy
+            let today = Local::now().date_naive();
+            let spent_at = match config.date.as_str() {
+                "" => today,
+                date => parse_date(date, today)?,
+            };
+            // la categoria tiene que existir y se guarda con su nombre en la lista
+            let categories = CategoryStore::new(&settings.data_dir);
+            let category = match config.category.as_str() {
+                "" => None,
+                name => Some(category::resolve(&categories, name)?),
+            };
+            Expense::build(
+                storage,
+                &config.descripcion,
+                &config.amount,
+                currency,
+                category.as_deref(),
+                &config.tags,
+                spent_at,
+            )?;
+            println!("Expense added successfully");
+        }
+        "update" => {
+            let expense = update_command(config, settings, storage)?;
+            println!("Expense {} updated successfully", expense.id);
+        }
+        "list" => {
+            let expenses = Expense::list(storage, &expense_query(&config.filters, settings)?)?;
+            print!("{}", render_expenses(&expenses, config.format));
+        }
+        "summary" => {
+            let rates = RateStore::new(&settings.data_dir).load().map_err(|err| {
+                ExpenseError::new(
+                    ExpenseErrorKind::ReadError,
+                    &format!("Error leyendo las tasas de cambio {}", err),
+                    "Expense::summary",
+                )
+            })?;
+            let summary = Expense::summary(
+                storage,
+                &expense_query(&config.filters, settings)?,
+                config.by,
+                &rates,
+                &settings.base_currency,
+            )?;
+            print!("{}", render_summary(&summary, config.format));
+        }
+        "init" => {
+            storage.init()?;
+            println!(
+                "Base de datos lista en {} (version de esquema {})",
+                settings.data_dir,
+                storage.schema_version()?
+            );
+        }
+        "check" => {
+            // los gastos tienen que usar categorias del arbol
+            let categories = CategoryStore::new(&settings.data_dir).load()?;
+            let report = ledger_check::check(storage, &categories)?;
+            println!("{}", report);
+            // con registros invalidos el comando falla, para que un script
+            // pueda detectar el ledger danado
+            if !report.is_ok() {
+                return Err(Box::new(InputError::new(
+                    "El ledger tiene registros invalidos, use repair para corregirlo",
+                )));
+            }
+        }
+        "repair" => {
+            let quarantine_path = Path::new(&settings.data_dir).join("quarantine.json");
+            let categories =
+                CategoryStore::new(&settings.data_dir).with_lock_timeout(settings.lock_timeout);
+            let report =
+                ledger_check::repair(storage, &quarantine_path.to_string_lossy(), &categories)?;
+            println!("{}", report);
+        }
+        "category" => category_command(config, settings, storage)?,
+        "tag" => tag_command(config, storage)?,
+        "rate" => rate_command(config, settings)?,
+        "run" => {
+            let categories =
+                CategoryStore::new(&settings.data_dir).with_lock_timeout(settings.lock_timeout);
+            let rates = RateStore::new(&settings.data_dir).with_lock_timeout(settings.lock_timeout);
+            let report = batch::run_script(
+                batch::open_script(&config.script)?,
+                storage,
+                &categories,
+                &rates,
+                config.atomic,
+                |config, storage| execute(config, settings, storage),
+                // los errores van a stderr, igual que fuera de un script
+                |result| match result {
+                    LineResult::Ok(_) => print!("{}", render_line_result(&result)),
+                    LineResult::Failed(_) => eprint!("{}", render_line_result(&result)),
+                },
+            )?;
+            if !report.is_ok() {
+                return Err(Box::new(InputError::new(&report.to_string())));
+            }
+            println!("{}", report);
+        }
+        "help" => {
+            let topic = (!config.subcomando.is_empty()).then_some(config.subcomando.as_str());
+            print!("{}", help(topic));
+        }
+        "delete" => {
+            let deleted = Expense::delete(storage, &expense_query(&config.filters, settings)?)?;
+            println!("Expenses deleted: {}", deleted);
+        }
+        _ => {
+            return Err(Box::new(ConfigError::new(
+                ConfigErrorKind::InvalidCommand,
+                "Comando invalido",
+            )));
+        }
+    }
+
+    Ok(())
+}
+
+// Arma la consulta de `list`, `summary` y `delete` con los flags de filtro,
+// orden y pagina. La categoria tiene que existir en el arbol.
+fn expense_query(
+    filters: &FilterArgs,
+    settings: &Settings,
+) -> Result<ExpenseQuery, Box<dyn Error>> {
+    let invalid = |message: &str| ConfigError::new(ConfigErrorKind::InvalidTypeData, message);
+    let today = Local::now().date_naive();
+    let mut query = ExpenseQuery::new();
+
+    if !filters.ids.is_empty() {
+        query = query.with_ids(filters.ids.clone());
+    }
+    if !filters.month.is_empty() {
+        query = query.with_month(Expense::parse_month(&filters.month)?);
+    }
+    for tag in parse_tags(&filters.tags)? {
+        query = query.with_tag(&tag);
+    }
+    if !filters.from.is_empty() {
+        query = query.with_from(parse_date(&filters.from, today)?);
+    }
+    if !filters.to.is_empty() {
+        query = query.with_to(parse_date(&filters.to, today)?);
+    }
+    if !filters.min_amount.is_empty() {
+        let amount = Amount::parse(&filters.min_amount).map_err(|err| invalid(err.message()))?;
+        query = query.with_min_amount(amount);
+    }
+    if !filters.max_amount.is_empty() {
+        let amount = Amount::parse(&filters.max_amount).map_err(|err| invalid(err.message()))?;
+        query = query.with_max_amount(amount);
+    }
+    if !filters.search.is_empty() {
+        query = query.with_text(&filters.search);
+    }
+    if !filters.regex.is_empty() {
+        let pattern = Regex::new(&filters.regex)
+            .map_err(|err| invalid(&format!("Expresion regular no valida: {}", err)))?;
+        query = query.with_pattern(pattern);
+    }
+    if !filters.category.is_empty() {
+        let categories = CategoryStore::new(&settings.data_dir);
+        query = query.with_category(&category::resolve(&categories, &filters.category)?);
+    }
+    if !filters.currency.is_empty() {
+        let currency = parse_currency(&filters.currency).map_err(|err| invalid(err.message()))?;
+        query = query.with_currency(&currency);
+    }
+
+    if !filters.sort.is_empty() || filters.desc {
+        let sort = match filters.sort.as_str() {
+            "" => SortKey::default(),
+            name => SortKey::parse(name).ok_or_else(|| {
+                invalid(&format!("Orden no valido: {} (use {})", name, SortKey::NAMES))
+            })?,
+        };
+        query = query.sorted_by(sort, filters.desc);
+    }
+    let count = |flag: &str, value: &str| {
+        value
+            .parse::<usize>()
+            .map_err(|_| invalid(&format!("{} debe ser un numero entero: {}", flag, value)))
+    };
+    if !filters.limit.is_empty() {
+        query = query.with_limit(count("--limit", &filters.limit)?);
+    }
+    if !filters.offset.is_empty() {
+        query = query.with_offset(count("--offset", &filters.offset)?);
+    }
+
+    Ok(query)
+}
+
+// Cambia los campos de un gasto que se pasaron a `update`; la categoria nueva
+// tiene que existir, igual que en `add`
+fn update_command(
+    config: &Config,
+    settings: &Settings,
+    storage: &mut dyn ExpenseStorage,
+) -> Result<Expense, Box<dyn Error>> {
+    // los campos vacios son los que no se pasaron; un flag con el valor vacio
+    // ya lo rechaza `Config`
+    let given = |value: &String| (!value.is_empty()).then(|| value.clone());
+
+    let category = if config.no_category {
+        Some(None)
+    } else if config.category.is_empty() {
+        None
+    } else {
+        let categories = CategoryStore::new(&settings.data_dir);
+        Some(Some(category::resolve(&categories, &config.category)?))
+    };
+    let tags = if config.no_tags {
+        Some(Vec::new())
+    } else {
+        (!config.tags.is_empty()).then(|| config.tags.clone())
+    };
+    let spent_at = match config.date.as_str() {
+        "" => None,
+        date => Some(parse_date(date, Local::now().date_naive())?),
+    };
+
+    let changes = ExpenseUpdate {
+        description: given(&config.descripcion),
+        amount: given(&config.amount),
+        currency: given(&config.currency),
+        category,
+        tags,
+        spent_at,
+    };
+    Ok(Expense::update(storage, &config.id, changes)?)
+}
+
+// Subcomandos de `category`: add, rename, merge, delete y list
+fn category_command(
+    config: &Config,
+    settings: &Settings,
+    storage: &mut dyn ExpenseStorage,
+) -> Result<(), Box<dyn Error>> {
+    let store = CategoryStore::new(&settings.data_dir).with_lock_timeout(settings.lock_timeout);
+    let name = &config.category;
+    let target = &config.category_target;
+
+    match config.subcomando.as_str() {
+        "add" => {
+            let name = category::add(&store, name)?;
+            println!("Categoria agregada: {}", name);
+        }
+        "rename" => {
+            let (old, new, changed) = category::rename(&store, storage, name, target)?;
+            println!("Categoria {} renombrada a {} ({} gastos)", old, new, changed);
+        }
+        "merge" => {
+            let (old, into, changed) = category::merge(&store, storage, name, target)?;
+            println!("Categoria {} combinada con {} ({} gastos)", old, into, changed);
+        }
+        "delete" => {
+            let (old, changed) = category::delete(&store, storage, name)?;
+            println!("Categoria {} eliminada ({} gastos quedan sin categoria)", old, changed);
+        }
+        _ => print!("{}", render_categories(&category::list(&store, storage)?)),
+    }
+
+    Ok(())
+}
+
+// Subcomandos de `tag`: add, remove y list
+fn tag_command(config: &Config, storage: &mut dyn ExpenseStorage) -> Result<(), Box<dyn Error>> {
+    match config.subcomando.as_str() {
+        "add" => {
+            let tags = tag::add(storage, &config.id, &config.tags)?;
+            println!("Etiquetas del gasto {}: {}", config.id, format_tags(&tags));
+        }
+        "remove" => {
+            let tags = tag::remove(storage, &config.id, &config.tags)?;
+            println!("Etiquetas del gasto {}: {}", config.id, format_tags(&tags));
+        }
+        _ => print!("{}", render_tags(&tag::list(storage)?)),
+    }
+
+    Ok(())
+}
+
+// Subcomandos de `rate`: set, get, import y list
+fn rate_command(config: &Config, settings: &Settings) -> Result<(), Box<dyn Error>> {
+    let store = RateStore::new(&settings.data_dir).with_lock_timeout(settings.lock_timeout);
+    let args = &config.rate;
+
+    // sin --to la tasa es hacia la moneda base, y sin --date es de hoy
+    let to = if args.to.is_empty() {
+        &settings.base_currency
+    } else {
+        &args.to
+    };
+    let date = if args.date.is_empty() {
+        Local::now().date_naive()
+    } else {
+        NaiveDate::parse_from_str(&args.date, RATE_DATE_FORMAT).map_err(|err| {
+            ConfigError::new(
+                ConfigErrorKind::InvalidTypeData,
+                &format!("Fecha no valida {} (use AAAA-MM-DD): {}", args.date, err),
+            )
+        })?
+    };
+
+    match config.subcomando.as_str() {
+        "set" => {
+            let rate = ExchangeRate::new(date, &args.from, to, Rate::parse(&args.rate)?)?;
+            match store.set(rate.clone())? {
+                Some(previous) => println!("Tasa actualizada: {} (antes {})", rate, previous.rate),
+                None => println!("Tasa agregada: {}", rate),
             }
-            _ => {
-                eprintln!("Error: Comando invalido");
-                continue;
+        }
+        "get" => {
+            let from = parse_currency(&args.from)?;
+            let to = parse_currency(to)?;
+            match store.load()?.find(&from, &to, date) {
+                Some(RateMatch::Direct(rate)) => println!("{}", rate),
+                Some(RateMatch::Inverse(rate)) => {
+                    println!("{} (no hay tasa de {} a {}, se usa la inversa)", rate, from, to)
+                }
+                None => println!("No hay una tasa de {} a {} para el {} ni antes", from, to, date),
             }
         }
+        "import" => {
+            let import = store.import_csv(&args.file)?;
+            println!(
+                "Se agregaron {} tasas y se actualizaron {}",
+                import.added, import.updated
+            );
+        }
+        _ => print!("{}", render_rates(store.load()?.rates())),
     }
+
+    Ok(())
 }
@@ -0,0 +1,336 @@
+use std::{
+    error::Error,
+    fmt,
+    fs::File,
+    io::{self, BufRead, BufReader},
+};
+
+use super::{
+    config::Config,
+    error::{ConfigError, ConfigErrorKind, InputError},
+};
+use crate::data::{categories::CategoryStore, expense_storage::ExpenseStorage, rates::RateStore};
+
+// Nombre de archivo con el que `run` lee los comandos de la entrada estandar
+pub const STDIN_SCRIPT: &str = "-";
+
+// Un comando del script que fallo
+#[derive(Debug, Clone)]
+pub struct LineError {
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for LineError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "linea {}: {}", self.line, self.message)
+    }
+}
+
+// Resultado de cada comando del script, a medida que se ejecuta
+#[derive(Debug)]
+pub enum LineResult<'a> {
+    Ok(usize),
+    Failed(&'a LineError),
+}
+
+// Resultado de `run`
+#[derive(Debug, Default)]
+pub struct ScriptReport {
+    // comandos que se intentaron ejecutar, sin contar lineas vacias ni comentarios
+    pub executed: usize,
+    pub failed: Vec<LineError>,
+    // con --atomic el primer error deshace todo lo que hizo el script
+    pub rolled_back: bool,
+}
+
+impl ScriptReport {
+    pub fn is_ok(&self) -> bool {
+        self.failed.is_empty()
+    }
+}
+
+impl fmt::Display for ScriptReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.is_ok() {
+            return write!(f, "Script terminado: {} comandos sin errores", self.executed);
+        }
+
+        if self.rolled_back {
+            write!(f, "Script detenido, no se guardo ningun cambio:")?;
+        } else {
+            write!(
+                f,
+                "Script terminado: {} comandos, {} con errores:",
+                self.executed,
+                self.failed.len()
+            )?;
+        }
+        for error in &self.failed {
+            write!(f, "\n  - {}", error)?;
+        }
+        Ok(())
+    }
+}
+
+// Abre el archivo de comandos, o la entrada estandar si es `-`
+pub fn open_script(path: &str) -> Result<Box<dyn BufRead>, InputError> {
+    if path == STDIN_SCRIPT {
+        return Ok(Box::new(io::stdin().lock()));
+    }
+
+    let file = File::open(path).map_err(|err| {
+        InputError::with_cause(
+            &format!("No se pudo abrir el archivo de comandos {}", path),
+            Box::new(err),
+        )
+    })?;
+    Ok(Box::new(BufReader::new(file)))
+}
+
+// Ejecuta un comando por linea con `execute` y pasa el resultado de cada una
+// a `on_line`. Las lineas vacias y las que empiezan con `#` se saltan.
+//
+// Sin `atomic` cada comando se guarda por su cuenta y un error no detiene el
+// script. Con `atomic` todo el script corre dentro de una transaccion: el
+// primer error la deshace, junto con los cambios a las categorias y las tasas.
+pub fn run_script(
+    input: impl BufRead,
+    storage: &mut dyn ExpenseStorage,
+    categories: &CategoryStore,
+    rates: &RateStore,
+    atomic: bool,
+    mut execute: impl FnMut(&Config, &mut dyn ExpenseStorage) -> Result<(), Box<dyn Error>>,
+    mut on_line: impl FnMut(LineResult),
+) -> Result<ScriptReport, Box<dyn Error>> {
+    if !atomic {
+        return run_lines(input, storage, false, &mut execute, &mut on_line);
+    }
+
+    // las categorias y las tasas viven fuera de la transaccion de los gastos
+    let saved_categories = categories.load()?;
+    let saved_rates = rates.load()?;
+    storage.begin()?;
+
+    let result = run_lines(input, storage, true, &mut execute, &mut on_line);
+    if let Ok(report) = &result {
+        if report.is_ok() {
+            storage.commit()?;
+            return result;
+        }
+    }
+
+    storage.rollback()?;
+    if categories.load()?.names() != saved_categories.names() {
+        categories.modify(|list| {
+            *list = saved_categories;
+            Ok(())
+        })?;
+    }
+    if rates.load()?.rates() != saved_rates.rates() {
+        rates.modify(|table| {
+            *table = saved_rates;
+            Ok(())
+        })?;
+    }
+    result.map(|report| ScriptReport {
+        rolled_back: true,
+        ..report
+    })
+}
+
+fn run_lines(
+    input: impl BufRead,
+    storage: &mut dyn ExpenseStorage,
+    stop_on_error: bool,
+    execute: &mut impl FnMut(&Config, &mut dyn ExpenseStorage) -> Result<(), Box<dyn Error>>,
+    on_line: &mut impl FnMut(LineResult),
+) -> Result<ScriptReport, Box<dyn Error>> {
+    let mut report = ScriptReport::default();
+
+    for (index, line) in input.lines().enumerate() {
+        let number = index + 1;
+        let line = line.map_err(|err| {
+            InputError::with_cause(&format!("No se pudo leer la linea {}", number), Box::new(err))
+        })?;
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        report.executed += 1;
+        let result = Config::build(line).map_err(Box::from).and_then(|config| {
+            if config.comando == "run" {
+                return Err(Box::new(ConfigError::new(
+                    ConfigErrorKind::InvalidCommand,
+                    "run no se puede usar dentro de un script",
+                )) as Box<dyn Error>);
+            }
+            execute(&config, storage)
+        });
+
+        match result {
+            Ok(()) => on_line(LineResult::Ok(number)),
+            Err(err) => {
+                let error = LineError {
+                    line: number,
+                    message: err.to_string(),
+                };
+                on_line(LineResult::Failed(&error));
+                report.failed.push(error);
+                if stop_on_error {
+                    break;
+                }
+            }
+        }
+    }
+
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::{json_storage::JsonExpenseStorage, rates::ExchangeRate};
+    use crate::models::{category, expense::Expense, money::Rate};
+    use crate::utils::test_utils::TempDir;
+    use chrono::NaiveDate;
+
+    // Ejecuta `add` (creando la categoria) y `rate set`; cualquier otro
+    // comando falla
+    fn run(
+        dir: &TempDir,
+        storage: &mut JsonExpenseStorage,
+        script: &str,
+        atomic: bool,
+    ) -> ScriptReport {
+        let categories = CategoryStore::new(dir.path());
+        let rates = RateStore::new(dir.path());
+        let date = NaiveDate::from_ymd_opt(2024, 3, 10).unwrap();
+        let execute =
+            |config: &Config, storage: &mut dyn ExpenseStorage| -> Result<(), Box<dyn Error>> {
+                match config.comando.as_str() {
+                    "add" => {
+                        let category = category::add(&categories, &config.category)?;
+                        Expense::build(
+                            storage,
+                            &config.descripcion,
+                            &config.amount,
+                            "COP",
+                            Some(&category),
+                            &config.tags,
+                            date,
+                        )?;
+                    }
+                    "rate" => {
+                        let rate = Rate::parse(&config.rate.rate)?;
+                        rates.set(ExchangeRate::new(
+                            date,
+                            &config.rate.from,
+                            &config.rate.to,
+                            rate,
+                        )?)?;
+                    }
+                    _ => {
+                        return Err(Box::from(format!(
+                            "comando no soportado: {}",
+                            config.comando
+                        )))
+                    }
+                }
+                Ok(())
+            };
+        run_script(
+            script.as_bytes(),
+            storage,
+            &categories,
+            &rates,
+            atomic,
+            execute,
+            |_| {},
+        )
+        .unwrap()
+    }
+
+    fn state(dir: &TempDir, storage: &JsonExpenseStorage) -> (Vec<String>, Vec<String>, usize) {
+        let descriptions = storage
+            .load()
+            .unwrap()
+            .iter()
+            .map(|expense| expense.description.clone())
+            .collect();
+        let categories = CategoryStore::new(dir.path())
+            .load()
+            .unwrap()
+            .names()
+            .to_vec();
+        let rates = RateStore::new(dir.path()).load().unwrap().rates().len();
+        (descriptions, categories, rates)
+    }
+
+    fn storage(dir: &TempDir) -> JsonExpenseStorage {
+        let mut storage = JsonExpenseStorage::new(dir.path());
+        storage.init().unwrap();
+        storage
+    }
+
+    const SETUP: &str = "add --description Cafe --amount 2700 --category Comida\n\
+                         rate set --from USD --to COP --rate 4000";
+
+    const FAILING: &str = "add --description Bus --amount 3000 --category Transporte\n\
+                           rate set --from EUR --to COP --rate 4300\n\
+                           # comentario\n\
+                           \n\
+                           delete --id 1\n\
+                           add --description Taxi --amount 9000 --category Viajes";
+
+    #[test]
+    fn atomic_script_restores_expenses_categories_and_rates() {
+        let dir = TempDir::new("batch_atomic");
+        let mut storage = storage(&dir);
+        assert!(run(&dir, &mut storage, SETUP, true).is_ok());
+        let before = state(&dir, &storage);
+        assert_eq!(before.0, vec!["Cafe"]);
+
+        let report = run(&dir, &mut storage, FAILING, true);
+        assert!(report.rolled_back);
+        assert_eq!(report.executed, 3);
+        assert_eq!(report.failed.len(), 1);
+        assert_eq!(report.failed[0].line, 5);
+        assert_eq!(state(&dir, &storage), before);
+        assert_eq!(JsonExpenseStorage::new(dir.path()).load().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn script_without_atomic_keeps_the_lines_that_worked() {
+        let dir = TempDir::new("batch_partial");
+        let mut storage = storage(&dir);
+
+        let report = run(&dir, &mut storage, FAILING, false);
+        assert!(!report.rolled_back);
+        assert_eq!(report.executed, 4);
+        assert_eq!(report.failed.len(), 1);
+        let (descriptions, categories, rates) = state(&dir, &storage);
+        assert_eq!(descriptions, vec!["Bus", "Taxi"]);
+        assert!(categories.contains(&"Transporte".to_string()));
+        assert_eq!(rates, 1);
+    }
+
+    #[test]
+    fn nested_run_is_rejected() {
+        let dir = TempDir::new("batch_nested");
+        let mut storage = storage(&dir);
+
+        let report = run(&dir, &mut storage, "run otro.txt\nrun - --atomic", false);
+        assert_eq!(report.executed, 2);
+        assert_eq!(report.failed.len(), 2);
+        assert!(report.failed[0]
+            .message
+            .contains("run no se puede usar dentro de un script"));
+        assert!(storage.load().unwrap().is_empty());
+
+        let report = run(&dir, &mut storage, "run otro.txt", true);
+        assert!(report.rolled_back);
+        assert_eq!(report.failed[0].line, 1);
+    }
+}
@@ -0,0 +1,331 @@
+use chrono::Utc;
+
+use super::{
+    error::{ExpenseError, ExpenseErrorKind, StorageError, StorageErrorKind},
+    expense::Expense,
+};
+use crate::data::{
+    categories::{category_levels, CategoryStore, CATEGORY_SEPARATOR},
+    expense_storage::ExpenseStorage,
+};
+
+// Operaciones sobre la lista de categorias. Las que cambian el nombre de una
+// categoria o la quitan tambien actualizan los gastos que la usan.
+
+// Resultado de `list` para una categoria
+#[derive(Debug, Clone)]
+pub struct CategoryCount {
+    // ruta completa: "Transporte > Gasolina"
+    pub name: String,
+    pub expenses: usize,
+    pub with_subcategories: usize,
+}
+
+fn category_error(err: StorageError, location: &str) -> ExpenseError {
+    let kind = match err.kind() {
+        StorageErrorKind::NotFound | StorageErrorKind::AlreadyExists => {
+            ExpenseErrorKind::InvalidCategory
+        }
+        _ => ExpenseErrorKind::WriteError,
+    };
+    ExpenseError::new(kind, err.message(), location)
+}
+
+// Nombre guardado de la categoria `name`. Falla si la categoria no existe.
+pub fn resolve(store: &CategoryStore, name: &str) -> Result<String, ExpenseError> {
+    let location = "category::resolve";
+    let categories = store.load().map_err(|err| category_error(err, location))?;
+    categories.find(name).map(String::from).ok_or(ExpenseError::new(
+        ExpenseErrorKind::InvalidCategory,
+        &format!(
+            "No existe la categoria {}, creela con: category add --name {}",
+            name, name
+        ),
+        location,
+    ))
+}
+
+pub fn add(store: &CategoryStore, name: &str) -> Result<String, ExpenseError> {
+    store
+        .modify(|categories| categories.add(name))
+        .map_err(|err| category_error(err, "category::add"))
+}
+
+// Cambia el nombre de la categoria, con sus subcategorias, y de los gastos que
+// las usan. Devuelve el nombre anterior, el nuevo y cuantos gastos cambiaron.
+pub fn rename(
+    store: &CategoryStore,
+    storage: &mut dyn ExpenseStorage,
+    name: &str,
+    new_name: &str,
+) -> Result<(String, String, usize), ExpenseError> {
+    store
+        .modify(|categories| {
+            let renamed = categories.rename(name, new_name)?;
+            let changed = relabel(storage, &renames(&renamed))?;
+            let (old, new) = renamed.into_iter().next().unwrap_or_default();
+            Ok((old, new, changed))
+        })
+        .map_err(|err| category_error(err, "category::rename"))
+}
+
+// Pasa los gastos de la categoria `name` a `into` y quita `name` de la lista.
+// Las subcategorias de `name` pasan a estar debajo de `into`.
+pub fn merge(
+    store: &CategoryStore,
+    storage: &mut dyn ExpenseStorage,
+    name: &str,
+    into: &str,
+) -> Result<(String, String, usize), ExpenseError> {
+    store
+        .modify(|categories| {
+            let merged = categories.merge(name, into)?;
+            let changed = relabel(storage, &renames(&merged))?;
+            let (old, into) = merged.into_iter().next().unwrap_or_default();
+            Ok((old, into, changed))
+        })
+        .map_err(|err| category_error(err, "category::merge"))
+}
+
+// Quita la categoria con sus subcategorias; los gastos que las usaban quedan
+// sin categoria
+pub fn delete(
+    store: &CategoryStore,
+    storage: &mut dyn ExpenseStorage,
+    name: &str,
+) -> Result<(String, usize), ExpenseError> {
+    store
+        .modify(|categories| {
+            let removed = categories.remove(name)?;
+            let changes: Vec<(String, Option<String>)> =
+                removed.iter().map(|old| (old.clone(), None)).collect();
+            let changed = relabel(storage, &changes)?;
+            Ok((removed[0].clone(), changed))
+        })
+        .map_err(|err| category_error(err, "category::delete"))
+}
+
+// Cada categoria del arbol, en orden, con la cantidad de gastos que la usan,
+// sola y contando sus subcategorias
+pub fn list(
+    store: &CategoryStore,
+    storage: &dyn ExpenseStorage,
+) -> Result<Vec<CategoryCount>, ExpenseError> {
+    let location = "category::list";
+    let categories = store.load().map_err(|err| category_error(err, location))?;
+    let expenses = storage.load().map_err(|err| {
+        ExpenseError::new(
+            ExpenseErrorKind::ReadError,
+            &format!("Error leyendo los gastos {}", err),
+            location,
+        )
+    })?;
+
+    Ok(categories
+        .names()
+        .iter()
+        .map(|name| {
+            let subtree = categories.subtree(name);
+            CategoryCount {
+                name: name.clone(),
+                expenses: expenses
+                    .iter()
+                    .filter(|expense| same_category(expense, name))
+                    .count(),
+                with_subcategories: expenses
+                    .iter()
+                    .filter(|expense| subtree.iter().any(|name| same_category(expense, name)))
+                    .count(),
+            }
+        })
+        .collect())
+}
+
+fn same_category(expense: &Expense, name: &str) -> bool {
+    expense.category.as_ref().is_some_and(|category| {
+        category_levels(category).join(CATEGORY_SEPARATOR).to_lowercase() == name.to_lowercase()
+    })
+}
+
+// Cambia cada categoria anterior por la nueva (o la quita) en todos los gastos,
+// en una transaccion. Devuelve cuantos gastos cambiaron.
+fn relabel(
+    storage: &mut dyn ExpenseStorage,
+    changes: &[(String, Option<String>)],
+) -> Result<usize, StorageError> {
+    storage.begin()?;
+    let changed = storage.load().and_then(|expenses| {
+        let mut changed = 0;
+        for mut expense in expenses {
+            let change = changes.iter().find(|(old, _)| same_category(&expense, old));
+            if let Some((_, new)) = change {
+                expense.category = new.clone();
+                expense.updated_at = Some(Utc::now());
+                storage.update(&expense)?;
+                changed += 1;
+            }
+        }
+        Ok(changed)
+    });
+
+    match changed {
+        Ok(changed) => {
+            storage.commit()?;
+            Ok(changed)
+        }
+        Err(err) => {
+            let _ = storage.rollback();
+            Err(err)
+        }
+    }
+}
+
+// Los cambios de nombre de `rename` y `merge` en la forma que usa `relabel`
+fn renames(changes: &[(String, String)]) -> Vec<(String, Option<String>)> {
+    changes
+        .iter()
+        .map(|(old, new)| (old.clone(), Some(new.clone())))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::memory_storage::MemoryExpenseStorage;
+    use crate::utils::test_utils::TempDir;
+    use chrono::NaiveDate;
+
+    fn store(dir: &TempDir, names: &[&str]) -> CategoryStore {
+        let store = CategoryStore::new(dir.path());
+        for name in names {
+            add(&store, name).unwrap();
+        }
+        store
+    }
+
+    fn storage(categories: &[Option<&str>]) -> MemoryExpenseStorage {
+        let mut storage = MemoryExpenseStorage::new();
+        for (index, category) in categories.iter().enumerate() {
+            Expense::build(
+                &mut storage,
+                &format!("Gasto {}", index + 1),
+                "1000",
+                "COP",
+                *category,
+                &[],
+                NaiveDate::from_ymd_opt(2024, 3, 10).unwrap(),
+            )
+            .unwrap();
+        }
+        storage
+    }
+
+    fn categories(storage: &MemoryExpenseStorage) -> Vec<Option<String>> {
+        storage
+            .load()
+            .unwrap()
+            .into_iter()
+            .map(|expense| expense.category)
+            .collect()
+    }
+
+    fn names(values: &[Option<&str>]) -> Vec<Option<String>> {
+        values.iter().map(|name| name.map(String::from)).collect()
+    }
+
+    #[test]
+    fn rename_of_a_parent_relabels_the_expenses_of_its_subtree() {
+        let dir = TempDir::new("category_rename");
+        let store = store(&dir, &["Auto > Gasolina", "Autos"]);
+        let mut storage = storage(&[Some("Auto"), Some("Auto > Gasolina"), Some("Autos"), None]);
+
+        let result = rename(&store, &mut storage, "auto", "Transporte").unwrap();
+        assert_eq!(result, ("Auto".to_string(), "Transporte".to_string(), 2));
+        assert_eq!(
+            categories(&storage),
+            names(&[
+                Some("Transporte"),
+                Some("Transporte > Gasolina"),
+                Some("Autos"),
+                None,
+            ])
+        );
+        let expenses = storage.load().unwrap();
+        assert!(expenses[1].updated_at.is_some());
+        assert!(expenses[2].updated_at.is_none());
+        assert_eq!(
+            store.load().unwrap().names(),
+            ["Autos", "Transporte", "Transporte > Gasolina"]
+        );
+    }
+
+    #[test]
+    fn merge_relabels_the_expenses_and_removes_the_category() {
+        let dir = TempDir::new("category_merge");
+        let store = store(&dir, &["Auto > Gasolina", "Transporte > Gasolina"]);
+        let mut storage = storage(&[
+            Some("Auto"),
+            Some("Auto > Gasolina"),
+            Some("Transporte > Gasolina"),
+        ]);
+
+        let result = merge(&store, &mut storage, "Auto", "Transporte").unwrap();
+        assert_eq!(result, ("Auto".to_string(), "Transporte".to_string(), 2));
+        assert_eq!(
+            categories(&storage),
+            names(&[
+                Some("Transporte"),
+                Some("Transporte > Gasolina"),
+                Some("Transporte > Gasolina"),
+            ])
+        );
+        assert!(store.load().unwrap().find("Auto").is_none());
+    }
+
+    #[test]
+    fn delete_leaves_the_expenses_of_the_subtree_without_category() {
+        let dir = TempDir::new("category_delete");
+        let store = store(&dir, &["Auto > Gasolina", "Casa"]);
+        let mut storage = storage(&[Some("Auto > Gasolina"), Some("Casa"), Some("Auto")]);
+
+        let result = delete(&store, &mut storage, "auto").unwrap();
+        assert_eq!(result, ("Auto".to_string(), 2));
+        assert_eq!(categories(&storage), names(&[None, Some("Casa"), None]));
+        assert_eq!(store.load().unwrap().names(), ["Casa"]);
+    }
+
+    #[test]
+    fn failed_rename_changes_nothing() {
+        let dir = TempDir::new("category_rename_fails");
+        let store = store(&dir, &["Auto > Gasolina", "Transporte > Gasolina"]);
+        let mut storage = storage(&[Some("Auto > Gasolina")]);
+
+        let err = rename(&store, &mut storage, "Auto", "Transporte").unwrap_err();
+        assert!(matches!(err.kind(), ExpenseErrorKind::InvalidCategory));
+        assert_eq!(categories(&storage), names(&[Some("Auto > Gasolina")]));
+        assert_eq!(store.load().unwrap().names().len(), 4);
+    }
+
+    #[test]
+    fn relabel_matches_categories_ignoring_case_and_spacing() {
+        // gastos guardados con otra forma del nombre, como los de versiones
+        // anteriores
+        let mut storage = storage(&[None, None, None]);
+        let mut expenses = storage.load().unwrap();
+        for (expense, category) in expenses.iter_mut().zip(["auto>gasolina", "AUTO", "Autos"]) {
+            expense.category = Some(category.to_string());
+        }
+        storage.replace_all(&expenses, 4).unwrap();
+
+        let changes = vec![
+            ("Auto".to_string(), Some("Transporte".to_string())),
+            ("Auto > Gasolina".to_string(), None),
+        ];
+        assert_eq!(relabel(&mut storage, &changes).unwrap(), 2);
+        assert_eq!(
+            categories(&storage),
+            names(&[None, Some("Transporte"), Some("Autos")])
+        );
+        assert_eq!(relabel(&mut storage, &changes).unwrap(), 0);
+    }
+}
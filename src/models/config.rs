@@ -1,100 +1,902 @@
-use super::error::{ConfigError, ConfigErrorKind};
+use std::collections::HashMap;
+
+use super::{
+    error::{ConfigError, ConfigErrorKind},
+    expense::GroupBy,
+    output::OutputFormat,
+};
 
 #[derive(Debug)]
 pub struct Config {
     pub comando: String,
+    // subcomando de `category`, `tag` o `rate`, o el comando del que se pide
+    // ayuda con `help`
+    pub subcomando: String,
+    // descripcion del gasto de `add` y `update`
     pub descripcion: String,
-    pub amount: f64,
+    // monto tal como se escribio; se valida al crear el gasto
+    pub amount: String,
+    // moneda del gasto; vacia si se usa la moneda base
+    pub currency: String,
+    // categoria del gasto, o la categoria sobre la que actua `category`
+    pub category: String,
+    // nuevo nombre (rename) o categoria destino (merge) de `category`
+    pub category_target: String,
+    // desglose de `summary`
+    pub by: Option<GroupBy>,
+    // como se muestran los resultados de `list` y `summary`
+    pub format: OutputFormat,
+    // id del gasto sobre el que actuan `tag` y `update`
+    pub id: String,
+    // etiquetas de `add`, `update` y `tag`, tal como se escribieron
+    pub tags: Vec<String>,
+    // fecha del gasto de `add` y `update`, tal como se escribio
+    pub date: String,
+    // `update` quita la categoria o las etiquetas del gasto
+    pub no_category: bool,
+    pub no_tags: bool,
+    pub rate: RateArgs,
+    pub filters: FilterArgs,
+    // archivo de comandos de `run`; `-` es la entrada estandar
+    pub script: String,
+    // `run` deshace todo el script si un comando falla
+    pub atomic: bool,
+}
+
+// Flags del comando `rate`; vacios si no se pasaron
+#[derive(Debug, Default)]
+pub struct RateArgs {
+    pub from: String,
+    pub to: String,
+    pub rate: String,
+    pub date: String,
+    pub file: String,
+}
+
+// Filtros de `list`, `summary` y `delete`, tal como se escribieron; vacios si
+// no se pasaron. Se validan al armar la consulta.
+#[derive(Debug, Default)]
+pub struct FilterArgs {
+    pub ids: Vec<String>,
+    pub month: String,
+    pub tags: Vec<String>,
+    pub from: String,
+    pub to: String,
+    pub min_amount: String,
+    pub max_amount: String,
+    pub search: String,
+    pub regex: String,
+    pub category: String,
+    pub currency: String,
+    // orden y pagina, solo en `list`
+    pub sort: String,
+    pub desc: bool,
+    pub limit: String,
+    pub offset: String,
+}
+
+impl FilterArgs {
+    fn from_flags(flags: &Flags) -> FilterArgs {
+        FilterArgs {
+            ids: flags.list("--id"),
+            month: flags.get("--month"),
+            tags: flags.list("--tag"),
+            from: flags.get("--from"),
+            to: flags.get("--to"),
+            min_amount: flags.get("--min-amount"),
+            max_amount: flags.get("--max-amount"),
+            search: flags.get("--search"),
+            regex: flags.get("--regex"),
+            category: flags.get("--category"),
+            currency: flags.get("--currency"),
+            sort: flags.get("--sort"),
+            desc: flags.has("--desc"),
+            limit: flags.get("--limit"),
+            offset: flags.get("--offset"),
+        }
+    }
+}
+
+// Una palabra del comando. Las que empiezan entre comillas son siempre un
+// valor, aunque empiecen con `--`: `--description "--amount no es un flag"`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Token {
+    pub text: String,
+    pub quoted: bool,
+}
+
+impl Token {
+    // Palabra que ya viene separada, por ejemplo un argumento del proceso
+    pub fn plain(text: &str) -> Token {
+        Token {
+            text: text.to_string(),
+            quoted: false,
+        }
+    }
+
+    fn is_flag(&self) -> bool {
+        !self.quoted && self.text.starts_with("--")
+    }
+}
+
+// Separa una linea en palabras como lo haria una shell: los espacios separan
+// palabras salvo entre comillas simples (todo es literal) o dobles (donde `\"`
+// y `\\` se escapan), y fuera de comillas `\` escapa el caracter siguiente.
+pub fn tokenize(input: &str) -> Result<Vec<Token>, ConfigError> {
+    let syntax_error = |message: &str| ConfigError::new(ConfigErrorKind::InvalidSyntax, message);
+    let mut tokens = Vec::new();
+    let mut current: Option<Token> = None;
+    let mut chars = input.chars();
+
+    while let Some(c) = chars.next() {
+        if c.is_whitespace() {
+            tokens.extend(current.take());
+            continue;
+        }
+
+        // una palabra que empieza entre comillas o escapada nunca es un flag
+        let token = current.get_or_insert_with(|| Token {
+            text: String::new(),
+            quoted: matches!(c, '\'' | '"' | '\\'),
+        });
+        match c {
+            '\'' => loop {
+                match chars.next() {
+                    Some('\'') => break,
+                    Some(c) => token.text.push(c),
+                    None => return Err(syntax_error("Falta cerrar una comilla simple")),
+                }
+            },
+            '"' => loop {
+                match chars.next() {
+                    Some('"') => break,
+                    Some('\\') => match chars.next() {
+                        Some(c @ ('"' | '\\')) => token.text.push(c),
+                        Some(c) => {
+                            token.text.push('\\');
+                            token.text.push(c);
+                        }
+                        None => return Err(syntax_error("Falta cerrar una comilla doble")),
+                    },
+                    Some(c) => token.text.push(c),
+                    None => return Err(syntax_error("Falta cerrar una comilla doble")),
+                }
+            },
+            '\\' => match chars.next() {
+                Some(c) => token.text.push(c),
+                None => return Err(syntax_error("La linea termina con \\ sin nada que escapar")),
+            },
+            c => token.text.push(c),
+        }
+    }
+
+    tokens.extend(current);
+    Ok(tokens)
+}
+
+// Como se leen los valores de un flag
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum FlagKind {
+    // un solo valor: `--amount 2700`
+    Value,
+    // varias palabras que forman un texto: `--description Almuerzo con amigos`
+    Words,
+    // varios valores; el flag se puede repetir: `--tags trabajo viaje`
+    List,
+    // sin valor: `--no-tags`
+    Switch,
+}
+
+use FlagKind::{List, Switch, Value, Words};
+
+// Comandos que se pueden escribir, en el orden en que los muestra la ayuda
+pub const COMMANDS: &[&str] = &[
+    "add", "update", "list", "summary", "delete", "category", "tag", "rate", "init", "check",
+    "repair", "run", "help",
+];
+
+// Filtros que comparten `list`, `summary` y `delete`
+const FILTER_FLAGS: &[(&str, FlagKind)] = &[
+    ("--month", Value),
+    ("--from", Value),
+    ("--to", Value),
+    ("--min-amount", Value),
+    ("--max-amount", Value),
+    ("--search", Words),
+    ("--regex", Value),
+    ("--category", Words),
+    ("--currency", Value),
+    ("--tag", List),
+];
+
+// Flags que admite cada comando. Los usan el parser y el autocompletado del
+// modo interactivo, asi que un flag nuevo se agrega solo aca.
+fn command_flags(command: &str) -> Vec<(&'static str, FlagKind)> {
+    let filters = match command {
+        "list" | "summary" | "delete" => FILTER_FLAGS,
+        _ => &[],
+    };
+    let own: &[(&str, FlagKind)] = match command {
+        "add" => &[
+            ("--description", Words),
+            ("--amount", Value),
+            ("--currency", Value),
+            ("--category", Words),
+            ("--tags", List),
+            ("--date", Value),
+        ],
+        "update" => &[
+            ("--id", Value),
+            ("--description", Words),
+            ("--amount", Value),
+            ("--currency", Value),
+            ("--category", Words),
+            ("--no-category", Switch),
+            ("--tags", List),
+            ("--no-tags", Switch),
+            ("--date", Value),
+        ],
+        "list" => &[
+            ("--sort", Value),
+            ("--desc", Switch),
+            ("--limit", Value),
+            ("--offset", Value),
+            ("--format", Value),
+        ],
+        "summary" => &[("--by", Value), ("--depth", Value), ("--format", Value)],
+        // se pueden borrar varios gastos: --id 1 2 3
+        "delete" => &[("--id", List)],
+        // los nombres pueden tener varias palabras
+        "category" => &[("--name", Words), ("--to", Words), ("--into", Words)],
+        "tag" => &[("--id", Value), ("--tags", List)],
+        "rate" => &[
+            ("--from", Value),
+            ("--to", Value),
+            ("--rate", Value),
+            ("--date", Value),
+            ("--file", Value),
+        ],
+        "run" => &[("--atomic", Switch)],
+        _ => &[],
+    };
+    own.iter().chain(filters).copied().collect()
+}
+
+// Nombres de los flags de un comando
+pub fn flag_names(command: &str) -> Vec<&'static str> {
+    command_flags(command).iter().map(|(name, _)| *name).collect()
+}
+
+// Subcomandos de `category`, `tag` y `rate`; vacio para el resto
+pub fn subcommands(command: &str) -> &'static [&'static str] {
+    match command {
+        "category" => &["add", "rename", "merge", "delete", "list"],
+        "tag" => &["add", "remove", "list"],
+        "rate" => &["set", "get", "import", "list"],
+        _ => &[],
+    }
+}
+
+// Flags de un comando con sus valores
+#[derive(Debug, Default)]
+struct Flags {
+    values: HashMap<&'static str, Vec<String>>,
+}
+
+impl Flags {
+    // Valor del flag; las palabras de un texto se unen con un espacio
+    fn get(&self, name: &str) -> String {
+        self.values
+            .get(name)
+            .map(|values| values.join(" "))
+            .unwrap_or_default()
+    }
+
+    fn list(&self, name: &str) -> Vec<String> {
+        self.values.get(name).cloned().unwrap_or_default()
+    }
+
+    fn has(&self, name: &str) -> bool {
+        self.values.contains_key(name)
+    }
+
+    // Falla con `MissingArguments` si falta alguno de los flags `required`
+    fn require(&self, command: &str, required: &[&str]) -> Result<(), ConfigError> {
+        match required.iter().find(|flag| !self.has(flag)) {
+            Some(flag) => Err(ConfigError::new(
+                ConfigErrorKind::MissingArguments,
+                &format!("{} necesita {}", command, flag),
+            )),
+            None => Ok(()),
+        }
+    }
+}
+
+// Lee los flags de un comando segun `spec`. Acepta `--flag valor` y
+// `--flag=valor`; un flag desconocido, repetido o sin valor es un error.
+fn parse_flags(
+    tokens: &[Token],
+    spec: &[(&'static str, FlagKind)],
+) -> Result<Flags, ConfigError> {
+    let mut flags = Flags::default();
+    // flag que recibe las palabras que siguen y cuantas recibio
+    let mut current: Option<(&'static str, FlagKind, usize)> = None;
+
+    let missing_value = |current: Option<(&str, FlagKind, usize)>| match current {
+        Some((name, kind, 0)) if kind != FlagKind::Switch => Err(ConfigError::new(
+            ConfigErrorKind::MissingArguments,
+            &format!("Falta el valor de {}", name),
+        )),
+        _ => Ok(()),
+    };
+
+    for token in tokens {
+        if token.is_flag() {
+            missing_value(current.take())?;
+
+            let (name, inline) = match token.text.split_once('=') {
+                Some((name, value)) => (name, Some(value)),
+                None => (token.text.as_str(), None),
+            };
+            let (name, kind) = *spec.iter().find(|(flag, _)| *flag == name).ok_or(
+                ConfigError::new(
+                    ConfigErrorKind::InvalidCommand,
+                    &format!("Argumento desconocido: {}", name),
+                ),
+            )?;
+            if kind != FlagKind::List && flags.has(name) {
+                return Err(ConfigError::new(
+                    ConfigErrorKind::DuplicateFlag,
+                    &format!("El flag {} esta repetido", name),
+                ));
+            }
+
+            let values = flags.values.entry(name).or_default();
+            match (kind, inline) {
+                (FlagKind::Switch, Some(_)) => {
+                    return Err(ConfigError::new(
+                        ConfigErrorKind::InvalidTypeData,
+                        &format!("{} no lleva valor", name),
+                    ));
+                }
+                (FlagKind::Switch, None) => {}
+                (kind, Some(value)) => {
+                    values.push(value.to_string());
+                    current = Some((name, kind, 1));
+                }
+                (kind, None) => current = Some((name, kind, 0)),
+            }
+            continue;
+        }
+
+        match &mut current {
+            Some((name, FlagKind::Value, received)) if *received > 0 => {
+                return Err(ConfigError::new(
+                    ConfigErrorKind::InvalidCommand,
+                    &format!("{} admite un solo valor, sobra: {}", name, token.text),
+                ));
+            }
+            Some((name, _, received)) => {
+                flags.values.entry(name).or_default().push(token.text.clone());
+                *received += 1;
+            }
+            None => {
+                return Err(ConfigError::new(
+                    ConfigErrorKind::InvalidCommand,
+                    &format!("Argumento desconocido: {}", token.text),
+                ));
+            }
+        }
+    }
+
+    missing_value(current)?;
+    Ok(flags)
+}
+
+// `--format` de `list` y `summary`; sin el flag se muestra la tabla
+fn parse_format(flags: &Flags) -> Result<OutputFormat, ConfigError> {
+    if !flags.has("--format") {
+        return Ok(OutputFormat::default());
+    }
+    let name = flags.get("--format");
+    OutputFormat::parse(&name).ok_or(ConfigError::new(
+        ConfigErrorKind::InvalidTypeData,
+        &format!("Formato no valido: {} (use {})", name, OutputFormat::NAMES),
+    ))
+}
+
+// Subcomando de `category`, `tag` o `rate`, que va justo despues del comando
+fn subcommand<'a>(command: &str, tokens: &'a [Token]) -> Result<(String, &'a [Token]), ConfigError> {
+    let valid = subcommands(command);
+    let options = valid.join(", ");
+    let (first, rest) = tokens.split_first().ok_or(ConfigError::new(
+        ConfigErrorKind::MissingArguments,
+        &format!("Falta el subcomando de {}: {}", command, options),
+    ))?;
+    if first.is_flag() || !valid.contains(&first.text.as_str()) {
+        return Err(ConfigError::new(
+            ConfigErrorKind::InvalidCommand,
+            &format!(
+                "Subcomando de {} invalido: {} (use {})",
+                command, first.text, options
+            ),
+        ));
+    }
+    Ok((first.text.clone(), rest))
 }
 
 impl Config {
+    // Lee un comando escrito en una linea, con comillas y escapes
     pub fn build(input: &str) -> Result<Config, ConfigError> {
-        let mut parts = input.split_whitespace();
-        let mut descripcion = String::new();
-        let mut description_flag = false;
-        let mut amount_flag = false;
-        let mut id_delete_flag = false;
-        let mut month_flag = false;
-        let mut amount: f64 = 0.0;
-        let comando = parts
-            .next()
-            .ok_or(ConfigError::new(
-                ConfigErrorKind::EmptyInput,
-                "No se proporcionó ningún comando",
-            ))?
-            .to_string();
+        Config::from_tokens(&tokenize(input)?)
+    }
+
+    // Lee un comando ya separado en palabras
+    pub fn from_tokens(tokens: &[Token]) -> Result<Config, ConfigError> {
+        let (comando, args) = tokens.split_first().ok_or(ConfigError::new(
+            ConfigErrorKind::EmptyInput,
+            "No se proporcionó ningún comando",
+        ))?;
+        let comando = comando.text.clone();
+        let mut config = Config {
+            comando: comando.clone(),
+            subcomando: String::new(),
+            descripcion: String::new(),
+            amount: String::new(),
+            currency: String::new(),
+            category: String::new(),
+            category_target: String::new(),
+            by: None,
+            format: OutputFormat::default(),
+            id: String::new(),
+            tags: Vec::new(),
+            date: String::new(),
+            no_category: false,
+            no_tags: false,
+            rate: RateArgs::default(),
+            filters: FilterArgs::default(),
+            script: String::new(),
+            atomic: false,
+        };
 
         match comando.as_str() {
-            "add" => loop {
-                let item = parts.next();
-                if item.is_none() {
-                    break;
+            "add" => {
+                let flags = parse_flags(args, &command_flags(&comando))?;
+                flags.require("add", &["--description", "--amount"])?;
+                config.descripcion = flags.get("--description");
+                config.amount = flags.get("--amount");
+                config.currency = flags.get("--currency");
+                config.category = flags.get("--category");
+                config.tags = flags.list("--tags");
+                config.date = flags.get("--date");
+            }
+            "update" => {
+                let flags = parse_flags(args, &command_flags(&comando))?;
+                flags.require("update", &["--id"])?;
+                if (flags.has("--no-category") && flags.has("--category"))
+                    || (flags.has("--no-tags") && flags.has("--tags"))
+                {
+                    return Err(ConfigError::new(
+                        ConfigErrorKind::InvalidCommand,
+                        "No se puede cambiar y quitar el mismo campo a la vez",
+                    ));
                 }
-                let item = item.unwrap();
-
-                if item == "--description" {
-                    description_flag = true;
-                    amount_flag = false;
-                } else if item == "--amount" {
-                    description_flag = false;
-                    amount_flag = true;
-                } else if description_flag {
-
-                    if !descripcion.is_empty() {
-                        descripcion.push(' ');
-                    }
-                    descripcion.push_str(item);
-                } else if amount_flag {
-                    amount = item.parse().map_err(|_| {
+                if flags.values.len() == 1 {
+                    return Err(ConfigError::new(
+                        ConfigErrorKind::MissingArguments,
+                        "update necesita al menos un campo para cambiar",
+                    ));
+                }
+                // un campo vacio (`--amount=` o `--description ""`) no deja el
+                // valor como estaba: solo un flag que no se paso no cambia nada
+                let fields = [
+                    "--id",
+                    "--description",
+                    "--amount",
+                    "--currency",
+                    "--category",
+                    "--date",
+                ];
+                if let Some(flag) = fields
+                    .iter()
+                    .find(|flag| flags.has(flag) && flags.get(flag).trim().is_empty())
+                {
+                    return Err(ConfigError::new(
+                        ConfigErrorKind::InvalidTypeData,
+                        &format!("El valor de {} no puede estar vacio", flag),
+                    ));
+                }
+                config.id = flags.get("--id");
+                config.descripcion = flags.get("--description");
+                config.amount = flags.get("--amount");
+                config.currency = flags.get("--currency");
+                config.category = flags.get("--category");
+                config.no_category = flags.has("--no-category");
+                config.tags = flags.list("--tags");
+                config.no_tags = flags.has("--no-tags");
+                config.date = flags.get("--date");
+            }
+            "init" | "check" | "repair" => {
+                parse_flags(args, &command_flags(&comando))?;
+            }
+            "list" => {
+                let flags = parse_flags(args, &command_flags(&comando))?;
+                config.filters = FilterArgs::from_flags(&flags);
+                config.format = parse_format(&flags)?;
+            }
+            "summary" => {
+                let flags = parse_flags(args, &command_flags(&comando))?;
+                config.filters = FilterArgs::from_flags(&flags);
+                config.format = parse_format(&flags)?;
+
+                if flags.has("--by") {
+                    let name = flags.get("--by");
+                    config.by = Some(GroupBy::parse(&name).ok_or(ConfigError::new(
+                        ConfigErrorKind::InvalidTypeData,
+                        &format!("No se puede agrupar por {}, use: category o tag", name),
+                    ))?);
+                }
+
+                // la profundidad solo tiene sentido al agrupar por categoria
+                if flags.has("--depth") {
+                    let value = flags.get("--depth");
+                    let depth = value.parse::<usize>().ok().filter(|depth| *depth > 0).ok_or(
                         ConfigError::new(
                             ConfigErrorKind::InvalidTypeData,
-                            "Ingrese un numero por favor"
-                        )
-                    })?;
-                } 
-            },
-            "list" => {},
-            "summary" => loop {
-                let item = parts.next();
-                if item.is_none() {
-                    break;
+                            &format!("--depth debe ser un numero mayor a 0: {}", value),
+                        ),
+                    )?;
+                    match config.by {
+                        Some(GroupBy::Category(_)) => {
+                            config.by = Some(GroupBy::Category(Some(depth)))
+                        }
+                        _ => {
+                            return Err(ConfigError::new(
+                                ConfigErrorKind::InvalidCommand,
+                                "--depth se usa junto con --by category",
+                            ));
+                        }
+                    }
                 }
-                let item = item.unwrap();
-                if item == "--month" {
-                    month_flag = true;
-                } else if month_flag {
-                    descripcion.push_str(item);
+            }
+            "delete" => {
+                let flags = parse_flags(args, &command_flags(&comando))?;
+                // sin ningun flag se borrarian todos los gastos
+                if flags.values.is_empty() {
+                    return Err(ConfigError::new(
+                        ConfigErrorKind::MissingArguments,
+                        "delete necesita --id o algun filtro",
+                    ));
                 }
-            },
-            "delete" => loop {
-                let item = parts.next();
-                if item.is_none() {
-                    break;
+                config.filters = FilterArgs::from_flags(&flags);
+            }
+            "category" => {
+                let (subcomando, args) = subcommand("category", args)?;
+                let flags = parse_flags(args, &command_flags(&comando))?;
+                let required: &[&str] = match subcomando.as_str() {
+                    "add" | "delete" => &["--name"],
+                    "rename" => &["--name", "--to"],
+                    "merge" => &["--name", "--into"],
+                    _ => &[],
+                };
+                flags.require(&format!("category {}", subcomando), required)?;
+                config.category = flags.get("--name");
+                config.category_target = match subcomando.as_str() {
+                    "merge" => flags.get("--into"),
+                    _ => flags.get("--to"),
+                };
+                config.subcomando = subcomando;
+            }
+            "tag" => {
+                let (subcomando, args) = subcommand("tag", args)?;
+                let flags = parse_flags(args, &command_flags(&comando))?;
+                if subcomando != "list" {
+                    flags.require(&format!("tag {}", subcomando), &["--id", "--tags"])?;
                 }
-                let item = item.unwrap();
-
-                if item == "--id" {
-                    id_delete_flag = true;
-                } else if id_delete_flag {
-                    if !descripcion.is_empty() {
-                        descripcion.push(' ');
+                config.id = flags.get("--id");
+                config.tags = flags.list("--tags");
+                config.subcomando = subcomando;
+            }
+            "rate" => {
+                let (subcomando, args) = subcommand("rate", args)?;
+                let flags = parse_flags(args, &command_flags(&comando))?;
+                let required: &[&str] = match subcomando.as_str() {
+                    "set" => &["--from", "--rate"],
+                    "get" => &["--from"],
+                    "import" => &["--file"],
+                    _ => &[],
+                };
+                flags.require(&format!("rate {}", subcomando), required)?;
+                config.rate = RateArgs {
+                    from: flags.get("--from"),
+                    to: flags.get("--to"),
+                    rate: flags.get("--rate"),
+                    date: flags.get("--date"),
+                    file: flags.get("--file"),
+                };
+                config.subcomando = subcomando;
+            }
+            "run" => {
+                // run <archivo> [--atomic]
+                let (script, args) = match args.split_first() {
+                    Some((first, rest)) if !first.is_flag() => (first.text.clone(), rest),
+                    _ => {
+                        return Err(ConfigError::new(
+                            ConfigErrorKind::MissingArguments,
+                            "Falta el archivo de comandos de run (use - para leer la entrada estandar)",
+                        ));
+                    }
+                };
+                let flags = parse_flags(args, &command_flags(&comando))?;
+                config.script = script;
+                config.atomic = flags.has("--atomic");
+            }
+            "help" => {
+                // `help` o `help <comando>`
+                match args {
+                    [] => {}
+                    [topic] if !topic.is_flag() && COMMANDS.contains(&topic.text.as_str()) => {
+                        config.subcomando = topic.text.clone();
+                    }
+                    _ => {
+                        let topic: Vec<&str> = args.iter().map(|token| token.text.as_str()).collect();
+                        return Err(ConfigError::new(
+                            ConfigErrorKind::InvalidCommand,
+                            &format!(
+                                "No hay ayuda para {} (use: {})",
+                                topic.join(" "),
+                                COMMANDS.join(", ")
+                            ),
+                        ));
                     }
-                    descripcion.push_str(item);
                 }
             }
             _ => {
                 return Err(ConfigError::new(
                     ConfigErrorKind::InvalidCommand,
-                    "Comando invalido",
+                    &format!("Comando invalido: {} (use help para ver los comandos)", comando),
                 ));
             }
         }
 
-        Ok(Config {
-            comando,
-            descripcion,
-            amount: amount,
-        })
+        Ok(config)
     }
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn texts(input: &str) -> Vec<String> {
+        tokenize(input)
+            .unwrap()
+            .into_iter()
+            .map(|token| token.text)
+            .collect()
+    }
+
+    fn parse(input: &str) -> Config {
+        Config::build(input).unwrap()
+    }
+
+    fn error(input: &str) -> ConfigError {
+        Config::build(input).unwrap_err()
+    }
+
+    #[test]
+    fn tokenize_splits_on_whitespace() {
+        assert_eq!(texts("  list   --month 3\t"), ["list", "--month", "3"]);
+        assert!(tokenize("   ").unwrap().is_empty());
+    }
+
+    #[test]
+    fn tokenize_single_quotes_are_literal() {
+        assert_eq!(
+            texts(r#"add 'Cafe "del" dia' 'a\b'"#),
+            ["add", r#"Cafe "del" dia"#, r"a\b"]
+        );
+        assert_eq!(texts("''"), [""]);
+    }
+
+    #[test]
+    fn tokenize_double_quotes_escape_quote_and_backslash() {
+        assert_eq!(texts(r#""Cena \"buena\"""#), [r#"Cena "buena""#]);
+        assert_eq!(texts(r#""C:\\datos""#), [r"C:\datos"]);
+        // otros escapes se dejan tal cual
+        assert_eq!(texts(r#""a\nb""#), [r"a\nb"]);
+    }
+
+    #[test]
+    fn tokenize_backslash_escapes_outside_quotes() {
+        assert_eq!(texts(r"Almuerzo\ con\ amigos"), ["Almuerzo con amigos"]);
+        assert_eq!(texts(r"\--amount"), ["--amount"]);
+        assert_eq!(texts(r#"a"b c"d"#), ["ab cd"]);
+    }
+
+    #[test]
+    fn tokenize_marks_quoted_words() {
+        let tokens = tokenize(r#"--amount "--amount" '--x' \--y a"--b""#).unwrap();
+        let quoted: Vec<bool> = tokens.iter().map(|token| token.quoted).collect();
+        assert_eq!(quoted, [false, true, true, true, false]);
+        assert!(tokens[0].is_flag());
+        assert!(!tokens[1].is_flag());
+    }
+
+    #[test]
+    fn tokenize_rejects_unterminated_quotes() {
+        for input in ["add 'Cafe", r#"add "Cafe"#, r#"add "Cafe\"#, "add Cafe\\"] {
+            assert!(
+                matches!(
+                    tokenize(input).unwrap_err().kind(),
+                    ConfigErrorKind::InvalidSyntax
+                ),
+                "{:?}",
+                input
+            );
+        }
+    }
+
+    #[test]
+    fn flag_with_equals_sign() {
+        let config = parse("add --description=Cafe --amount=2700 --tags=a b");
+        assert_eq!(config.descripcion, "Cafe");
+        assert_eq!(config.amount, "2700");
+        assert_eq!(config.tags, ["a", "b"]);
+
+        let config = parse(r#"add --description="Cafe con leche" --amount 1"#);
+        assert_eq!(config.descripcion, "Cafe con leche");
+    }
+
+    #[test]
+    fn quoted_value_that_starts_with_dashes() {
+        let config = parse(r#"add --description "--amount no es un flag" --amount 5"#);
+        assert_eq!(config.descripcion, "--amount no es un flag");
+        assert_eq!(config.amount, "5");
+
+        let tokens = [
+            Token::plain("add"),
+            Token::plain("--description"),
+            Token::plain("--amount"),
+        ];
+        assert!(matches!(
+            Config::from_tokens(&tokens).unwrap_err().kind(),
+            ConfigErrorKind::MissingArguments
+        ));
+    }
+
+    #[test]
+    fn duplicate_flags_are_rejected_except_lists() {
+        assert!(matches!(
+            error("add --description a --amount 1 --amount 2").kind(),
+            ConfigErrorKind::DuplicateFlag
+        ));
+        assert!(matches!(
+            error("add --description a --description=b --amount 1").kind(),
+            ConfigErrorKind::DuplicateFlag
+        ));
+        let config = parse("add --description a --amount 1 --tags x --tags y z");
+        assert_eq!(config.tags, ["x", "y", "z"]);
+    }
+
+    #[test]
+    fn missing_values_are_rejected() {
+        for input in [
+            "add --description a --amount",
+            "add --description --amount 1",
+            "list --tag",
+            "delete --id",
+        ] {
+            assert!(
+                matches!(error(input).kind(), ConfigErrorKind::MissingArguments),
+                "{:?}",
+                input
+            );
+        }
+        assert!(matches!(
+            error("add --amount 1").kind(),
+            ConfigErrorKind::MissingArguments
+        ));
+    }
+
+    #[test]
+    fn update_rejects_empty_values() {
+        for input in [
+            "update --id 1 --description=",
+            r#"update --id 1 --description """#,
+            "update --id 1 --amount=",
+            "update --id 1 --currency=",
+            r#"update --id 1 --category "  ""#,
+            "update --id 1 --date=",
+            "update --id= --amount 5",
+        ] {
+            assert!(
+                matches!(error(input).kind(), ConfigErrorKind::InvalidTypeData),
+                "{:?}",
+                input
+            );
+        }
+        let config = parse("update --id 1 --amount 5");
+        assert_eq!(config.amount, "5");
+        assert_eq!(config.descripcion, "");
+    }
+
+    #[test]
+    fn value_flags_take_one_word() {
+        assert!(matches!(
+            error("add --description a --amount 1 2").kind(),
+            ConfigErrorKind::InvalidCommand
+        ));
+        assert!(matches!(
+            error("add --description a --amount=1 2").kind(),
+            ConfigErrorKind::InvalidCommand
+        ));
+    }
+
+    #[test]
+    fn words_flags_join_their_words() {
+        let config =
+            parse("add --description Almuerzo con amigos --amount 1 --category Comida rapida");
+        assert_eq!(config.descripcion, "Almuerzo con amigos");
+        assert_eq!(config.category, "Comida rapida");
+    }
+
+    #[test]
+    fn list_flags_keep_each_value() {
+        let config = parse("delete --id 1 2 3");
+        assert_eq!(config.filters.ids, ["1", "2", "3"]);
+        let config = parse("list --tag a --tag b");
+        assert_eq!(config.filters.tags, ["a", "b"]);
+    }
+
+    #[test]
+    fn switch_flags_take_no_value() {
+        let config = parse("update --id 1 --no-tags --no-category");
+        assert!(config.no_tags);
+        assert!(config.no_category);
+        assert!(parse("run - --atomic").atomic);
+
+        assert!(matches!(
+            error("update --id 1 --no-tags=si").kind(),
+            ConfigErrorKind::InvalidTypeData
+        ));
+        assert!(matches!(
+            error("update --id 1 --no-tags si").kind(),
+            ConfigErrorKind::InvalidCommand
+        ));
+    }
+
+    #[test]
+    fn unknown_flags_and_words_are_rejected() {
+        assert!(matches!(
+            error("list --foo 1").kind(),
+            ConfigErrorKind::InvalidCommand
+        ));
+        assert!(matches!(
+            error("list --foo=1").kind(),
+            ConfigErrorKind::InvalidCommand
+        ));
+        assert!(matches!(
+            error("list marzo").kind(),
+            ConfigErrorKind::InvalidCommand
+        ));
+        assert!(matches!(error("").kind(), ConfigErrorKind::EmptyInput));
+    }
+
+    #[test]
+    fn format_and_group_flags() {
+        assert_eq!(parse("list --format=csv").format, OutputFormat::Csv);
+        assert_eq!(parse("list").format, OutputFormat::Table);
+        assert!(matches!(
+            error("list --format xml").kind(),
+            ConfigErrorKind::InvalidTypeData
+        ));
+        assert!(matches!(
+            parse("summary --by category --depth 2").by,
+            Some(GroupBy::Category(Some(2)))
+        ));
+        assert!(matches!(
+            error("summary --depth 2").kind(),
+            ConfigErrorKind::InvalidCommand
+        ));
+    }
+}
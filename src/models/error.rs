@@ -47,7 +47,10 @@ pub enum ConfigErrorKind {
     InvalidCommand,
     MissingArguments,
     InvalidTypeData,
-    
+    // un flag aparece mas de una vez
+    DuplicateFlag,
+    // comillas sin cerrar o un escape incompleto
+    InvalidSyntax,
 }
 
 #[derive(Debug)]
@@ -88,13 +91,18 @@ pub enum ExpenseErrorKind {
     IdsErrorType,
     WriteError,
     InvalidDateFormat,
-    InvalidMonth
+    InvalidMonth,
+    InvalidRecord,
+    InvalidCategory,
+    InvalidTag,
+    MissingFilter,
 }
 #[derive(Debug)]
 pub struct ExpenseError {
     kind: ExpenseErrorKind,
     message: String,
     location: String,
+    records: Vec<RecordError>,
 }
 
 impl ExpenseError {
@@ -103,9 +111,16 @@ impl ExpenseError {
             kind,
             message: message.to_string(),
             location: location.to_string(),
+            records: Vec::new(),
         }
     }
 
+    // Adjunta el detalle de los registros que no se pudieron leer
+    pub fn with_records(mut self, records: Vec<RecordError>) -> Self {
+        self.records = records;
+        self
+    }
+
     pub fn kind(&self) -> &ExpenseErrorKind {
         &self.kind
     }
@@ -115,11 +130,18 @@ impl ExpenseError {
     pub fn location(&self) -> &String {
         &self.location
     }
+    pub fn records(&self) -> &[RecordError] {
+        &self.records
+    }
 }
 
 impl fmt::Display for ExpenseError {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "Error en '{}': {}", self.location, self.message)
+        write!(f, "Error en '{}': {}", self.location, self.message)?;
+        for record in &self.records {
+            write!(f, "\n  - {}", record)?;
+        }
+        Ok(())
     }
 }
 
@@ -133,11 +155,13 @@ pub enum JsonFileErrorKind {
     PermissionDenied, // No hay permisos para acceder al archivo
     ReadError,        // Error al leer el archivo
     WriteError,       // Error al escribir en el archivo
+    PartialWrite,     // La escritura quedo a medias, se conserva la version anterior
     ParseError,       // Error al analizar (parsear) el contenido JSON
     InvalidFormat,    // El formato JSON es inválido
     ModifyError,      // Error al intentar modificar el archivo
     DeleteError,      // Error al intentar eliminar el archivo
     CreateError,      // Error al intentar crear el archivo
+    Locked,           // Otro proceso tiene el lock de la base de datos
 }
 #[derive(Debug)]
 
@@ -180,3 +204,132 @@ impl fmt::Display for JsonFileError {
 
 // Implementar el trait `Error` para permitir el manejo de errores
 impl Error for JsonFileError {}
+
+//Errores de la capa de almacenamiento
+#[derive(Debug)]
+pub enum StorageErrorKind {
+    NotFound,       // No existe el recurso solicitado (archivo, registro, etc.)
+    ReadError,      // Error al leer los datos del backend
+    WriteError,     // Error al escribir los datos en el backend
+    CorruptedData,  // Los datos guardados no tienen la estructura esperada
+    IdsError,       // Error obteniendo o actualizando el contador de ids
+    UnknownBackend, // El backend de almacenamiento solicitado no existe
+    TransactionError, // Uso incorrecto de una transaccion (commit sin begin...)
+    Locked,         // Otro proceso esta modificando los datos
+    SchemaVersion,  // Los datos estan en una version de esquema que no se puede usar
+    InvalidRecords, // Algunos registros no tienen el formato de un gasto
+    AlreadyExists,  // Ya existe un elemento con ese nombre (categoria, etc.)
+}
+
+#[derive(Debug)]
+pub struct StorageError {
+    kind: StorageErrorKind,
+    message: String,
+    location: String,
+    records: Vec<RecordError>,
+}
+
+impl StorageError {
+    pub fn new(kind: StorageErrorKind, message: &str, location: &str) -> Self {
+        StorageError {
+            kind,
+            message: message.to_string(),
+            location: location.to_string(),
+            records: Vec::new(),
+        }
+    }
+
+    // Adjunta el detalle de los registros que no se pudieron leer
+    pub fn with_records(mut self, records: Vec<RecordError>) -> Self {
+        self.records = records;
+        self
+    }
+
+    pub fn kind(&self) -> &StorageErrorKind {
+        &self.kind
+    }
+
+    pub fn message(&self) -> &String {
+        &self.message
+    }
+
+    pub fn location(&self) -> &String {
+        &self.location
+    }
+
+    pub fn records(&self) -> &[RecordError] {
+        &self.records
+    }
+}
+
+impl fmt::Display for StorageError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Error en '{}': {}", self.location, self.message)?;
+        for record in &self.records {
+            write!(f, "\n  - {}", record)?;
+        }
+        Ok(())
+    }
+}
+
+impl Error for StorageError {}
+
+//Error puntual de un registro guardado que no se pudo convertir en gasto
+#[derive(Debug, Clone)]
+pub struct RecordError {
+    pub index: usize,       // posicion del registro en la base de datos
+    pub id: Option<String>, // id del registro, si se pudo leer
+    pub field: String,      // campo con el problema
+    pub reason: String,     // descripcion del problema
+}
+
+impl fmt::Display for RecordError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "registro {}", self.index)?;
+        if let Some(id) = &self.id {
+            write!(f, " (id {})", id)?;
+        }
+        write!(f, ", campo '{}': {}", self.field, self.reason)
+    }
+}
+
+//Errores de montos y monedas
+#[derive(Debug)]
+pub enum MoneyErrorKind {
+    InvalidAmount,    // El texto no es un numero decimal
+    TooManyDecimals,  // El monto tiene mas decimales de los que admite la moneda
+    InvalidCurrency,  // El codigo de moneda no tiene el formato ISO 4217
+    CurrencyMismatch, // Operacion entre montos de monedas distintas
+    Overflow,         // El monto no cabe en el rango soportado
+}
+
+#[derive(Debug)]
+pub struct MoneyError {
+    kind: MoneyErrorKind,
+    message: String,
+}
+
+impl MoneyError {
+    pub fn new(kind: MoneyErrorKind, message: &str) -> Self {
+        MoneyError {
+            kind,
+            message: message.to_string(),
+        }
+    }
+
+    pub fn kind(&self) -> &MoneyErrorKind {
+        &self.kind
+    }
+
+    pub fn message(&self) -> &String {
+        &self.message
+    }
+}
+
+impl fmt::Display for MoneyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+impl Error for MoneyError {}
@@ -1,351 +1,828 @@
-use std::time::SystemTime;
-
-use chrono::{DateTime, Datelike, Local, NaiveDateTime, Utc};
-use serde_json::json;
-
-use super::error::{ExpenseError, ExpenseErrorKind};
-use crate::utils::file_utils::{open_json, save_json, JsonStructure};
-use prettytable::{row, Cell, Row, Table};
+use std::{collections::BTreeMap, time::SystemTime};
+
+use chrono::{DateTime, NaiveDate, Utc};
+use serde_json::Value;
+
+use super::{
+    tag::parse_tags,
+    error::{
+        ExpenseError, ExpenseErrorKind, MoneyError, RecordError, StorageError, StorageErrorKind,
+    },
+    money::{parse_currency, Money},
+};
+use crate::data::{
+    categories::{category_levels, category_prefix},
+    expense_storage::ExpenseStorage,
+    query::ExpenseQuery,
+    rates::RateTable,
+};
 use serde::{Deserialize, Serialize};
 
-#[derive(Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Expense {
     pub id: String,
     pub description: String,
-    pub amount: f64,
+    #[serde(flatten)]
+    pub amount: Money,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub category: Option<String>,
+    // etiquetas normalizadas (ver `tag::parse_tags`), ordenadas y sin repetir
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+    // dia en que se hizo el gasto; `created_at` es cuando se registro
+    pub spent_at: NaiveDate,
     pub created_at: DateTime<Utc>,
+    // ultima vez que se modifico con `update`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub updated_at: Option<DateTime<Utc>>,
 }
 
-impl Expense {
-    pub fn build(description: &str, amount: f64) -> Result<(), ExpenseError> {
-        if description == "".to_string() {
-            return Err(ExpenseError::new(
-                ExpenseErrorKind::EmptyDescription,
-                "La descripcion no puede estar vacia",
-                "Expense::build",
-            ));
-        }
+// Cambios de `update`. Los campos en `None` no cambian; en `category`,
+// `Some(None)` quita la categoria.
+#[derive(Debug, Default)]
+pub struct ExpenseUpdate {
+    pub description: Option<String>,
+    pub amount: Option<String>,
+    pub currency: Option<String>,
+    pub category: Option<Option<String>>,
+    pub tags: Option<Vec<String>>,
+    pub spent_at: Option<NaiveDate>,
+}
 
-        if amount <= 0.0 {
-            return Err(ExpenseError::new(
-                ExpenseErrorKind::InvalidAmountType,
-                "El monto debe ser mayor a 0",
-                "Expense::build",
-            ));
+// Criterio para desglosar el total de `summary`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum GroupBy {
+    // por categoria, hasta la profundidad indicada del arbol (sin limite si es
+    // `None`); cada categoria suma tambien los gastos de sus subcategorias
+    Category(Option<usize>),
+    // por etiqueta; un gasto con varias etiquetas suma en cada una
+    Tag,
+}
+
+impl GroupBy {
+    pub fn parse(name: &str) -> Option<GroupBy> {
+        match name.to_lowercase().as_str() {
+            "category" => Some(GroupBy::Category(None)),
+            "tag" => Some(GroupBy::Tag),
+            _ => None,
         }
+    }
+}
 
-        let now = SystemTime::now();
-        let datetime: DateTime<Utc> = DateTime::from(now);
+// Resultado de `summary`. Los montos estan en la moneda base, salvo los
+// originales de `converted` y los gastos de `missing_rate`.
+#[derive(Debug, Clone)]
+pub struct Summary {
+    pub total: Money,
+    // un subtotal por cada moneda distinta de la base, por codigo
+    pub converted: Vec<ConvertedTotal>,
+    pub group_by: Option<GroupBy>,
+    // subtotales del desglose; vacio sin `group_by`
+    pub groups: Vec<GroupTotal>,
+    // gastos sin tasa de cambio a la moneda base, que no entran en el total
+    pub missing_rate: Vec<Expense>,
+}
 
-        let mut id_json = match open_json("./DB/ids.json") {
-            Ok(JsonStructure::Object(map)) => map,
-            Ok(JsonStructure::Array(vec)) => {
-                return Err(ExpenseError::new(
-                    ExpenseErrorKind::CreateError, // Asignar un tipo de error específico en `ExpenseError`
-                    &format!("Error en archivo JSON: es un array {:?}", vec), // Usar el mensaje de error original
-                    "Expense::build",
-                ));
-            }
-            Err(err) => {
-                // Mapeo del error `JsonFileError` al `ExpenseError`
-                return Err(ExpenseError::new(
-                    ExpenseErrorKind::CreateError, // Asignar un tipo de error específico en `ExpenseError`
-                    &format!("Error en archivo JSON: {}", err), // Usar el mensaje de error original
-                    "Expense::build",
-                ));
-            }
-        };
+// Suma de los gastos en una moneda, antes y despues de convertirla
+#[derive(Debug, Clone)]
+pub struct ConvertedTotal {
+    pub original: Money,
+    pub converted: Money,
+}
 
-        let id = match id_json.get("id").and_then(|id| id.as_i64()) {
-            Some(id_data) => id_data,
-            None => {
-                return Err(ExpenseError::new(
-                    ExpenseErrorKind::CreateError,
-                    "La clave id no fue encontrada en el archivo json",
-                    "Expense:build",
-                ));
+// Subtotal de una categoria o etiqueta. `name` es `None` para los gastos sin
+// categoria o sin etiquetas.
+#[derive(Debug, Clone)]
+pub struct GroupTotal {
+    pub name: Option<String>,
+    pub total: Money,
+}
+
+// Convierte un error al leer los gastos en un `ExpenseError`, conservando el
+// detalle de los registros que no se pudieron leer
+fn read_error(err: StorageError, location: &str) -> ExpenseError {
+    match err.kind() {
+        StorageErrorKind::InvalidRecords => {
+            ExpenseError::new(ExpenseErrorKind::InvalidRecord, err.message(), location)
+                .with_records(err.records().to_vec())
+        }
+        _ => ExpenseError::new(
+            ExpenseErrorKind::ReadError,
+            &format!("Error leyendo los gastos {}", err),
+            location,
+        ),
+    }
+}
+
+// Campo del monto que fallo, leyendo solo `amount` y `currency` del registro
+fn money_field(record: &Value) -> String {
+    match serde_path_to_error::deserialize::<_, Money>(record) {
+        Err(err) if err.path().to_string() != "." => err.path().to_string(),
+        _ => {
+            let currency = record.get("currency").and_then(|currency| currency.as_str());
+            match currency.map(parse_currency) {
+                Some(Err(_)) => "currency".to_string(),
+                _ => "amount".to_string(),
             }
-        };
+        }
+    }
+}
 
-        let new_id = id + 1;
-        id_json.insert("id".to_string(), json!(new_id));
+// Reglas comunes de `build` y `update`
 
-        // Aquí está el cambio principal: envolvemos id_json en JsonStructure::Object
-        let json_structure = JsonStructure::Object(id_json);
+fn validate_description(description: &str, location: &str) -> Result<(), ExpenseError> {
+    if description.trim().is_empty() {
+        return Err(ExpenseError::new(
+            ExpenseErrorKind::EmptyDescription,
+            "La descripcion no puede estar vacia",
+            location,
+        ));
+    }
+    Ok(())
+}
 
-        //se guarda el nuevo id
-        match save_json("./DB/ids.json", json_structure) {
-            Ok(_) => (),
-            Err(err) => {
-                return Err(ExpenseError::new(
-                    ExpenseErrorKind::CreateError, // Asignar un tipo de error específico en `ExpenseError`
-                    &format!("Error en archivo JSON: {}", err), // Usar el mensaje de error original
+fn parse_amount(amount: &str, currency: &str, location: &str) -> Result<Money, ExpenseError> {
+    let amount = Money::parse(amount, currency).map_err(|err| {
+        ExpenseError::new(ExpenseErrorKind::InvalidAmountType, err.message(), location)
+    })?;
+
+    if !amount.is_positive() {
+        return Err(ExpenseError::new(
+            ExpenseErrorKind::InvalidAmountType,
+            "El monto debe ser mayor a 0",
+            location,
+        ));
+    }
+    Ok(amount)
+}
+
+impl Expense {
+    // Convierte un registro guardado (en la posicion `index`) en un gasto. Si no
+    // se puede, el error indica el campo que fallo y el motivo.
+    pub fn from_record(index: usize, record: &Value) -> Result<Expense, RecordError> {
+        serde_path_to_error::deserialize(record).map_err(|err| {
+            let reason = err.inner().to_string();
+            let field = match err.path().to_string().as_str() {
+                // serde no da la ruta de los campos que faltan, viene en el mensaje
+                "." => match reason
+                    .strip_prefix("missing field `")
+                    .and_then(|rest| rest.strip_suffix('`'))
+                {
+                    Some(field) => field.to_string(),
+                    // el monto va aplanado y sus errores tambien llegan sin ruta
+                    None => money_field(record),
+                },
+                path => path.to_string(),
+            };
+
+            RecordError {
+                index,
+                id: record.get("id").and_then(|id| id.as_str()).map(String::from),
+                field,
+                reason,
+            }
+        })
+    }
+
+    pub fn build(
+        storage: &mut dyn ExpenseStorage,
+        description: &str,
+        amount: &str,
+        currency: &str,
+        category: Option<&str>,
+        tags: &[String],
+        spent_at: NaiveDate,
+    ) -> Result<(), ExpenseError> {
+        validate_description(description, "Expense::build")?;
+        let amount = parse_amount(amount, currency, "Expense::build")?;
+        let tags = parse_tags(tags)?;
+
+        //el id y el gasto se guardan en la misma transaccion: o se confirman
+        //los dos o no se confirma ninguno
+        storage.begin().map_err(|err| {
+            ExpenseError::new(
+                ExpenseErrorKind::CreateError,
+                &format!("Error iniciando la transaccion: {}", err),
+                "Expense::build",
+            )
+        })?;
+
+        match Expense::insert_new(storage, description, amount, category, tags, spent_at) {
+            Ok(_) => storage.commit().map_err(|err| {
+                ExpenseError::new(
+                    ExpenseErrorKind::CreateError,
+                    &format!("Error confirmando el gasto: {}", err),
                     "Expense::build",
-                ));
+                )
+            }),
+            Err(err) => {
+                let _ = storage.rollback();
+                Err(err)
             }
         }
+    }
+
+    fn insert_new(
+        storage: &mut dyn ExpenseStorage,
+        description: &str,
+        amount: Money,
+        category: Option<&str>,
+        tags: Vec<String>,
+        spent_at: NaiveDate,
+    ) -> Result<(), ExpenseError> {
+        let now = SystemTime::now();
+        let datetime: DateTime<Utc> = DateTime::from(now);
+
+        //se reserva el id del nuevo gasto
+        let id = storage.next_id().map_err(|err| {
+            ExpenseError::new(
+                ExpenseErrorKind::CreateError,
+                &format!("Error obteniendo el id: {}", err),
+                "Expense::build",
+            )
+        })?;
 
         //se crea la estructura
         let expense = Expense {
-            id: id.to_string(),
+            id,
             description: description.to_string(),
             amount,
+            category: category.map(String::from),
+            tags,
+            spent_at,
             created_at: datetime,
+            updated_at: None,
         };
 
-        //se obtiene la base de datos
-        let mut expenses_json = match open_json("./DB/expenses.json") {
-            Ok(JsonStructure::Array(vec)) => vec,
-            Ok(JsonStructure::Object(map)) => {
-                return Err(ExpenseError::new(
-                    ExpenseErrorKind::CreateError, // Asignar un tipo de error específico en `ExpenseError`
-                    &format!("Error en archivo JSON: es un array {:?}", map), // Usar el mensaje de error original
-                    "Expense::build",
-                ));
-            }
-            Err(err) => {
-                return Err(ExpenseError::new(
-                    ExpenseErrorKind::CreateError, // Asignar un tipo de error específico en `ExpenseError`
-                    &format!("Error en archivo JSON: {}", err), // Usar el mensaje de error original
-                    "Expense::build",
-                ));
-            }
+        //se guarda en el almacenamiento
+        storage.insert(&expense).map_err(|err| {
+            ExpenseError::new(
+                ExpenseErrorKind::CreateError,
+                &format!("Error guardando el gasto: {}", err),
+                "Expense::build",
+            )
+        })?;
+
+        Ok(())
+    }
+
+    // Cambia los campos indicados del gasto `id`, validandolos igual que
+    // `build`, y guarda la hora de la modificacion. El id no cambia.
+    pub fn update(
+        storage: &mut dyn ExpenseStorage,
+        id: &str,
+        changes: ExpenseUpdate,
+    ) -> Result<Expense, ExpenseError> {
+        let location = "Expense::update";
+        let write_error = |err: StorageError| {
+            ExpenseError::new(
+                ExpenseErrorKind::WriteError,
+                &format!("Error escribiendo los datos {}", err),
+                location,
+            )
         };
 
-        // se agrega el elemento al array
-        expenses_json.push(json!(expense));
+        if let Some(description) = &changes.description {
+            validate_description(description, location)?;
+        }
+        let tags = changes.tags.as_deref().map(parse_tags).transpose()?;
+
+        storage.begin().map_err(write_error)?;
+        let updated = storage
+            .query(&ExpenseQuery::new().with_ids(vec![id.to_string()]))
+            .map_err(|err| read_error(err, location))
+            .and_then(|expenses| {
+                let mut expense = expenses.into_iter().next().ok_or(ExpenseError::new(
+                    ExpenseErrorKind::IdsErrorType,
+                    &format!("No existe un gasto con id {}", id),
+                    location,
+                ))?;
+
+                if let Some(description) = changes.description {
+                    expense.description = description;
+                }
+                // sin --amount se conserva el monto, y sin --currency la moneda
+                if changes.amount.is_some() || changes.currency.is_some() {
+                    let amount = changes
+                        .amount
+                        .unwrap_or_else(|| expense.amount.amount_string());
+                    let currency = changes
+                        .currency
+                        .unwrap_or_else(|| expense.amount.currency().to_string());
+                    expense.amount = parse_amount(&amount, &currency, location)?;
+                }
+                if let Some(category) = changes.category {
+                    expense.category = category;
+                }
+                if let Some(tags) = tags {
+                    expense.tags = tags;
+                }
+                if let Some(spent_at) = changes.spent_at {
+                    expense.spent_at = spent_at;
+                }
+                expense.updated_at = Some(Utc::now());
 
-        let json_structure = JsonStructure::Array(expenses_json);
+                storage.update(&expense).map_err(write_error)?;
+                Ok(expense)
+            });
 
-        //se guarda el nuevo archivo .json
-        match save_json("./DB/expenses.json", json_structure) {
-            Ok(_) => (),
+        match updated {
+            Ok(expense) => {
+                storage.commit().map_err(write_error)?;
+                Ok(expense)
+            }
             Err(err) => {
-                return Err(ExpenseError::new(
-                    ExpenseErrorKind::CreateError, // Asignar un tipo de error específico en `ExpenseError`
-                    &format!("Error en archivo JSON: {}", err), // Usar el mensaje de error original
-                    "Expense::build",
-                ));
+                let _ = storage.rollback();
+                Err(err)
             }
         }
+    }
 
-        Ok(())
+    // Gastos que cumplen la consulta, en su orden
+    pub fn list(
+        storage: &dyn ExpenseStorage,
+        query: &ExpenseQuery,
+    ) -> Result<Vec<Expense>, ExpenseError> {
+        storage
+            .query(query)
+            .map_err(|err| read_error(err, "Expense::list"))
     }
 
-    pub fn list() -> Result<(), ExpenseError> {
-        let expeneses_json = match open_json("./DB/expenses.json") {
-            Ok(JsonStructure::Array(vec)) => vec,
-            Ok(JsonStructure::Object(map)) => {
-                return Err(ExpenseError::new(
-                    ExpenseErrorKind::ReadError,
-                    &format!("Error en la estructura del JSON {:?}", map),
-                    "Expense:list",
-                ))
-            }
+    // Lee el numero de mes de `--month` (1 a 12)
+    pub fn parse_month(month: &str) -> Result<u32, ExpenseError> {
+        let month = match month.parse::<u32>() {
+            Ok(num) => num,
             Err(err) => {
                 return Err(ExpenseError::new(
-                    ExpenseErrorKind::ReadError,
-                    &format!("Erros leyendo el archivo JSON {}", err),
-                    "Expense::list",
+                    ExpenseErrorKind::InvalidMonth,
+                    &format!("Formato de mes no valido {:?}", err),
+                    "Expense::parse_month",
                 ));
             }
         };
 
-        // Crea una tabla para mostrar los datos
-        let mut table = Table::new();
-        table.add_row(row!["ID", "Descripción", "Monto", "Fecha de Creación"]);
-
-        // Agrega cada gasto a la tabla
-        for expense in expeneses_json {
-            let mut fila = vec![];
-            match expense.get("id") {
-                Some(id) => {
-                    fila.push(Cell::new(id.as_str().unwrap_or("N/A")));
-                }
-                None => {}
-            }
+        if !(1..=12).contains(&month) {
+            return Err(ExpenseError::new(
+                ExpenseErrorKind::InvalidMonth,
+                "Mes no valido",
+                "Expense::parse_month",
+            ));
+        }
+        Ok(month)
+    }
 
-            match expense.get("description") {
-                Some(description) => {
-                    fila.push(Cell::new(description.as_str().unwrap_or("N/A")));
-                }
-                None => {}
-            }
+    // Total en la moneda base de los gastos que cumplen la consulta (por
+    // ejemplo los de un mes, segun la fecha del gasto). Los gastos en otra
+    // moneda se convierten con la tasa de su fecha; los que no tienen tasa
+    // quedan aparte y no entran en el total.
+    pub fn summary(
+        storage: &dyn ExpenseStorage,
+        query: &ExpenseQuery,
+        group_by: Option<GroupBy>,
+        rates: &RateTable,
+        base_currency: &str,
+    ) -> Result<Summary, ExpenseError> {
+        let expenses = storage
+            .query(query)
+            .map_err(|err| read_error(err, "Expense::summary"))?;
+
+        let money_error = |err: MoneyError| {
+            ExpenseError::new(
+                ExpenseErrorKind::InvalidAmountType,
+                err.message(),
+                "Expense::summary",
+            )
+        };
 
-            match expense.get("amount") {
-                Some(amount) => {
-                    fila.push(Cell::new(amount.to_string().as_str()));
-                }
-                None => {}
+        let mut total = Money::zero(base_currency).map_err(money_error)?;
+        // por cada moneda distinta de la base: subtotal original y convertido
+        let mut converted: BTreeMap<String, (Money, Money)> = BTreeMap::new();
+        // subtotales por grupo; la clave va en minusculas para que "Bus" y
+        // "bus" queden juntos y se guarda el primer nombre encontrado. Cada
+        // categoria va justo antes de sus subcategorias y los gastos sin
+        // categoria o sin etiquetas (`None`) van primero.
+        let mut groups: BTreeMap<Option<String>, GroupTotal> = BTreeMap::new();
+        let mut missing_rate = Vec::new();
+
+        for expense in expenses {
+            let date = expense.spent_at;
+            let Some(amount) = rates
+                .convert(&expense.amount, total.currency(), date)
+                .map_err(money_error)?
+            else {
+                missing_rate.push(expense);
+                continue;
+            };
+            total = total.checked_add(&amount).map_err(money_error)?;
+
+            if expense.amount.currency() != total.currency() {
+                let subtotal = match converted.remove(expense.amount.currency()) {
+                    Some((original, base)) => (
+                        original.checked_add(&expense.amount).map_err(money_error)?,
+                        base.checked_add(&amount).map_err(money_error)?,
+                    ),
+                    None => (expense.amount.clone(), amount.clone()),
+                };
+                converted.insert(expense.amount.currency().to_string(), subtotal);
             }
 
-            match expense.get("created_at") {
-                Some(created_at) => {
-                    let date_format = match created_at.as_str().unwrap().parse::<DateTime<Utc>>() {
-                        Ok(date_format) => {
-                            let local_time = date_format.with_timezone(&Local);
-                            local_time.format("%Y-%m-%d %H:%M:%S").to_string()
-                        }
-                        Err(err) => {
-                            return Err(ExpenseError::new(
-                                ExpenseErrorKind::ReadError,
-                                &format!("Error leyendo la fecha {}", err),
-                                "Expense::list",
-                            ));
-                        }
-                    };
-                    fila.push(Cell::new(&date_format.to_string()));
-                }
-                None => {}
+            let names = match group_by {
+                Some(GroupBy::Category(depth)) => match &expense.category {
+                    Some(category) => {
+                        let levels = category_levels(category).len();
+                        (1..=depth.unwrap_or(levels).min(levels))
+                            .map(|depth| Some(category_prefix(category, depth)))
+                            .collect()
+                    }
+                    None => vec![None],
+                },
+                Some(GroupBy::Tag) if expense.tags.is_empty() => vec![None],
+                Some(GroupBy::Tag) => expense.tags.iter().cloned().map(Some).collect(),
+                None => Vec::new(),
+            };
+            for name in names {
+                let key = name.as_ref().map(|name| name.to_lowercase());
+                let group = match groups.remove(&key) {
+                    Some(group) => GroupTotal {
+                        total: group.total.checked_add(&amount).map_err(money_error)?,
+                        ..group
+                    },
+                    None => GroupTotal {
+                        name,
+                        total: amount.clone(),
+                    },
+                };
+                groups.insert(key, group);
             }
-
-            table.add_row(Row::new(fila));
         }
 
-        // Imprime la tabla
-        table.printstd();
-
-        Ok(())
+        Ok(Summary {
+            total,
+            converted: converted
+                .into_values()
+                .map(|(original, converted)| ConvertedTotal {
+                    original,
+                    converted,
+                })
+                .collect(),
+            group_by,
+            groups: groups.into_values().collect(),
+            missing_rate,
+        })
     }
 
-    pub fn summary(description: &str) -> Result<(), ExpenseError> {
-        let expeneses_json = match open_json("./DB/expenses.json") {
-            Ok(JsonStructure::Array(vec)) => vec,
-            Ok(JsonStructure::Object(map)) => {
-                return Err(ExpenseError::new(
-                    ExpenseErrorKind::ReadError,
-                    &format!("Error en la estructura del JSON {:?}", map),
-                    "Expense:list",
-                ))
-            }
-            Err(err) => {
-                return Err(ExpenseError::new(
-                    ExpenseErrorKind::ReadError,
-                    &format!("Erros leyendo el archivo JSON {}", err),
-                    "Expense::list",
-                ));
-            }
+    // Elimina los gastos que cumplen la consulta y devuelve cuantos borro
+    pub fn delete(
+        storage: &mut dyn ExpenseStorage,
+        query: &ExpenseQuery,
+    ) -> Result<usize, ExpenseError> {
+        let location = "Expense::delete";
+        // una consulta sin filtros borraria todos los gastos
+        if query.selects_all() {
+            return Err(ExpenseError::new(
+                ExpenseErrorKind::MissingFilter,
+                "delete necesita --id o algun filtro",
+                location,
+            ));
+        }
+        let write_error = |err: StorageError| {
+            ExpenseError::new(
+                ExpenseErrorKind::WriteError,
+                &format!("Error escribiendo los datos {}", err),
+                location,
+            )
         };
 
-        let mut total: f64 = 0.0;
-
-        if description == "" {
-            for expense in expeneses_json {
-                match expense.get("amount") {
-                    Some(amount) => total = amount.as_f64().unwrap_or(0.0) + total,
-                    None => {}
-                }
+        // la busqueda y el borrado van en la misma transaccion para no borrar
+        // gastos que otro proceso haya cambiado entre medio
+        storage.begin().map_err(write_error)?;
+        let deleted = storage
+            .query(query)
+            .map_err(|err| read_error(err, location))
+            .and_then(|expenses| {
+                let ids: Vec<String> = expenses.into_iter().map(|expense| expense.id).collect();
+                storage.delete(&ids).map_err(write_error)
+            });
+
+        match deleted {
+            Ok(count) => {
+                storage.commit().map_err(write_error)?;
+                Ok(count)
             }
-        } else {
-            for expense in expeneses_json {
-                match expense.get("created_at") {
-                    Some(created_at) => {
-                        if let Some(date_str) = created_at.as_str() {
-                            // Parseamos la fecha en formato YYYY-MM-DD
-                            match NaiveDateTime::parse_from_str(date_str, "%Y-%m-%dT%H:%M:%S%.fZ") {
-                                Ok(date) => {
-                                    let month = date.month(); // Extraemos el mes
-                                    let description_month = match description.parse::<u32>() {
-                                        Ok(num) => num,
-                                        Err(err) => {
-                                            return Err(ExpenseError::new(
-                                                ExpenseErrorKind::InvalidMonth,
-                                                &format!("Formato de mes no valido {:?}", err),
-                                                "Expense::summary",
-                                            ));
-                                        }
-                                    };
-
-                                    if description_month < 1 || description_month > 12 {
-                                        return Err(ExpenseError::new(
-                                            ExpenseErrorKind::InvalidMonth,
-                                            &format!("Mes no valido"),
-                                            "Expense::summary",
-                                        ));
-                                    }
-
-                                    if month == description_month {
-                                        match expense.get("amount") {
-                                            Some(amount) => {
-                                                total = amount.as_f64().unwrap_or(0.0) + total
-                                            }
-                                            None => {}
-                                        }
-                                    }
-                                }
-                                Err(err) => {
-                                    return Err(ExpenseError::new(
-                                        ExpenseErrorKind::InvalidDateFormat,
-                                        &format!(
-                                            "Formato de fecha no válido: {:?}, Error: {:?}",
-                                            date_str, err
-                                        ),
-                                        "Expense::summary",
-                                    ));
-                                }
-                            }
-                        }
-                    }
-                    None => {}
-                }
+            Err(err) => {
+                let _ = storage.rollback();
+                Err(err)
             }
         }
+    }
+}
 
-        println!("Total expenses: ${total}");
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::{memory_storage::MemoryExpenseStorage, rates::ExchangeRate};
+    use crate::models::money::Rate;
 
-        Ok(())
+    fn date(text: &str) -> NaiveDate {
+        NaiveDate::parse_from_str(text, "%Y-%m-%d").unwrap()
     }
 
-    pub fn delete(ids_string: &str) -> Result<(), ExpenseError> {
-        let ids: Vec<String> = ids_string.split_whitespace().map(String::from).collect();
-        //se abre el archivo json
-        let mut expeneses_json = match open_json("./DB/expenses.json") {
-            Ok(JsonStructure::Array(vec)) => vec,
-            Ok(JsonStructure::Object(map)) => {
-                return Err(ExpenseError::new(
-                    ExpenseErrorKind::ReadError,
-                    &format!("Error en la estructura del JSON {:?}", map),
-                    "Expense:delete",
-                ))
-            }
-            Err(err) => {
-                return Err(ExpenseError::new(
-                    ExpenseErrorKind::ReadError,
-                    &format!("Erros leyendo el archivo JSON {}", err),
-                    "Expense::delete",
-                ));
-            }
+    fn add(
+        storage: &mut MemoryExpenseStorage,
+        description: &str,
+        amount: &str,
+        currency: &str,
+        category: Option<&str>,
+        tags: &[&str],
+    ) {
+        let tags: Vec<String> = tags.iter().map(|tag| tag.to_string()).collect();
+        Expense::build(
+            storage,
+            description,
+            amount,
+            currency,
+            category,
+            &tags,
+            date("2024-03-10"),
+        )
+        .unwrap();
+    }
+
+    fn all(storage: &MemoryExpenseStorage) -> Vec<Expense> {
+        Expense::list(storage, &ExpenseQuery::new()).unwrap()
+    }
+
+    #[test]
+    fn build_assigns_ids_and_normalizes_tags() {
+        let mut storage = MemoryExpenseStorage::new();
+        add(
+            &mut storage,
+            "Cafe",
+            "2700",
+            "COP",
+            Some("Comida"),
+            &["Trabajo", "viaje", "trabajo"],
+        );
+        add(&mut storage, "Taxi", "12.5", "USD", None, &[]);
+
+        let expenses = all(&storage);
+        assert_eq!(expenses.len(), 2);
+        assert_eq!(expenses[0].id, "1");
+        assert_eq!(expenses[0].amount, Money::parse("2700", "COP").unwrap());
+        assert_eq!(expenses[0].category.as_deref(), Some("Comida"));
+        assert_eq!(expenses[0].tags, ["trabajo", "viaje"]);
+        assert_eq!(expenses[0].spent_at, date("2024-03-10"));
+        assert!(expenses[0].updated_at.is_none());
+        assert_eq!(expenses[1].id, "2");
+        assert_eq!(expenses[1].amount.currency(), "USD");
+    }
+
+    #[test]
+    fn build_rejects_invalid_input_without_saving() {
+        let mut storage = MemoryExpenseStorage::new();
+        let today = date("2024-03-10");
+        let cases = [
+            ("  ", "10", ExpenseErrorKind::EmptyDescription),
+            ("Cafe", "0", ExpenseErrorKind::InvalidAmountType),
+            ("Cafe", "-5", ExpenseErrorKind::InvalidAmountType),
+            ("Cafe", "1.234", ExpenseErrorKind::InvalidAmountType),
+            ("Cafe", "diez", ExpenseErrorKind::InvalidAmountType),
+        ];
+        for (description, amount, expected) in cases {
+            let err = Expense::build(&mut storage, description, amount, "COP", None, &[], today)
+                .unwrap_err();
+            assert_eq!(
+                std::mem::discriminant(err.kind()),
+                std::mem::discriminant(&expected),
+                "{:?} {:?}",
+                description,
+                amount
+            );
+        }
+        assert!(all(&storage).is_empty());
+        // los intentos fallidos no consumen ids
+        add(&mut storage, "Cafe", "10", "COP", None, &[]);
+        assert_eq!(all(&storage)[0].id, "1");
+    }
+
+    #[test]
+    fn update_changes_only_the_given_fields() {
+        let mut storage = MemoryExpenseStorage::new();
+        add(
+            &mut storage,
+            "Cafe",
+            "2700",
+            "COP",
+            Some("Comida"),
+            &["trabajo"],
+        );
+
+        let changes = ExpenseUpdate {
+            amount: Some("3000".to_string()),
+            category: Some(None),
+            ..Default::default()
+        };
+        let updated = Expense::update(&mut storage, "1", changes).unwrap();
+        assert_eq!(updated.description, "Cafe");
+        assert_eq!(updated.amount, Money::parse("3000", "COP").unwrap());
+        assert_eq!(updated.category, None);
+        assert_eq!(updated.tags, ["trabajo"]);
+        assert!(updated.updated_at.is_some());
+
+        // solo --currency conserva el monto
+        let changes = ExpenseUpdate {
+            currency: Some("usd".to_string()),
+            ..Default::default()
         };
+        Expense::update(&mut storage, "1", changes).unwrap();
+        assert_eq!(
+            all(&storage)[0].amount,
+            Money::parse("3000", "USD").unwrap()
+        );
+    }
 
-        expeneses_json.retain(|expense| {
-            if let Some(id) = expense.get("id") {
-                if let Some(id_str) = id.as_str() {
-                    println!("{:?}", id_str);
-                    println!("{:?}", ids);
-                    !ids.contains(&id_str.to_string())
-                } else {
-                    true
-                }
-            } else {
-                true
-            }
-        });
+    #[test]
+    fn update_fails_without_changes_on_bad_input() {
+        let mut storage = MemoryExpenseStorage::new();
+        add(&mut storage, "Cafe", "2700", "COP", None, &[]);
 
-        println!("{:?}", expeneses_json);
+        let err = Expense::update(&mut storage, "9", ExpenseUpdate::default()).unwrap_err();
+        assert!(matches!(err.kind(), ExpenseErrorKind::IdsErrorType));
 
-        let json_structure = JsonStructure::Array(expeneses_json);
+        // 2700.5 no cabe en yenes: el gasto no cambia
+        let changes = ExpenseUpdate {
+            description: Some("Otro".to_string()),
+            currency: Some("JPY".to_string()),
+            ..Default::default()
+        };
+        let err = Expense::update(&mut storage, "1", changes).unwrap_err();
+        assert!(matches!(err.kind(), ExpenseErrorKind::InvalidAmountType));
+        let expense = &all(&storage)[0];
+        assert_eq!(expense.description, "Cafe");
+        assert!(expense.updated_at.is_none());
+    }
 
-        match save_json("./DB/expenses.json", json_structure) {
-            Ok(_) => (),
-            Err(err) => {
-                return Err(ExpenseError::new(
-                    ExpenseErrorKind::WriteError,
-                    &format!("Error escribiendo los datos {}", err),
-                    "Expense:delete",
-                ));
-            }
-        }
+    #[test]
+    fn delete_removes_the_selected_expenses() {
+        let mut storage = MemoryExpenseStorage::new();
+        add(&mut storage, "Cafe", "10", "COP", Some("Comida"), &[]);
+        add(&mut storage, "Taxi", "20", "COP", Some("Transporte"), &[]);
+        add(&mut storage, "Pan", "30", "COP", Some("Comida"), &[]);
+
+        let query = ExpenseQuery::new().with_category("comida");
+        assert_eq!(Expense::delete(&mut storage, &query).unwrap(), 2);
+        let ids: Vec<String> = all(&storage)
+            .into_iter()
+            .map(|expense| expense.id)
+            .collect();
+        assert_eq!(ids, ["2"]);
+
+        let query = ExpenseQuery::new().with_ids(vec!["7".to_string()]);
+        assert_eq!(Expense::delete(&mut storage, &query).unwrap(), 0);
+    }
 
-        Ok(())
+    #[test]
+    fn delete_refuses_a_query_without_filters() {
+        let mut storage = MemoryExpenseStorage::new();
+        add(&mut storage, "Cafe", "10", "COP", None, &[]);
+
+        let err = Expense::delete(&mut storage, &ExpenseQuery::new()).unwrap_err();
+        assert!(matches!(err.kind(), ExpenseErrorKind::MissingFilter));
+        assert_eq!(all(&storage).len(), 1);
+    }
+
+    #[test]
+    fn summary_converts_to_the_base_currency() {
+        let mut storage = MemoryExpenseStorage::new();
+        add(
+            &mut storage,
+            "Cafe",
+            "2700",
+            "COP",
+            Some("Comida > Cafe"),
+            &["trabajo"],
+        );
+        add(&mut storage, "Taxi", "10", "USD", Some("Transporte"), &[]);
+        add(
+            &mut storage,
+            "Pan",
+            "5",
+            "USD",
+            Some("Comida"),
+            &["trabajo", "casa"],
+        );
+        add(&mut storage, "Tren", "3", "EUR", None, &[]);
+
+        let rate = ExchangeRate::new(
+            date("2024-03-01"),
+            "USD",
+            "COP",
+            Rate::parse("4000").unwrap(),
+        )
+        .unwrap();
+        let rates = RateTable::with_rates(vec![rate]);
+        let cop = |amount: &str| Money::parse(amount, "COP").unwrap();
+
+        let summary =
+            Expense::summary(&storage, &ExpenseQuery::new(), None, &rates, "COP").unwrap();
+        assert_eq!(summary.total, cop("62700"));
+        assert_eq!(summary.converted.len(), 1);
+        assert_eq!(
+            summary.converted[0].original,
+            Money::parse("15", "USD").unwrap()
+        );
+        assert_eq!(summary.converted[0].converted, cop("60000"));
+        assert!(summary.groups.is_empty());
+        // sin tasa EUR -> COP el gasto queda fuera del total
+        assert_eq!(summary.missing_rate.len(), 1);
+        assert_eq!(summary.missing_rate[0].description, "Tren");
+
+        let by_category = Some(GroupBy::Category(None));
+        let summary =
+            Expense::summary(&storage, &ExpenseQuery::new(), by_category, &rates, "COP").unwrap();
+        let groups: Vec<(Option<&str>, Money)> = summary
+            .groups
+            .iter()
+            .map(|group| (group.name.as_deref(), group.total.clone()))
+            .collect();
+        assert_eq!(
+            groups,
+            [
+                (Some("Comida"), cop("22700")),
+                (Some("Comida > Cafe"), cop("2700")),
+                (Some("Transporte"), cop("40000")),
+            ]
+        );
+
+        let summary = Expense::summary(
+            &storage,
+            &ExpenseQuery::new(),
+            Some(GroupBy::Tag),
+            &rates,
+            "COP",
+        )
+        .unwrap();
+        let groups: Vec<(Option<&str>, Money)> = summary
+            .groups
+            .iter()
+            .map(|group| (group.name.as_deref(), group.total.clone()))
+            .collect();
+        assert_eq!(
+            groups,
+            [
+                (None, cop("40000")),
+                (Some("casa"), cop("20000")),
+                (Some("trabajo"), cop("22700")),
+            ]
+        );
+    }
+
+    #[test]
+    fn summary_uses_the_rate_of_the_opposite_pair() {
+        let mut storage = MemoryExpenseStorage::new();
+        add(&mut storage, "Tren", "9", "EUR", None, &[]);
+        add(&mut storage, "Taxi", "10", "USD", None, &[]);
+
+        let rate = ExchangeRate::new(
+            date("2024-03-01"),
+            "USD",
+            "EUR",
+            Rate::parse("0.9").unwrap(),
+        )
+        .unwrap();
+        let rates = RateTable::with_rates(vec![rate]);
+
+        let summary =
+            Expense::summary(&storage, &ExpenseQuery::new(), None, &rates, "USD").unwrap();
+        assert_eq!(summary.total, Money::parse("20", "USD").unwrap());
+        assert_eq!(
+            summary.converted[0].converted,
+            Money::parse("10", "USD").unwrap()
+        );
+        assert!(summary.missing_rate.is_empty());
+    }
+
+    #[test]
+    fn summary_of_an_empty_selection_is_zero() {
+        let mut storage = MemoryExpenseStorage::new();
+        add(&mut storage, "Cafe", "2700", "COP", None, &[]);
+
+        let query = ExpenseQuery::new().with_month(12);
+        let summary = Expense::summary(&storage, &query, None, &RateTable::new(), "COP").unwrap();
+        assert_eq!(summary.total, Money::zero("COP").unwrap());
+        assert!(summary.converted.is_empty());
+        assert!(summary.missing_rate.is_empty());
+    }
+
+    #[test]
+    fn parse_month_accepts_1_to_12() {
+        assert_eq!(Expense::parse_month("1").unwrap(), 1);
+        assert_eq!(Expense::parse_month("12").unwrap(), 12);
+        for month in ["0", "13", "marzo", ""] {
+            assert!(matches!(
+                Expense::parse_month(month).unwrap_err().kind(),
+                ExpenseErrorKind::InvalidMonth
+            ));
+        }
     }
 }
@@ -0,0 +1,160 @@
+use super::config::{flag_names, subcommands, COMMANDS};
+
+// Ayuda de un comando: que hace y como se escribe
+struct CommandHelp {
+    name: &'static str,
+    summary: &'static str,
+    usage: &'static [&'static str],
+}
+
+const HELP: &[CommandHelp] = &[
+    CommandHelp {
+        name: "add",
+        summary: "Agrega un gasto",
+        usage: &[
+            "add --description <texto> --amount <monto> [--currency <codigo>]",
+            "    [--category <categoria>] [--tags <etiqueta>...] [--date <fecha>]",
+            "La fecha acepta AAAA-MM-DD, DD/MM/AAAA, today, yesterday o -3d.",
+        ],
+    },
+    CommandHelp {
+        name: "update",
+        summary: "Cambia los campos de un gasto",
+        usage: &[
+            "update --id <id> [--description <texto>] [--amount <monto>] [--currency <codigo>]",
+            "    [--category <categoria> | --no-category] [--tags <etiqueta>... | --no-tags]",
+            "    [--date <fecha>]",
+        ],
+    },
+    CommandHelp {
+        name: "list",
+        summary: "Muestra los gastos",
+        usage: &[
+            "list [filtros] [--sort <campo>] [--desc] [--limit <n>] [--offset <n>]",
+            "    [--format <formato>]",
+            "Filtros: --month <mes>, --from <fecha>, --to <fecha>, --min-amount <monto>,",
+            "    --max-amount <monto>, --search <texto>, --regex <expresion>,",
+            "    --category <categoria>, --currency <codigo>, --tag <etiqueta>...",
+            "Se ordena por id, date, amount, description o category.",
+            "Formatos: table, json, jsonl, csv, tsv o markdown.",
+        ],
+    },
+    CommandHelp {
+        name: "summary",
+        summary: "Total de los gastos en la moneda base",
+        usage: &[
+            "summary [filtros] [--by category [--depth <niveles>] | --by tag]",
+            "    [--format <formato>]",
+            "Acepta los mismos filtros y formatos que list.",
+        ],
+    },
+    CommandHelp {
+        name: "delete",
+        summary: "Elimina gastos",
+        usage: &[
+            "delete --id <id>... | delete [filtros]",
+            "Borra todos los gastos que cumplen los filtros de list.",
+        ],
+    },
+    CommandHelp {
+        name: "category",
+        summary: "Administra el arbol de categorias",
+        usage: &[
+            "category add --name <categoria>",
+            "category rename --name <categoria> --to <nuevo nombre>",
+            "category merge --name <categoria> --into <otra categoria>",
+            "category delete --name <categoria>",
+            "category list",
+            "Las subcategorias se escriben como ruta: \"Transporte > Gasolina\".",
+        ],
+    },
+    CommandHelp {
+        name: "tag",
+        summary: "Administra las etiquetas de un gasto",
+        usage: &[
+            "tag add --id <id> --tags <etiqueta>...",
+            "tag remove --id <id> --tags <etiqueta>...",
+            "tag list",
+        ],
+    },
+    CommandHelp {
+        name: "rate",
+        summary: "Administra las tasas de cambio",
+        usage: &[
+            "rate set --from <codigo> --rate <tasa> [--to <codigo>] [--date <AAAA-MM-DD>]",
+            "rate get --from <codigo> [--to <codigo>] [--date <AAAA-MM-DD>]",
+            "rate import --file <csv>",
+            "rate list",
+        ],
+    },
+    CommandHelp {
+        name: "init",
+        summary: "Crea la base de datos si no existe y muestra su version",
+        usage: &["init"],
+    },
+    CommandHelp {
+        name: "check",
+        summary: "Revisa los registros sin cambiar nada",
+        usage: &["check"],
+    },
+    CommandHelp {
+        name: "repair",
+        summary: "Corrige lo que encuentra check y aparta lo que no se puede corregir",
+        usage: &["repair"],
+    },
+    CommandHelp {
+        name: "run",
+        summary: "Ejecuta un archivo de comandos, uno por linea",
+        usage: &[
+            "run <archivo> [--atomic]",
+            "Con - se leen los comandos de la entrada estandar. Las lineas vacias",
+            "y las que empiezan con # se saltan. Con --atomic el primer error",
+            "detiene el script y deshace todos sus cambios.",
+        ],
+    },
+    CommandHelp {
+        name: "help",
+        summary: "Muestra esta ayuda, o la de un comando",
+        usage: &["help [comando]"],
+    },
+];
+
+// Texto de `help`: la lista de comandos, o el uso de uno de ellos
+pub fn help(command: Option<&str>) -> String {
+    match command.and_then(|name| HELP.iter().find(|help| help.name == name)) {
+        Some(help) => command_help(help),
+        None => general_help(),
+    }
+}
+
+fn general_help() -> String {
+    let width = COMMANDS.iter().map(|name| name.len()).max().unwrap_or(0);
+    let mut text = String::from("Comandos:\n");
+    for name in COMMANDS {
+        let summary = HELP
+            .iter()
+            .find(|help| help.name == *name)
+            .map_or("", |help| help.summary);
+        text.push_str(&format!("  {:width$}  {}\n", name, summary, width = width));
+    }
+    text.push_str("\nUse help <comando> para ver sus flags. ");
+    text.push_str("En el modo interactivo, exit o quit (o Ctrl-D) terminan la sesion.\n");
+    text
+}
+
+fn command_help(help: &CommandHelp) -> String {
+    let mut text = format!("{}: {}\n\nUso:\n", help.name, help.summary);
+    for line in help.usage {
+        text.push_str(&format!("  {}\n", line));
+    }
+
+    let subcommands = subcommands(help.name);
+    if !subcommands.is_empty() {
+        text.push_str(&format!("\nSubcomandos: {}\n", subcommands.join(", ")));
+    }
+    let flags = flag_names(help.name);
+    if !flags.is_empty() {
+        text.push_str(&format!("Flags: {}\n", flags.join(", ")));
+    }
+    text
+}
@@ -1,3 +1,11 @@
 pub mod error;
+pub mod batch;
+pub mod category;
 pub mod config;
-pub mod expense;
\ No newline at end of file
+pub mod expense;
+pub mod help;
+pub mod money;
+pub mod output;
+pub mod repl;
+pub mod settings;
+pub mod tag;
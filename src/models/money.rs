@@ -0,0 +1,726 @@
+use std::{cmp::Ordering, fmt};
+
+use serde::{Deserialize, Serialize};
+
+use super::error::{MoneyError, MoneyErrorKind};
+
+// Moneda de los gastos que no indican otra
+pub const DEFAULT_CURRENCY: &str = "COP";
+
+// Cantidad de decimales de la mayoria de las monedas
+pub const DEFAULT_EXPONENT: u32 = 2;
+
+// Cantidad de decimales de las monedas (ISO 4217) que no usan `DEFAULT_EXPONENT`
+pub const CURRENCY_EXPONENTS: &[(&str, u32)] = &[
+    ("BHD", 3),
+    ("CLP", 0),
+    ("ISK", 0),
+    ("JPY", 0),
+    ("KRW", 0),
+    ("KWD", 3),
+    ("PYG", 0),
+    ("TND", 3),
+    ("VND", 0),
+];
+
+// Decimales de la moneda `currency`
+pub fn currency_exponent(currency: &str) -> u32 {
+    CURRENCY_EXPONENTS
+        .iter()
+        .find(|(code, _)| *code == currency)
+        .map(|(_, exponent)| *exponent)
+        .unwrap_or(DEFAULT_EXPONENT)
+}
+
+// Normaliza y valida un codigo de moneda de tres letras ("cop" -> "COP")
+pub fn parse_currency(currency: &str) -> Result<String, MoneyError> {
+    let code = currency.trim().to_ascii_uppercase();
+    if code.len() != 3 || !code.chars().all(|c| c.is_ascii_uppercase()) {
+        return Err(MoneyError::new(
+            MoneyErrorKind::InvalidCurrency,
+            &format!("La moneda debe ser un codigo ISO de tres letras: {}", currency),
+        ));
+    }
+    Ok(code)
+}
+
+// Monto exacto: un entero de unidades menores (centavos para COP o USD) y la
+// moneda. Las sumas no acumulan el error de redondeo de un `f64`.
+//
+// Se guarda como texto decimal junto a la moneda, por ejemplo
+// `"amount": "2700.00", "currency": "COP"`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "MoneyRecord", into = "MoneyRecord")]
+pub struct Money {
+    minor_units: i64,
+    currency: String,
+}
+
+// Forma en la que se guarda un `Money`
+#[derive(Serialize, Deserialize)]
+struct MoneyRecord {
+    amount: String,
+    currency: String,
+}
+
+impl TryFrom<MoneyRecord> for Money {
+    type Error = MoneyError;
+
+    fn try_from(record: MoneyRecord) -> Result<Self, Self::Error> {
+        Money::parse(&record.amount, &record.currency)
+    }
+}
+
+impl From<Money> for MoneyRecord {
+    fn from(money: Money) -> Self {
+        MoneyRecord {
+            amount: money.amount_string(),
+            currency: money.currency,
+        }
+    }
+}
+
+impl Money {
+    pub fn new(minor_units: i64, currency: &str) -> Result<Money, MoneyError> {
+        Ok(Money {
+            minor_units,
+            currency: parse_currency(currency)?,
+        })
+    }
+
+    pub fn zero(currency: &str) -> Result<Money, MoneyError> {
+        Money::new(0, currency)
+    }
+
+    // Lee un monto decimal como "2700", "2700.5" o "-12.25". No se aceptan mas
+    // decimales de los que tiene la moneda, ni separadores de miles.
+    pub fn parse(amount: &str, currency: &str) -> Result<Money, MoneyError> {
+        let currency = parse_currency(currency)?;
+        let exponent = currency_exponent(&currency);
+
+        let (value, scale) = parse_decimal(amount)?;
+        if scale > exponent {
+            return Err(MoneyError::new(
+                MoneyErrorKind::TooManyDecimals,
+                &format!(
+                    "El monto {} tiene mas de {} decimales, que es lo que admite {}",
+                    amount, exponent, currency
+                ),
+            ));
+        }
+
+        // los decimales que faltan se completan con ceros: "2700.5" -> 270050
+        let minor_units = value
+            .checked_mul(10i128.pow(exponent - scale))
+            .and_then(|value| i64::try_from(value).ok())
+            .ok_or(MoneyError::new(
+                MoneyErrorKind::Overflow,
+                &format!("El monto {} es demasiado grande", amount),
+            ))?;
+
+        Ok(Money {
+            minor_units,
+            currency,
+        })
+    }
+
+    // Convierte un monto guardado como `f64` por versiones anteriores. Se usa la
+    // representacion decimal mas corta del numero, que es la que escribio el
+    // usuario; si no cabe exactamente en los decimales de la moneda, falla.
+    pub fn from_f64(amount: f64, currency: &str) -> Result<Money, MoneyError> {
+        if !amount.is_finite() {
+            return Err(MoneyError::new(
+                MoneyErrorKind::InvalidAmount,
+                &format!("El monto no es un numero valido: {}", amount),
+            ));
+        }
+        Money::parse(&amount.to_string(), currency)
+    }
+
+    pub fn minor_units(&self) -> i64 {
+        self.minor_units
+    }
+
+    pub fn currency(&self) -> &str {
+        &self.currency
+    }
+
+    pub fn is_positive(&self) -> bool {
+        self.minor_units > 0
+    }
+
+    pub fn checked_add(&self, other: &Money) -> Result<Money, MoneyError> {
+        if self.currency != other.currency {
+            return Err(MoneyError::new(
+                MoneyErrorKind::CurrencyMismatch,
+                &format!(
+                    "No se pueden sumar montos en {} y {}",
+                    self.currency, other.currency
+                ),
+            ));
+        }
+
+        let minor_units = self
+            .minor_units
+            .checked_add(other.minor_units)
+            .ok_or(MoneyError::new(
+                MoneyErrorKind::Overflow,
+                "La suma de los montos es demasiado grande",
+            ))?;
+
+        Ok(Money {
+            minor_units,
+            currency: self.currency.clone(),
+        })
+    }
+
+    // Convierte el monto a `currency` con la tasa indicada, redondeando a la
+    // unidad menor mas cercana de la moneda destino
+    pub fn convert(&self, rate: &Rate, currency: &str) -> Result<Money, MoneyError> {
+        self.scale_to(currency, rate.value, 10i128.pow(rate.scale))
+    }
+
+    // Convierte el monto a `currency` con una tasa de `currency` a la moneda del
+    // monto, es decir, dividiendo por la tasa. El resultado es el mismo que con
+    // la tasa inversa, sin perder los decimales que no caben en un `Rate`.
+    pub fn convert_inverse(&self, rate: &Rate, currency: &str) -> Result<Money, MoneyError> {
+        self.scale_to(currency, 10i128.pow(rate.scale), rate.value)
+    }
+
+    // monto * multiplicador / divisor en `currency`, ajustando la diferencia de
+    // decimales entre monedas
+    fn scale_to(
+        &self,
+        currency: &str,
+        multiplier: i128,
+        divisor: i128,
+    ) -> Result<Money, MoneyError> {
+        let currency = parse_currency(currency)?;
+        let overflow = || {
+            MoneyError::new(
+                MoneyErrorKind::Overflow,
+                &format!("El monto {} es demasiado grande para convertirlo", self),
+            )
+        };
+
+        let numerator = i128::from(self.minor_units)
+            .checked_mul(multiplier)
+            .and_then(|value| value.checked_mul(10i128.pow(currency_exponent(&currency))))
+            .ok_or_else(overflow)?;
+        let denominator = divisor
+            .checked_mul(10i128.pow(currency_exponent(&self.currency)))
+            .ok_or_else(overflow)?;
+
+        let minor_units = i64::try_from(div_round(numerator, denominator)).map_err(|_| overflow())?;
+        Ok(Money {
+            minor_units,
+            currency,
+        })
+    }
+
+    // Valor del monto sin la moneda, para compararlo con otros montos o con los
+    // limites de un filtro
+    pub fn value(&self) -> Amount {
+        Amount {
+            value: i128::from(self.minor_units),
+            scale: currency_exponent(&self.currency),
+        }
+    }
+
+    // El monto sin la moneda, con todos los decimales de la moneda ("2700.00")
+    pub fn amount_string(&self) -> String {
+        let exponent = currency_exponent(&self.currency);
+        let sign = if self.minor_units < 0 { "-" } else { "" };
+        let units = self.minor_units.unsigned_abs();
+        if exponent == 0 {
+            return format!("{}{}", sign, units);
+        }
+
+        let scale = 10u64.pow(exponent);
+        format!(
+            "{}{}.{:0width$}",
+            sign,
+            units / scale,
+            units % scale,
+            width = exponent as usize
+        )
+    }
+}
+
+// Cantidad maxima de decimales de un `Amount`, la de las monedas con mas decimales
+pub const MAX_AMOUNT_DECIMALS: u32 = 3;
+
+// Numero decimal exacto sin moneda, como los limites de monto de un filtro. Se
+// compara por su valor: "50.5" es igual a 50.50 USD y a 50.50 EUR.
+#[derive(Debug, Clone, Copy)]
+pub struct Amount {
+    value: i128,
+    scale: u32,
+}
+
+impl Amount {
+    pub fn parse(amount: &str) -> Result<Amount, MoneyError> {
+        let (value, scale) = parse_decimal(amount)?;
+        if scale > MAX_AMOUNT_DECIMALS {
+            return Err(MoneyError::new(
+                MoneyErrorKind::TooManyDecimals,
+                &format!(
+                    "El monto {} tiene mas de {} decimales",
+                    amount, MAX_AMOUNT_DECIMALS
+                ),
+            ));
+        }
+        Ok(Amount { value, scale })
+    }
+
+    // Limite en unidades menores de una moneda con `exponent` decimales, para
+    // comparar con montos guardados como enteros. Si sobran decimales se
+    // redondea hacia arriba para un minimo (`round_up`) y hacia abajo para un
+    // maximo, asi la comparacion da lo mismo que con el valor exacto.
+    pub fn minor_units(&self, exponent: u32, round_up: bool) -> i64 {
+        let units = if exponent >= self.scale {
+            self.scaled(exponent)
+        } else {
+            let divisor = 10i128.pow(self.scale - exponent);
+            let floor = self.value.div_euclid(divisor);
+            match round_up && self.value.rem_euclid(divisor) != 0 {
+                true => floor + 1,
+                false => floor,
+            }
+        };
+        units.clamp(i64::MIN.into(), i64::MAX.into()) as i64
+    }
+
+    // Valor con `scale` decimales; `scale` nunca es menor que la del monto
+    fn scaled(&self, scale: u32) -> i128 {
+        self.value.saturating_mul(10i128.pow(scale - self.scale))
+    }
+}
+
+impl Ord for Amount {
+    fn cmp(&self, other: &Self) -> Ordering {
+        let scale = self.scale.max(other.scale);
+        self.scaled(scale).cmp(&other.scaled(scale))
+    }
+}
+
+impl PartialOrd for Amount {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for Amount {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Amount {}
+
+// Cantidad maxima de decimales de una tasa de cambio
+const MAX_RATE_DECIMALS: u32 = 12;
+
+// Tasa de cambio exacta: cuantas unidades de la moneda destino vale una unidad
+// de la moneda origen, por ejemplo "4200.50" pesos por dolar. Se guarda como
+// texto decimal para no perder precision.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct Rate {
+    value: i128,
+    scale: u32,
+}
+
+impl TryFrom<String> for Rate {
+    type Error = MoneyError;
+
+    fn try_from(rate: String) -> Result<Self, Self::Error> {
+        Rate::parse(&rate)
+    }
+}
+
+impl From<Rate> for String {
+    fn from(rate: Rate) -> Self {
+        rate.to_string()
+    }
+}
+
+impl Rate {
+    pub fn parse(rate: &str) -> Result<Rate, MoneyError> {
+        let (value, scale) = parse_decimal(rate).map_err(|err| match err.kind() {
+            MoneyErrorKind::InvalidAmount => MoneyError::new(
+                MoneyErrorKind::InvalidAmount,
+                &format!("La tasa de cambio no es un numero valido: {}", rate),
+            ),
+            _ => err,
+        })?;
+        if value <= 0 {
+            return Err(MoneyError::new(
+                MoneyErrorKind::InvalidAmount,
+                &format!("La tasa de cambio debe ser mayor a 0: {}", rate),
+            ));
+        }
+        if scale > MAX_RATE_DECIMALS {
+            return Err(MoneyError::new(
+                MoneyErrorKind::TooManyDecimals,
+                &format!(
+                    "La tasa de cambio {} tiene mas de {} decimales",
+                    rate, MAX_RATE_DECIMALS
+                ),
+            ));
+        }
+        Ok(Rate { value, scale })
+    }
+}
+
+impl fmt::Display for Rate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.scale == 0 {
+            return write!(f, "{}", self.value);
+        }
+        let scale = 10i128.pow(self.scale);
+        write!(
+            f,
+            "{}.{:0width$}",
+            self.value / scale,
+            self.value % scale,
+            width = self.scale as usize
+        )
+    }
+}
+
+// Lee un decimal con signo opcional y devuelve el valor sin la coma y la
+// cantidad de decimales: "-12.25" -> (-1225, 2)
+fn parse_decimal(text: &str) -> Result<(i128, u32), MoneyError> {
+    let invalid = || {
+        MoneyError::new(
+            MoneyErrorKind::InvalidAmount,
+            &format!("El monto no es un numero valido: {}", text),
+        )
+    };
+
+    let trimmed = text.trim();
+    let (negative, digits) = match trimmed.strip_prefix('-') {
+        Some(rest) => (true, rest),
+        None => (false, trimmed.strip_prefix('+').unwrap_or(trimmed)),
+    };
+    let (whole, fraction) = digits.split_once('.').unwrap_or((digits, ""));
+
+    if whole.is_empty() && fraction.is_empty() {
+        return Err(invalid());
+    }
+
+    let mut value: i128 = 0;
+    for c in whole.chars().chain(fraction.chars()) {
+        let digit = c.to_digit(10).ok_or_else(invalid)?;
+        value = value
+            .checked_mul(10)
+            .and_then(|value| value.checked_add(i128::from(digit)))
+            .ok_or(MoneyError::new(
+                MoneyErrorKind::Overflow,
+                &format!("El monto {} es demasiado grande", text),
+            ))?;
+    }
+
+    let scale = u32::try_from(fraction.len()).map_err(|_| invalid())?;
+    Ok((if negative { -value } else { value }, scale))
+}
+
+// Divide redondeando al entero mas cercano; los empates se alejan del cero
+fn div_round(numerator: i128, denominator: i128) -> i128 {
+    let quotient = numerator / denominator;
+    let remainder = numerator % denominator;
+    if remainder.abs() * 2 >= denominator.abs() {
+        quotient + numerator.signum() * denominator.signum()
+    } else {
+        quotient
+    }
+}
+
+impl fmt::Display for Money {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {}", self.amount_string(), self.currency)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn money(amount: &str, currency: &str) -> Money {
+        Money::parse(amount, currency).unwrap()
+    }
+
+    fn error(result: Result<Money, MoneyError>) -> MoneyError {
+        result.unwrap_err()
+    }
+
+    #[test]
+    fn parse_fills_missing_decimals() {
+        assert_eq!(money("2700", "COP").minor_units(), 270000);
+        assert_eq!(money("2700.5", "COP").minor_units(), 270050);
+        assert_eq!(money(" +12.25 ", "usd").minor_units(), 1225);
+        assert_eq!(money("12.25", "usd").currency(), "USD");
+    }
+
+    #[test]
+    fn parse_uses_the_currency_exponent() {
+        assert_eq!(money("1500", "JPY").minor_units(), 1500);
+        assert_eq!(money("1500", "JPY").amount_string(), "1500");
+        assert_eq!(money("1.5", "KWD").minor_units(), 1500);
+        assert_eq!(money("1.5", "KWD").amount_string(), "1.500");
+        assert_eq!(money("1.5", "EUR").amount_string(), "1.50");
+    }
+
+    #[test]
+    fn parse_rejects_too_many_decimals() {
+        assert!(matches!(
+            error(Money::parse("12.345", "COP")).kind(),
+            MoneyErrorKind::TooManyDecimals
+        ));
+        assert!(matches!(
+            error(Money::parse("10.5", "JPY")).kind(),
+            MoneyErrorKind::TooManyDecimals
+        ));
+        assert!(matches!(
+            error(Money::parse("1.0005", "BHD")).kind(),
+            MoneyErrorKind::TooManyDecimals
+        ));
+        assert_eq!(money("1.005", "BHD").minor_units(), 1005);
+    }
+
+    #[test]
+    fn parse_rejects_invalid_text_and_currency() {
+        for amount in ["", ".", "-", "1,000", "1e5", "abc", "1.2.3"] {
+            assert!(
+                matches!(
+                    error(Money::parse(amount, "COP")).kind(),
+                    MoneyErrorKind::InvalidAmount
+                ),
+                "{:?}",
+                amount
+            );
+        }
+        assert!(matches!(
+            error(Money::parse("1", "US")).kind(),
+            MoneyErrorKind::InvalidCurrency
+        ));
+        assert!(matches!(
+            error(Money::parse("1", "U$D")).kind(),
+            MoneyErrorKind::InvalidCurrency
+        ));
+    }
+
+    #[test]
+    fn parse_detects_overflow_of_minor_units() {
+        // i64::MAX en centavos son 92233720368547758.07
+        assert_eq!(money("92233720368547758.07", "USD").minor_units(), i64::MAX);
+        assert!(matches!(
+            error(Money::parse("92233720368547758.08", "USD")).kind(),
+            MoneyErrorKind::Overflow
+        ));
+        assert!(matches!(
+            error(Money::parse(
+                "99999999999999999999999999999999999999999",
+                "USD"
+            ))
+            .kind(),
+            MoneyErrorKind::Overflow
+        ));
+    }
+
+    #[test]
+    fn negative_and_zero_amounts() {
+        let negative = money("-12.05", "COP");
+        assert_eq!(negative.minor_units(), -1205);
+        assert_eq!(negative.amount_string(), "-12.05");
+        assert!(!negative.is_positive());
+
+        let zero = money("0", "COP");
+        assert_eq!(zero, Money::zero("COP").unwrap());
+        assert_eq!(zero.amount_string(), "0.00");
+        assert!(!zero.is_positive());
+        assert_eq!(money("-0.5", "COP").amount_string(), "-0.50");
+    }
+
+    #[test]
+    fn from_f64_uses_the_shortest_decimal() {
+        assert_eq!(
+            Money::from_f64(2700.5, "COP").unwrap().minor_units(),
+            270050
+        );
+        assert_eq!(Money::from_f64(0.1, "USD").unwrap().minor_units(), 10);
+        // 0.1 + 0.2 es 0.30000000000000004: no cabe en centavos
+        assert!(matches!(
+            error(Money::from_f64(0.1 + 0.2, "USD")).kind(),
+            MoneyErrorKind::TooManyDecimals
+        ));
+        assert!(matches!(
+            error(Money::from_f64(f64::NAN, "USD")).kind(),
+            MoneyErrorKind::InvalidAmount
+        ));
+        assert!(matches!(
+            error(Money::from_f64(f64::INFINITY, "USD")).kind(),
+            MoneyErrorKind::InvalidAmount
+        ));
+    }
+
+    #[test]
+    fn checked_add_needs_the_same_currency() {
+        let total = money("0.10", "USD")
+            .checked_add(&money("0.20", "USD"))
+            .unwrap();
+        assert_eq!(total, money("0.30", "USD"));
+        assert!(matches!(
+            error(money("1", "USD").checked_add(&money("1", "EUR"))).kind(),
+            MoneyErrorKind::CurrencyMismatch
+        ));
+        let max = Money::new(i64::MAX, "USD").unwrap();
+        assert!(matches!(
+            error(max.checked_add(&money("0.01", "USD"))).kind(),
+            MoneyErrorKind::Overflow
+        ));
+    }
+
+    #[test]
+    fn convert_rounds_half_away_from_zero() {
+        let rate = Rate::parse("0.5").unwrap();
+        // 0.01 * 0.5 = 0.005 -> 0.01; -0.01 * 0.5 = -0.005 -> -0.01
+        assert_eq!(
+            money("0.01", "USD").convert(&rate, "EUR").unwrap(),
+            money("0.01", "EUR")
+        );
+        assert_eq!(
+            money("-0.01", "USD").convert(&rate, "EUR").unwrap(),
+            money("-0.01", "EUR")
+        );
+        // 0.03 * 0.5 = 0.015 -> 0.02
+        assert_eq!(
+            money("0.03", "USD").convert(&rate, "EUR").unwrap(),
+            money("0.02", "EUR")
+        );
+
+        let rate = Rate::parse("0.333").unwrap();
+        // 0.10 * 0.333 = 0.0333 -> 0.03
+        assert_eq!(
+            money("0.10", "USD").convert(&rate, "EUR").unwrap(),
+            money("0.03", "EUR")
+        );
+    }
+
+    #[test]
+    fn convert_adjusts_the_exponent_between_currencies() {
+        let rate = Rate::parse("4210.50").unwrap();
+        assert_eq!(
+            money("10.00", "USD").convert(&rate, "COP").unwrap(),
+            money("42105.00", "COP")
+        );
+
+        let rate = Rate::parse("0.0067").unwrap();
+        // 1500 JPY * 0.0067 = 10.05 USD
+        assert_eq!(
+            money("1500", "JPY").convert(&rate, "USD").unwrap(),
+            money("10.05", "USD")
+        );
+
+        let rate = Rate::parse("149.5").unwrap();
+        // 1.01 USD * 149.5 = 150.995 -> 151 JPY
+        assert_eq!(
+            money("1.01", "USD").convert(&rate, "JPY").unwrap(),
+            money("151", "JPY")
+        );
+
+        let rate = Rate::parse("0.307").unwrap();
+        assert_eq!(
+            money("1", "USD").convert(&rate, "KWD").unwrap(),
+            money("0.307", "KWD")
+        );
+    }
+
+    #[test]
+    fn convert_detects_overflow() {
+        let rate = Rate::parse("1000").unwrap();
+        let max = Money::new(i64::MAX, "USD").unwrap();
+        assert!(matches!(
+            error(max.convert(&rate, "EUR")).kind(),
+            MoneyErrorKind::Overflow
+        ));
+    }
+
+    #[test]
+    fn convert_inverse_divides_by_the_rate() {
+        // 1 USD = 4000 COP: 10000 COP son 2.50 USD
+        let rate = Rate::parse("4000").unwrap();
+        assert_eq!(
+            money("10000", "COP").convert_inverse(&rate, "USD").unwrap(),
+            money("2.50", "USD")
+        );
+        // 1 USD = 3 EUR: 1 EUR = 0.333... USD -> 0.33, sin pasar por una tasa redondeada
+        let rate = Rate::parse("3").unwrap();
+        assert_eq!(
+            money("1", "EUR").convert_inverse(&rate, "USD").unwrap(),
+            money("0.33", "USD")
+        );
+        assert_eq!(
+            money("3000000000", "EUR").convert_inverse(&rate, "USD").unwrap(),
+            money("1000000000", "USD")
+        );
+        // 1 USD = 149.5 JPY: 1 JPY = 0.00668... USD -> 0.01
+        let rate = Rate::parse("149.5").unwrap();
+        assert_eq!(
+            money("1", "JPY").convert_inverse(&rate, "USD").unwrap(),
+            money("0.01", "USD")
+        );
+    }
+
+    #[test]
+    fn div_round_ties_go_away_from_zero() {
+        assert_eq!(div_round(5, 10), 1);
+        assert_eq!(div_round(4, 10), 0);
+        assert_eq!(div_round(-5, 10), -1);
+        assert_eq!(div_round(-4, 10), 0);
+        assert_eq!(div_round(5, -10), -1);
+        assert_eq!(div_round(-15, -10), 2);
+        assert_eq!(div_round(25, 10), 3);
+        assert_eq!(div_round(20, 10), 2);
+    }
+
+    #[test]
+    fn amount_compares_by_value() {
+        let amount = |text: &str| Amount::parse(text).unwrap();
+        assert_eq!(amount("50.5"), amount("50.500"));
+        assert!(amount("50.49") < amount("50.5"));
+        assert!(amount("-1") < amount("0"));
+        assert_eq!(money("50.50", "USD").value(), amount("50.5"));
+        assert_eq!(money("51", "JPY").value(), amount("51.00"));
+        assert!(matches!(
+            Amount::parse("1.0001").unwrap_err().kind(),
+            MoneyErrorKind::TooManyDecimals
+        ));
+    }
+
+    #[test]
+    fn rate_parse_and_display() {
+        assert_eq!(Rate::parse("4210.50").unwrap().to_string(), "4210.50");
+        assert_eq!(Rate::parse("4000").unwrap().to_string(), "4000");
+        assert_eq!(Rate::parse("0.000123").unwrap().to_string(), "0.000123");
+        for rate in ["0", "-1", "abc", "0.0000000000001"] {
+            assert!(Rate::parse(rate).is_err(), "{:?}", rate);
+        }
+    }
+
+    #[test]
+    fn money_is_stored_as_decimal_text() {
+        let json = serde_json::to_value(money("2700", "COP")).unwrap();
+        assert_eq!(
+            json,
+            serde_json::json!({"amount": "2700.00", "currency": "COP"})
+        );
+        let parsed: Money = serde_json::from_value(json).unwrap();
+        assert_eq!(parsed, money("2700", "COP"));
+        assert!(serde_json::from_value::<Money>(
+            serde_json::json!({"amount": "1.234", "currency": "COP"})
+        )
+        .is_err());
+    }
+}
@@ -0,0 +1,474 @@
+use chrono::{Local, SecondsFormat};
+use prettytable::{row, Table};
+use serde_json::{json, Map, Value};
+
+use super::{
+    batch::LineResult,
+    category::CategoryCount,
+    expense::{Expense, GroupBy, Summary},
+    tag::{format_tags, TagCount},
+};
+use crate::data::{categories::category_levels, rates::ExchangeRate};
+
+// Formato de salida de `list` y `summary`. Los formatos distintos de la tabla
+// son para otros programas: nombres de campo fijos en ingles, fechas ISO 8601
+// y montos como texto decimal.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub enum OutputFormat {
+    #[default]
+    Table,
+    // un arreglo JSON
+    Json,
+    // un objeto JSON por linea
+    Jsonl,
+    Csv,
+    Tsv,
+    Markdown,
+}
+
+impl OutputFormat {
+    pub const NAMES: &'static str = "table, json, jsonl, csv, tsv, markdown";
+
+    pub fn parse(name: &str) -> Option<OutputFormat> {
+        match name.to_lowercase().as_str() {
+            "table" => Some(OutputFormat::Table),
+            "json" => Some(OutputFormat::Json),
+            "jsonl" => Some(OutputFormat::Jsonl),
+            "csv" => Some(OutputFormat::Csv),
+            "tsv" => Some(OutputFormat::Tsv),
+            "markdown" => Some(OutputFormat::Markdown),
+            _ => None,
+        }
+    }
+}
+
+// Nombre del grupo de los gastos sin categoria en la tabla de `summary`
+const UNCATEGORIZED: &str = "(sin categoria)";
+// Nombre del grupo de los gastos sin etiquetas en la tabla de `summary`
+const UNTAGGED: &str = "(sin etiquetas)";
+
+// Campos de cada gasto en los formatos distintos de la tabla
+pub const EXPENSE_FIELDS: &[&str] = &[
+    "id",
+    "description",
+    "amount",
+    "currency",
+    "category",
+    "tags",
+    "spent_at",
+    "created_at",
+    "updated_at",
+];
+
+// Columnas de `summary` en los formatos de una fila por dato. `kind` es
+// total, currency (subtotal de una moneda; `base_amount` es el convertido),
+// category, tag o missing_rate (un gasto sin tasa; `name` es su id).
+pub const SUMMARY_FIELDS: &[&str] = &["kind", "name", "amount", "currency", "base_amount"];
+
+// Texto de los gastos de `list` en el formato pedido, terminado en salto de
+// linea
+pub fn render_expenses(expenses: &[Expense], format: OutputFormat) -> String {
+    match format {
+        OutputFormat::Table => {
+            let mut table = Table::new();
+            table.add_row(row![
+                "ID",
+                "Descripción",
+                "Categoría",
+                "Etiquetas",
+                "Monto",
+                "Fecha",
+                "Fecha de Creación"
+            ]);
+            for expense in expenses {
+                let local_time = expense.created_at.with_timezone(&Local);
+                table.add_row(row![
+                    expense.id,
+                    expense.description,
+                    expense.category.as_deref().unwrap_or(""),
+                    format_tags(&expense.tags),
+                    expense.amount.to_string(),
+                    expense.spent_at,
+                    local_time.format("%Y-%m-%d %H:%M:%S")
+                ]);
+            }
+            table.to_string()
+        }
+        OutputFormat::Json => {
+            let records: Vec<Value> = expenses.iter().map(expense_record).collect();
+            format!("{}\n", Value::Array(records))
+        }
+        OutputFormat::Jsonl => expenses
+            .iter()
+            .map(|expense| format!("{}\n", expense_record(expense)))
+            .collect(),
+        _ => {
+            let rows = expenses
+                .iter()
+                .map(|expense| {
+                    let record = expense_record(expense);
+                    EXPENSE_FIELDS
+                        .iter()
+                        .map(|field| match &record[*field] {
+                            Value::String(text) => text.clone(),
+                            Value::Array(tags) => {
+                                let tags: Vec<&str> = tags.iter().filter_map(Value::as_str).collect();
+                                tags.join(" ")
+                            }
+                            _ => String::new(),
+                        })
+                        .collect()
+                })
+                .collect();
+            render_rows(format, EXPENSE_FIELDS, rows)
+        }
+    }
+}
+
+// Gasto con todos los campos de `EXPENSE_FIELDS`; los que no tiene van en null
+pub fn expense_record(expense: &Expense) -> Value {
+    json!({
+        "id": expense.id,
+        "description": expense.description,
+        "amount": expense.amount.amount_string(),
+        "currency": expense.amount.currency(),
+        "category": expense.category,
+        "tags": expense.tags,
+        "spent_at": expense.spent_at.to_string(),
+        "created_at": expense.created_at.to_rfc3339_opts(SecondsFormat::Secs, true),
+        "updated_at": expense
+            .updated_at
+            .map(|date| date.to_rfc3339_opts(SecondsFormat::Secs, true)),
+    })
+}
+
+// Texto del resumen de `summary` en el formato pedido, terminado en salto de
+// linea
+pub fn render_summary(summary: &Summary, format: OutputFormat) -> String {
+    match format {
+        OutputFormat::Table => summary_table(summary),
+        OutputFormat::Json => format!("{}\n", summary_record(summary)),
+        OutputFormat::Jsonl => summary_rows(summary)
+            .into_iter()
+            .map(|row| {
+                // las celdas vacias van en null
+                let record: Map<String, Value> = SUMMARY_FIELDS
+                    .iter()
+                    .zip(row)
+                    .map(|(field, cell)| {
+                        let value = match cell.is_empty() {
+                            true => Value::Null,
+                            false => Value::String(cell),
+                        };
+                        (field.to_string(), value)
+                    })
+                    .collect();
+                format!("{}\n", Value::Object(record))
+            })
+            .collect(),
+        _ => render_rows(format, SUMMARY_FIELDS, summary_rows(summary)),
+    }
+}
+
+// El resumen como un objeto JSON
+pub fn summary_record(summary: &Summary) -> Value {
+    let converted: Vec<Value> = summary
+        .converted
+        .iter()
+        .map(|subtotal| {
+            json!({
+                "currency": subtotal.original.currency(),
+                "amount": subtotal.original.amount_string(),
+                "base_amount": subtotal.converted.amount_string(),
+            })
+        })
+        .collect();
+    let groups: Vec<Value> = summary
+        .groups
+        .iter()
+        .map(|group| json!({ "name": group.name, "amount": group.total.amount_string() }))
+        .collect();
+    let missing_rate: Vec<Value> = summary
+        .missing_rate
+        .iter()
+        .map(|expense| {
+            json!({
+                "id": expense.id,
+                "amount": expense.amount.amount_string(),
+                "currency": expense.amount.currency(),
+                "spent_at": expense.spent_at.to_string(),
+            })
+        })
+        .collect();
+
+    json!({
+        "currency": summary.total.currency(),
+        "total": summary.total.amount_string(),
+        "converted": converted,
+        "group_by": summary.group_by.map(group_kind),
+        "groups": groups,
+        "missing_rate": missing_rate,
+    })
+}
+
+fn summary_table(summary: &Summary) -> String {
+    let mut text = format!("Total expenses: {}\n", summary.total);
+    for subtotal in &summary.converted {
+        text.push_str(&format!("  {} -> {}\n", subtotal.original, subtotal.converted));
+    }
+
+    if let Some(group_by) = summary.group_by {
+        let (header, empty) = match group_by {
+            GroupBy::Category(_) => ("Categoría", UNCATEGORIZED),
+            GroupBy::Tag => ("Etiqueta", UNTAGGED),
+        };
+        let mut table = Table::new();
+        table.add_row(row![header, "Total"]);
+        for group in &summary.groups {
+            let label = match (&group.name, group_by) {
+                (None, _) => empty.to_string(),
+                (Some(name), GroupBy::Category(_)) => tree_label(name),
+                (Some(name), GroupBy::Tag) => format!("#{}", name),
+            };
+            table.add_row(row![label, group.total.to_string()]);
+        }
+        text.push_str(&table.to_string());
+    }
+
+    if !summary.missing_rate.is_empty() {
+        text.push_str(&format!(
+            "Sin tasa de cambio a {}, no se incluyen en el total:\n",
+            summary.total.currency()
+        ));
+        for expense in &summary.missing_rate {
+            text.push_str(&format!(
+                "  - id {}: {} del {}\n",
+                expense.id, expense.amount, expense.spent_at
+            ));
+        }
+    }
+    text
+}
+
+// Nombre del desglose en los formatos distintos de la tabla
+fn group_kind(group_by: GroupBy) -> &'static str {
+    match group_by {
+        GroupBy::Category(_) => "category",
+        GroupBy::Tag => "tag",
+    }
+}
+
+// Filas de `summary` con las columnas de `SUMMARY_FIELDS`
+fn summary_rows(summary: &Summary) -> Vec<Vec<String>> {
+    let total = summary.total.amount_string();
+    let base = summary.total.currency().to_string();
+    let mut rows = vec![vec![
+        "total".to_string(),
+        String::new(),
+        total.clone(),
+        base.clone(),
+        total,
+    ]];
+    for subtotal in &summary.converted {
+        rows.push(vec![
+            "currency".to_string(),
+            subtotal.original.currency().to_string(),
+            subtotal.original.amount_string(),
+            subtotal.original.currency().to_string(),
+            subtotal.converted.amount_string(),
+        ]);
+    }
+    if let Some(group_by) = summary.group_by {
+        for group in &summary.groups {
+            let amount = group.total.amount_string();
+            rows.push(vec![
+                group_kind(group_by).to_string(),
+                group.name.clone().unwrap_or_default(),
+                amount.clone(),
+                base.clone(),
+                amount,
+            ]);
+        }
+    }
+    for expense in &summary.missing_rate {
+        rows.push(vec![
+            "missing_rate".to_string(),
+            expense.id.clone(),
+            expense.amount.amount_string(),
+            expense.amount.currency().to_string(),
+            String::new(),
+        ]);
+    }
+    rows
+}
+
+// Tabla en CSV, TSV o Markdown, con una fila de encabezado
+fn render_rows(format: OutputFormat, header: &[&str], rows: Vec<Vec<String>>) -> String {
+    let header: Vec<String> = header.iter().map(|field| field.to_string()).collect();
+    let mut text = String::new();
+    for (index, row) in std::iter::once(header).chain(rows).enumerate() {
+        match format {
+            OutputFormat::Csv => {
+                let cells: Vec<String> = row.iter().map(|cell| csv_cell(cell)).collect();
+                text.push_str(&format!("{}\n", cells.join(",")));
+            }
+            OutputFormat::Tsv => {
+                // TSV no tiene comillas: los tabs y saltos de linea se cambian por espacios
+                let cells: Vec<String> = row
+                    .iter()
+                    .map(|cell| cell.replace(['\t', '\n', '\r'], " "))
+                    .collect();
+                text.push_str(&format!("{}\n", cells.join("\t")));
+            }
+            _ => {
+                let cells: Vec<String> = row
+                    .iter()
+                    .map(|cell| cell.replace('|', "\\|").replace(['\n', '\r'], " "))
+                    .collect();
+                text.push_str(&format!("| {} |\n", cells.join(" | ")));
+                if index == 0 {
+                    text.push_str(&format!("|{}|\n", vec!["---"; cells.len()].join("|")));
+                }
+            }
+        }
+    }
+    text
+}
+
+// Celda CSV (RFC 4180): entre comillas si tiene comas, comillas o saltos de linea
+fn csv_cell(cell: &str) -> String {
+    if cell.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", cell.replace('"', "\"\""))
+    } else {
+        cell.to_string()
+    }
+}
+
+// Arbol de `category list` con la cantidad de gastos de cada categoria
+pub fn render_categories(categories: &[CategoryCount]) -> String {
+    let mut table = Table::new();
+    table.add_row(row!["Categoría", "Gastos", "Con subcategorías"]);
+    for category in categories {
+        table.add_row(row![
+            tree_label(&category.name),
+            category.expenses,
+            category.with_subcategories
+        ]);
+    }
+    table.to_string()
+}
+
+// Tabla de `tag list`
+pub fn render_tags(tags: &[TagCount]) -> String {
+    let mut table = Table::new();
+    table.add_row(row!["Etiqueta", "Gastos"]);
+    for tag in tags {
+        table.add_row(row![format!("#{}", tag.tag), tag.expenses]);
+    }
+    table.to_string()
+}
+
+// Tabla de `rate list`
+pub fn render_rates(rates: &[ExchangeRate]) -> String {
+    let mut table = Table::new();
+    table.add_row(row!["Fecha", "Desde", "Hacia", "Tasa"]);
+    for rate in rates {
+        table.add_row(row![rate.date, rate.from, rate.to, rate.rate]);
+    }
+    table.to_string()
+}
+
+// Linea de `run` con el resultado de un comando del script
+pub fn render_line_result(result: &LineResult) -> String {
+    match result {
+        LineResult::Ok(line) => format!("linea {}: ok\n", line),
+        LineResult::Failed(error) => format!("{}\n", error),
+    }
+}
+
+// Nombre de la categoria para mostrarla en un arbol: el ultimo nivel, con
+// sangria segun la profundidad
+fn tree_label(name: &str) -> String {
+    let levels = category_levels(name);
+    format!(
+        "{}{}",
+        "  ".repeat(levels.len() - 1),
+        levels.last().unwrap_or(&name)
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::money::Money;
+    use chrono::{NaiveDate, TimeZone, Utc};
+
+    // Celda con todos los caracteres que cada formato tiene que escapar
+    const TRICKY: &str = "a,b \"c\"\nd\te|f";
+
+    fn rows(format: OutputFormat) -> String {
+        let rows = vec![
+            vec![TRICKY.to_string(), "simple".to_string()],
+            vec![String::new(), "x".to_string()],
+        ];
+        render_rows(format, &["name", "value"], rows)
+    }
+
+    #[test]
+    fn csv_quotes_cells_with_separators_quotes_or_newlines() {
+        assert_eq!(csv_cell("simple"), "simple");
+        assert_eq!(csv_cell("a|b\tc"), "a|b\tc");
+        assert_eq!(csv_cell("a,b"), "\"a,b\"");
+        assert_eq!(csv_cell("dice \"hola\""), "\"dice \"\"hola\"\"\"");
+        assert_eq!(csv_cell("linea\notra"), "\"linea\notra\"");
+        assert_eq!(csv_cell("linea\r\notra"), "\"linea\r\notra\"");
+
+        assert_eq!(
+            rows(OutputFormat::Csv),
+            "name,value\n\"a,b \"\"c\"\"\nd\te|f\",simple\n,x\n"
+        );
+    }
+
+    #[test]
+    fn tsv_replaces_tabs_and_newlines_with_spaces() {
+        assert_eq!(
+            rows(OutputFormat::Tsv),
+            "name\tvalue\na,b \"c\" d e|f\tsimple\n\tx\n"
+        );
+    }
+
+    #[test]
+    fn markdown_escapes_pipes_and_joins_lines() {
+        assert_eq!(
+            rows(OutputFormat::Markdown),
+            "| name | value |\n|---|---|\n| a,b \"c\" d\te\\|f | simple |\n|  | x |\n"
+        );
+    }
+
+    #[test]
+    fn every_expense_is_one_line_in_tsv_and_markdown() {
+        let expense = Expense {
+            id: "7".to_string(),
+            description: TRICKY.to_string(),
+            amount: Money::parse("12.5", "USD").unwrap(),
+            category: Some("Comida > Cafe".to_string()),
+            tags: vec!["a".to_string(), "b".to_string()],
+            spent_at: NaiveDate::from_ymd_opt(2024, 3, 10).unwrap(),
+            created_at: Utc.with_ymd_and_hms(2024, 3, 10, 12, 0, 0).unwrap(),
+            updated_at: None,
+        };
+        let expenses = [expense.clone(), expense];
+
+        let csv = render_expenses(&expenses, OutputFormat::Csv);
+        let cells = "7,\"a,b \"\"c\"\"\nd\te|f\",12.50,USD,Comida > Cafe,a b,2024-03-10,";
+        assert!(csv.contains(cells));
+        let tsv = render_expenses(&expenses, OutputFormat::Tsv);
+        assert_eq!(tsv.lines().count(), 3);
+        assert!(tsv
+            .lines()
+            .all(|line| line.split('\t').count() == EXPENSE_FIELDS.len()));
+        let markdown = render_expenses(&expenses, OutputFormat::Markdown);
+        assert_eq!(markdown.lines().count(), 4);
+        assert!(markdown.contains("| 7 | a,b \"c\" d\te\\|f | 12.50 | USD |"));
+    }
+}
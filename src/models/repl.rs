@@ -0,0 +1,212 @@
+use std::path::{Path, PathBuf};
+
+use rustyline::{
+    completion::{Completer, Pair},
+    error::ReadlineError,
+    highlight::Highlighter,
+    hint::Hinter,
+    history::DefaultHistory,
+    validate::Validator,
+    CompletionType, Config as EditorConfig, Context, Editor, Helper,
+};
+
+use super::{
+    config::{flag_names, subcommands, COMMANDS},
+    error::InputError,
+    output::OutputFormat,
+};
+use crate::data::{categories::CategoryStore, query::SortKey};
+
+pub const PROMPT: &str = "expense-tracker ";
+// archivo del historial de comandos, dentro del directorio de datos
+pub const HISTORY_FILE: &str = "history.txt";
+const HISTORY_SIZE: usize = 1000;
+
+// Comandos que solo existen en el modo interactivo
+const SESSION_COMMANDS: &[&str] = &["exit", "quit"];
+
+// Flags cuyo valor es una categoria, por comando
+const CATEGORY_FLAGS: &[(&str, &str)] = &[
+    ("add", "--category"),
+    ("update", "--category"),
+    ("list", "--category"),
+    ("summary", "--category"),
+    ("delete", "--category"),
+    ("category", "--name"),
+    ("category", "--to"),
+    ("category", "--into"),
+];
+
+// Modo interactivo: lee los comandos con edicion de linea, historial
+// persistente y autocompletado de comandos, flags y categorias
+pub struct Repl {
+    editor: Editor<CommandHelper, DefaultHistory>,
+    history_path: PathBuf,
+}
+
+impl Repl {
+    pub fn new(data_dir: &str) -> Result<Repl, InputError> {
+        let config = EditorConfig::builder()
+            .max_history_size(HISTORY_SIZE)
+            .and_then(|builder| builder.history_ignore_dups(true))
+            .map_err(|err| editor_error("No se pudo configurar el editor de lineas", err))?
+            .completion_type(CompletionType::List)
+            .build();
+        let mut editor = Editor::with_config(config)
+            .map_err(|err| editor_error("No se pudo abrir el editor de lineas", err))?;
+        editor.set_helper(Some(CommandHelper {
+            data_dir: data_dir.to_string(),
+        }));
+
+        let history_path = Path::new(data_dir).join(HISTORY_FILE);
+        if history_path.exists() {
+            editor
+                .load_history(&history_path)
+                .map_err(|err| editor_error("No se pudo leer el historial de comandos", err))?;
+        }
+
+        Ok(Repl {
+            editor,
+            history_path,
+        })
+    }
+
+    // Siguiente comando escrito; `None` cuando se termina la sesion con exit,
+    // quit o Ctrl-D. Ctrl-C descarta la linea y las lineas vacias se ignoran.
+    pub fn read_command(&mut self) -> Result<Option<String>, InputError> {
+        loop {
+            let line = match self.editor.readline(PROMPT) {
+                Ok(line) => line,
+                Err(ReadlineError::Interrupted) => continue,
+                Err(ReadlineError::Eof) => return Ok(None),
+                Err(err) => return Err(editor_error("No se pudo leer la entrada", err)),
+            };
+
+            let line = line.trim();
+            if line.is_empty() {
+                continue;
+            }
+            self.editor
+                .add_history_entry(line)
+                .map_err(|err| editor_error("No se pudo guardar el comando en el historial", err))?;
+            if SESSION_COMMANDS.contains(&line) {
+                return Ok(None);
+            }
+            return Ok(Some(line.to_string()));
+        }
+    }
+
+    pub fn save_history(&mut self) -> Result<(), InputError> {
+        self.editor
+            .save_history(&self.history_path)
+            .map_err(|err| editor_error("No se pudo guardar el historial de comandos", err))
+    }
+}
+
+fn editor_error(message: &str, err: ReadlineError) -> InputError {
+    InputError::with_cause(message, Box::new(err))
+}
+
+// Autocompletado con <Tab>
+struct CommandHelper {
+    data_dir: String,
+}
+
+impl CommandHelper {
+    // Candidatos para la palabra que se esta escribiendo al final de `line`,
+    // junto con la posicion donde empieza
+    fn candidates(&self, line: &str) -> (usize, Vec<Pair>) {
+        let words: Vec<&str> = line.split_whitespace().collect();
+        let current = match words.last() {
+            Some(word) if !line.ends_with(char::is_whitespace) => *word,
+            _ => "",
+        };
+        let start = line.len() - current.len();
+        let previous = &words[..words.len() - usize::from(!current.is_empty())];
+
+        let options: Vec<&str> = match previous {
+            [] => COMMANDS.iter().chain(SESSION_COMMANDS).copied().collect(),
+            ["help"] => COMMANDS.to_vec(),
+            [command] if !subcommands(command).is_empty() => subcommands(command).to_vec(),
+            [command, ..] if current.starts_with("--") => flag_names(command),
+            [command, ..] => {
+                let flag = previous.iter().rev().find(|word| word.starts_with("--"));
+                match (*command, flag.copied()) {
+                    ("summary", Some("--by")) => vec!["category", "tag"],
+                    ("list", Some("--sort")) => SortKey::NAMES.split(", ").collect(),
+                    ("list" | "summary", Some("--format")) => {
+                        OutputFormat::NAMES.split(", ").collect()
+                    }
+                    (command, Some(flag)) if CATEGORY_FLAGS.contains(&(command, flag)) => {
+                        return self.category_candidates(line, flag);
+                    }
+                    _ => Vec::new(),
+                }
+            }
+        };
+
+        // se agrega el espacio para seguir escribiendo el siguiente argumento
+        let pairs = options
+            .into_iter()
+            .filter(|option| option.starts_with(current))
+            .map(|option| Pair {
+                display: option.to_string(),
+                replacement: format!("{} ", option),
+            })
+            .collect();
+        (start, pairs)
+    }
+
+    // Las categorias pueden tener varias palabras, asi que se completa todo el
+    // texto despues del flag, entre comillas si hace falta
+    fn category_candidates(&self, line: &str, flag: &str) -> (usize, Vec<Pair>) {
+        let Some(flag_end) = line.rfind(flag).map(|index| index + flag.len()) else {
+            return (line.len(), Vec::new());
+        };
+        let value = line[flag_end..].trim_start();
+        let start = line.len() - value.len();
+        let typed = value.trim_start_matches(['"', '\'']).to_lowercase();
+
+        let categories = match CategoryStore::new(&self.data_dir).load() {
+            Ok(categories) => categories,
+            Err(_) => return (start, Vec::new()),
+        };
+        let pairs = categories
+            .names()
+            .iter()
+            .filter(|name| name.to_lowercase().starts_with(&typed))
+            .map(|name| Pair {
+                display: name.clone(),
+                replacement: if name.contains(char::is_whitespace) {
+                    format!("\"{}\"", name)
+                } else {
+                    name.clone()
+                },
+            })
+            .collect();
+        (start, pairs)
+    }
+}
+
+impl Completer for CommandHelper {
+    type Candidate = Pair;
+
+    fn complete(
+        &self,
+        line: &str,
+        pos: usize,
+        _ctx: &Context<'_>,
+    ) -> rustyline::Result<(usize, Vec<Pair>)> {
+        Ok(self.candidates(&line[..pos]))
+    }
+}
+
+impl Hinter for CommandHelper {
+    type Hint = String;
+}
+
+impl Highlighter for CommandHelper {}
+
+impl Validator for CommandHelper {}
+
+impl Helper for CommandHelper {}
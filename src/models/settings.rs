@@ -0,0 +1,148 @@
+use std::{env, path::PathBuf, time::Duration};
+
+use super::{
+    error::{ConfigError, ConfigErrorKind},
+    money::{parse_currency, DEFAULT_CURRENCY},
+};
+use crate::data::expense_storage::StorageKind;
+
+// Variables de entorno que se consultan cuando no se pasa el flag equivalente
+pub const DATA_DIR_ENV: &str = "EXPENSE_TRACKER_DATA_DIR";
+pub const STORAGE_ENV: &str = "EXPENSE_TRACKER_STORAGE";
+pub const LOCK_TIMEOUT_ENV: &str = "EXPENSE_TRACKER_LOCK_TIMEOUT";
+pub const BASE_CURRENCY_ENV: &str = "EXPENSE_TRACKER_BASE_CURRENCY";
+
+// Opciones de arranque de la aplicacion (donde estan los datos y como se abren).
+// Cada opcion se toma, en orden, del flag de linea de comandos, de la variable
+// de entorno y por ultimo de un valor por defecto. Las opciones van antes del
+// comando: `expense-tracker --storage sqlite list` o
+// `expense-tracker --storage=sqlite list`.
+#[derive(Debug)]
+pub struct Settings {
+    pub data_dir: String,
+    pub storage: StorageKind,
+    pub lock_timeout: Option<Duration>,
+    // moneda en la que se muestran los resumenes y de los gastos sin --currency
+    pub base_currency: String,
+    // comando a ejecutar una sola vez con sus argumentos; vacio para el modo
+    // interactivo
+    pub command: Vec<String>,
+}
+
+impl Settings {
+    pub fn build(args: &[String]) -> Result<Settings, ConfigError> {
+        let mut data_dir: Option<String> = None;
+        let mut storage: Option<String> = None;
+        let mut lock_timeout: Option<String> = None;
+        let mut base_currency: Option<String> = None;
+
+        let mut parts = args.iter();
+        let mut command = Vec::new();
+        while let Some(item) = parts.next() {
+            // las opciones aceptan tambien la forma `--storage=sqlite`
+            let (item, inline_value) = match item.split_once('=') {
+                Some((flag, value)) if item.starts_with("--") => (flag, Some(value)),
+                _ => (item.as_str(), None),
+            };
+            let target = match item {
+                "--data-dir" => &mut data_dir,
+                "--storage" => &mut storage,
+                "--lock-timeout" => &mut lock_timeout,
+                "--base-currency" => &mut base_currency,
+                // lo que sigue es el comando
+                _ if !item.starts_with("--") => {
+                    command.push(item.to_string());
+                    command.extend(parts.by_ref().cloned());
+                    break;
+                }
+                _ => {
+                    return Err(ConfigError::new(
+                        ConfigErrorKind::InvalidCommand,
+                        &format!("Argumento desconocido: {}", item),
+                    ));
+                }
+            };
+
+            let value = match inline_value {
+                Some(value) => Some(value),
+                None => parts.next().map(String::as_str),
+            };
+            match value {
+                Some(value) if !value.trim().is_empty() => *target = Some(value.to_string()),
+                _ => {
+                    return Err(ConfigError::new(
+                        ConfigErrorKind::MissingArguments,
+                        &format!("Falta el valor de {}", item),
+                    ));
+                }
+            }
+        }
+
+        let data_dir = match data_dir.or_else(|| env::var(DATA_DIR_ENV).ok()) {
+            Some(dir) => dir,
+            None => default_data_dir()?,
+        };
+
+        let storage = match storage.or_else(|| env::var(STORAGE_ENV).ok()) {
+            Some(name) => StorageKind::parse(&name)
+                .map_err(|err| ConfigError::new(ConfigErrorKind::InvalidTypeData, err.message()))?,
+            None => StorageKind::Json,
+        };
+
+        let lock_timeout = match lock_timeout.or_else(|| env::var(LOCK_TIMEOUT_ENV).ok()) {
+            Some(seconds) => Some(parse_seconds(&seconds)?),
+            None => None,
+        };
+
+        let base_currency = match base_currency.or_else(|| env::var(BASE_CURRENCY_ENV).ok()) {
+            Some(currency) => parse_currency(&currency)
+                .map_err(|err| ConfigError::new(ConfigErrorKind::InvalidTypeData, err.message()))?,
+            None => DEFAULT_CURRENCY.to_string(),
+        };
+
+        Ok(Settings {
+            data_dir,
+            storage,
+            lock_timeout,
+            base_currency,
+            command,
+        })
+    }
+}
+
+// Directorio por defecto al estilo XDG: `$XDG_DATA_HOME/expense_tracker` o
+// `~/.local/share/expense_tracker` si la variable no esta definida
+fn default_data_dir() -> Result<String, ConfigError> {
+    let base = match env::var_os("XDG_DATA_HOME").filter(|dir| !dir.is_empty()) {
+        Some(dir) => PathBuf::from(dir),
+        None => {
+            let home = env::var_os("HOME")
+                .or_else(|| env::var_os("USERPROFILE"))
+                .ok_or(ConfigError::new(
+                    ConfigErrorKind::MissingArguments,
+                    &format!(
+                        "No se encontro el directorio personal, use --data-dir o {}",
+                        DATA_DIR_ENV
+                    ),
+                ))?;
+            PathBuf::from(home).join(".local").join("share")
+        }
+    };
+
+    Ok(base.join("expense_tracker").to_string_lossy().to_string())
+}
+
+fn parse_seconds(seconds: &str) -> Result<Duration, ConfigError> {
+    seconds
+        .trim()
+        .parse::<f64>()
+        .ok()
+        .and_then(|value| Duration::try_from_secs_f64(value).ok())
+        .ok_or(ConfigError::new(
+            ConfigErrorKind::InvalidTypeData,
+            &format!(
+                "El tiempo de espera debe ser un numero de segundos: {}",
+                seconds
+            ),
+        ))
+}
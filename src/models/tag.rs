@@ -0,0 +1,200 @@
+use chrono::Utc;
+
+use super::{
+    error::{ExpenseError, ExpenseErrorKind},
+    expense::Expense,
+};
+use crate::data::{expense_storage::ExpenseStorage, query::ExpenseQuery};
+
+// Etiquetas libres de los gastos ("#trabajo", "#viaje-bogota"), independientes
+// de la categoria. Se guardan sin el `#` y en minusculas, asi "#Trabajo" y
+// "trabajo" son la misma etiqueta.
+
+// Normaliza una etiqueta: sin el `#` inicial y en minusculas. Solo puede tener
+// letras, numeros, `-` y `_`.
+pub fn parse_tag(tag: &str) -> Result<String, ExpenseError> {
+    let normalized = tag.trim().trim_start_matches('#').to_lowercase();
+    let valid = normalized
+        .chars()
+        .all(|c| c.is_alphanumeric() || c == '-' || c == '_');
+    if normalized.is_empty() || !valid {
+        return Err(ExpenseError::new(
+            ExpenseErrorKind::InvalidTag,
+            &format!(
+                "Etiqueta no valida {}: solo puede tener letras, numeros, - y _",
+                tag
+            ),
+            "tag::parse_tag",
+        ));
+    }
+    Ok(normalized)
+}
+
+// Normaliza varias etiquetas, ordenadas y sin repetir
+pub fn parse_tags(tags: &[String]) -> Result<Vec<String>, ExpenseError> {
+    let mut parsed = tags
+        .iter()
+        .map(|tag| parse_tag(tag))
+        .collect::<Result<Vec<String>, ExpenseError>>()?;
+    parsed.sort();
+    parsed.dedup();
+    Ok(parsed)
+}
+
+// Las etiquetas como se muestran: "#trabajo #viaje-bogota"
+pub fn format_tags(tags: &[String]) -> String {
+    tags.iter()
+        .map(|tag| format!("#{}", tag))
+        .collect::<Vec<String>>()
+        .join(" ")
+}
+
+// Agrega etiquetas al gasto `id`. Devuelve como quedaron sus etiquetas.
+pub fn add(
+    storage: &mut dyn ExpenseStorage,
+    id: &str,
+    tags: &[String],
+) -> Result<Vec<String>, ExpenseError> {
+    let tags = parse_tags(tags)?;
+    retag(storage, id, "tag::add", |current| {
+        current.extend(tags);
+        current.sort();
+        current.dedup();
+    })
+}
+
+// Quita etiquetas del gasto `id`. Devuelve como quedaron sus etiquetas.
+pub fn remove(
+    storage: &mut dyn ExpenseStorage,
+    id: &str,
+    tags: &[String],
+) -> Result<Vec<String>, ExpenseError> {
+    let tags = parse_tags(tags)?;
+    retag(storage, id, "tag::remove", |current| {
+        current.retain(|tag| !tags.contains(tag));
+    })
+}
+
+// Resultado de `list` para una etiqueta
+#[derive(Debug, Clone)]
+pub struct TagCount {
+    pub tag: String,
+    pub expenses: usize,
+}
+
+// Cada etiqueta en uso, en orden, con la cantidad de gastos que la tienen
+pub fn list(storage: &dyn ExpenseStorage) -> Result<Vec<TagCount>, ExpenseError> {
+    let expenses = storage.load().map_err(|err| {
+        ExpenseError::new(
+            ExpenseErrorKind::ReadError,
+            &format!("Error leyendo los gastos {}", err),
+            "tag::list",
+        )
+    })?;
+
+    let mut tags: Vec<&String> = expenses.iter().flat_map(|expense| &expense.tags).collect();
+    tags.sort();
+
+    Ok(tags
+        .chunk_by(|a, b| a == b)
+        .map(|tag| TagCount {
+            tag: tag[0].clone(),
+            expenses: tag.len(),
+        })
+        .collect())
+}
+
+// Cambia las etiquetas del gasto `id` con `change`, en una transaccion
+fn retag(
+    storage: &mut dyn ExpenseStorage,
+    id: &str,
+    location: &str,
+    change: impl FnOnce(&mut Vec<String>),
+) -> Result<Vec<String>, ExpenseError> {
+    let write_error = |err: String| ExpenseError::new(ExpenseErrorKind::WriteError, &err, location);
+
+    storage.begin().map_err(|err| write_error(err.to_string()))?;
+    let updated = storage
+        .query(&ExpenseQuery::new().with_ids(vec![id.to_string()]))
+        .map_err(|err| write_error(err.to_string()))
+        .and_then(|expenses| {
+            let mut expense: Expense = expenses.into_iter().next().ok_or(ExpenseError::new(
+                ExpenseErrorKind::IdsErrorType,
+                &format!("No existe un gasto con id {}", id),
+                location,
+            ))?;
+            let before = expense.tags.clone();
+            change(&mut expense.tags);
+            // agregar una etiqueta que ya tiene o quitar una que no tiene no
+            // cambia el gasto
+            if expense.tags != before {
+                expense.updated_at = Some(Utc::now());
+                storage
+                    .update(&expense)
+                    .map_err(|err| write_error(err.to_string()))?;
+            }
+            Ok(expense.tags)
+        });
+
+    match updated {
+        Ok(tags) => {
+            storage.commit().map_err(|err| write_error(err.to_string()))?;
+            Ok(tags)
+        }
+        Err(err) => {
+            let _ = storage.rollback();
+            Err(err)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn texts(tags: &[&str]) -> Vec<String> {
+        tags.iter().map(|tag| tag.to_string()).collect()
+    }
+
+    #[test]
+    fn parse_tag_removes_the_hash_and_lowercases() {
+        assert_eq!(parse_tag("#Trabajo").unwrap(), "trabajo");
+        assert_eq!(parse_tag("  viaje-Bogota ").unwrap(), "viaje-bogota");
+        assert_eq!(parse_tag("##año_2024").unwrap(), "año_2024");
+    }
+
+    #[test]
+    fn parse_tag_rejects_empty_tags_and_other_characters() {
+        for tag in ["", "#", "  ", "dos palabras", "a,b", "#a#b", "viaje/bogota"] {
+            let err = parse_tag(tag).unwrap_err();
+            assert!(
+                matches!(err.kind(), ExpenseErrorKind::InvalidTag),
+                "{}",
+                tag
+            );
+        }
+    }
+
+    #[test]
+    fn parse_tags_sorts_and_removes_duplicates() {
+        let tags = texts(&["#Viaje", "trabajo", "viaje", "#TRABAJO", "casa"]);
+        assert_eq!(parse_tags(&tags).unwrap(), ["casa", "trabajo", "viaje"]);
+        assert!(parse_tags(&[]).unwrap().is_empty());
+    }
+
+    #[test]
+    fn parse_tags_fails_if_any_tag_is_invalid() {
+        let err = parse_tags(&texts(&["casa", "no valida"])).unwrap_err();
+        assert!(matches!(err.kind(), ExpenseErrorKind::InvalidTag));
+        assert!(err.to_string().contains("no valida"));
+    }
+
+    #[test]
+    fn format_tags_adds_the_hash() {
+        assert_eq!(
+            format_tags(&texts(&["casa", "viaje-bogota"])),
+            "#casa #viaje-bogota"
+        );
+        assert_eq!(format_tags(&[]), "");
+    }
+}
@@ -0,0 +1,99 @@
+use chrono::{Duration, NaiveDate};
+
+use crate::models::error::{ConfigError, ConfigErrorKind};
+
+// Formato ISO de las fechas de los gastos
+pub const DATE_FORMAT: &str = "%Y-%m-%d";
+
+// Lee la fecha de un gasto tal como se escribe en un comando, relativa a
+// `today`: ISO (`2024-09-30`), `dd/mm/yyyy` (`30/09/2024`), `today`,
+// `yesterday` o una cantidad de dias hacia atras (`-3d`)
+pub fn parse_date(text: &str, today: NaiveDate) -> Result<NaiveDate, ConfigError> {
+    let invalid = || {
+        ConfigError::new(
+            ConfigErrorKind::InvalidTypeData,
+            &format!(
+                "Fecha no valida {} (use AAAA-MM-DD, DD/MM/AAAA, today, yesterday o -Nd)",
+                text
+            ),
+        )
+    };
+
+    let text = text.trim();
+    match text.to_lowercase().as_str() {
+        "today" => return Ok(today),
+        "yesterday" => return Ok(today - Duration::days(1)),
+        _ => {}
+    }
+
+    if let Some(days) = text.strip_prefix('-').and_then(|rest| rest.strip_suffix(['d', 'D'])) {
+        let days = days.parse::<u32>().map_err(|_| invalid())?;
+        return today
+            .checked_sub_signed(Duration::days(i64::from(days)))
+            .ok_or_else(invalid);
+    }
+
+    NaiveDate::parse_from_str(text, DATE_FORMAT)
+        .or_else(|_| NaiveDate::parse_from_str(text, "%d/%m/%Y"))
+        .map_err(|_| invalid())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn day(year: i32, month: u32, day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(year, month, day).unwrap()
+    }
+
+    fn parse(text: &str) -> Result<NaiveDate, ConfigError> {
+        parse_date(text, day(2024, 3, 1))
+    }
+
+    #[test]
+    fn parses_iso_and_day_month_year() {
+        assert_eq!(parse("2024-09-30").unwrap(), day(2024, 9, 30));
+        assert_eq!(parse(" 2023-12-31 ").unwrap(), day(2023, 12, 31));
+        assert_eq!(parse("30/09/2024").unwrap(), day(2024, 9, 30));
+        assert_eq!(parse("1/2/2024").unwrap(), day(2024, 2, 1));
+        assert_eq!(parse("29/02/2024").unwrap(), day(2024, 2, 29));
+    }
+
+    #[test]
+    fn parses_dates_relative_to_today() {
+        assert_eq!(parse("today").unwrap(), day(2024, 3, 1));
+        assert_eq!(parse("TODAY").unwrap(), day(2024, 3, 1));
+        assert_eq!(parse("yesterday").unwrap(), day(2024, 2, 29));
+        assert_eq!(parse("-3d").unwrap(), day(2024, 2, 27));
+        assert_eq!(parse("-0d").unwrap(), day(2024, 3, 1));
+        assert_eq!(parse("-366D").unwrap(), day(2023, 3, 1));
+    }
+
+    #[test]
+    fn rejects_invalid_dates() {
+        for text in [
+            "31/02/2024",
+            "29/02/2023",
+            "2024-02-30",
+            "-d",
+            "-xd",
+            "--3d",
+            "3d",
+            "-3",
+            "-4294967296d",
+            "manana",
+            "",
+        ] {
+            let err = parse(text).unwrap_err();
+            assert!(
+                matches!(err.kind(), ConfigErrorKind::InvalidTypeData),
+                "{}",
+                text
+            );
+        }
+        assert!(parse("31/02/2024")
+            .unwrap_err()
+            .to_string()
+            .contains("31/02/2024"));
+    }
+}
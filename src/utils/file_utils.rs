@@ -1,4 +1,9 @@
-use std::{fs::File, io::BufReader, path::Path};
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{self, BufReader, BufWriter, Write},
+    path::{Path, PathBuf},
+    process,
+};
 
 use serde_json::{json, Map, Value};
 
@@ -12,7 +17,7 @@ pub enum JsonStructure {
 pub fn open_json(path: &str) -> Result<JsonStructure, JsonFileError> {
     let path = Path::new(path);
     //se abre el file
-    let file = match File::open(&path) {
+    let file = match File::open(path) {
         Ok(file) => file,
         Err(err) => {
             let kind = match err.kind() {
@@ -53,9 +58,14 @@ pub fn open_json(path: &str) -> Result<JsonStructure, JsonFileError> {
     }
 }
 
+// Guarda el JSON de forma atomica: primero se escribe un archivo temporal en el
+// mismo directorio, se sincroniza a disco y luego se renombra sobre el original.
+// Si algo falla a mitad de camino el archivo original queda intacto.
 pub fn save_json(path: &str, json_data: JsonStructure) -> Result<(), JsonFileError> {
     let path = Path::new(&path);
-    let mut file = match File::create(&path) {
+    let tmp_path = temp_path(path);
+
+    let file = match OpenOptions::new().write(true).create(true).truncate(true).open(&tmp_path) {
         Ok(file) => file,
         Err(err) => {
             let kind = match err.kind() {
@@ -66,7 +76,7 @@ pub fn save_json(path: &str, json_data: JsonStructure) -> Result<(), JsonFileErr
 
             return Err(JsonFileError::new(
                 kind,
-                &format!("No se pudo modificar el archivo: {}", err),
+                &format!("No se pudo crear el archivo temporal: {}", err),
                 path.to_string_lossy().as_ref(),
             ));
         }
@@ -77,16 +87,137 @@ pub fn save_json(path: &str, json_data: JsonStructure) -> Result<(), JsonFileErr
         JsonStructure::Array(vec) => json!(vec),
     };
 
+    //se escribe y se sincroniza el temporal, si falla se descarta
+    if let Err(err) = write_and_sync(file, &json_value) {
+        let _ = fs::remove_file(&tmp_path);
+        return Err(JsonFileError::new(
+            JsonFileErrorKind::PartialWrite,
+            &format!("Error al escribir el archivo, no se modifico el original: {}", err),
+            path.to_string_lossy().as_ref(),
+        ));
+    }
 
-    match serde_json::to_writer(&mut file, &json_value) {
-        Ok(_) => (),
-        Err(err) => {
-            return Err(JsonFileError::new(
-                JsonFileErrorKind::WriteError,
-                &format!("Error al escribir el archivo: {}", err),
-                path.to_string_lossy().as_ref(),
-            ))
-        }
+    //se reemplaza el original por el temporal en una sola operacion
+    if let Err(err) = fs::rename(&tmp_path, path) {
+        let _ = fs::remove_file(&tmp_path);
+        return Err(JsonFileError::new(
+            JsonFileErrorKind::WriteError,
+            &format!("No se pudo reemplazar el archivo: {}", err),
+            path.to_string_lossy().as_ref(),
+        ));
     }
+
+    sync_parent_dir(path);
+
     Ok(())
 }
+
+// Archivo temporal oculto junto al destino, para que el rename no cruce sistemas de archivos
+fn temp_path(path: &Path) -> PathBuf {
+    let file_name = path
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_default();
+    path.with_file_name(format!(".{}.{}.tmp", file_name, process::id()))
+}
+
+fn write_and_sync(file: File, json_value: &Value) -> io::Result<()> {
+    let mut writer = BufWriter::new(file);
+    serde_json::to_writer(&mut writer, json_value)?;
+    writer.flush()?;
+    let file = writer.into_inner().map_err(|err| err.into_error())?;
+    file.sync_all()
+}
+
+// En unix el rename solo es durable cuando se sincroniza el directorio que lo contiene
+#[cfg(unix)]
+fn sync_parent_dir(path: &Path) {
+    let parent = match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+    if let Ok(dir) = File::open(parent) {
+        let _ = dir.sync_all();
+    }
+}
+
+#[cfg(not(unix))]
+fn sync_parent_dir(_path: &Path) {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::test_utils::TempDir;
+
+    fn object(value: Value) -> JsonStructure {
+        match value {
+            Value::Object(map) => JsonStructure::Object(map),
+            _ => panic!("se esperaba un objeto"),
+        }
+    }
+
+    fn leftovers(dir: &TempDir) -> Vec<String> {
+        fs::read_dir(dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().to_string())
+            .filter(|name| name.ends_with(".tmp"))
+            .collect()
+    }
+
+    #[test]
+    fn save_replaces_the_file_without_leaving_the_temporary() {
+        let dir = TempDir::new("save_json");
+        let path = dir.join("ids.json");
+        let path = path.to_str().unwrap();
+
+        save_json(path, object(json!({"id": 1}))).unwrap();
+        save_json(path, JsonStructure::Array(vec![json!(1), json!(2)])).unwrap();
+
+        match open_json(path).unwrap() {
+            JsonStructure::Array(values) => assert_eq!(values, [json!(1), json!(2)]),
+            JsonStructure::Object(_) => panic!("se esperaba un array"),
+        }
+        assert!(leftovers(&dir).is_empty());
+    }
+
+    // El temporal apunta a /dev/full: crearlo funciona pero escribirlo no
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn failed_write_keeps_the_original() {
+        let dir = TempDir::new("save_json_full");
+        let path = dir.join("ids.json");
+        let path = path.to_str().unwrap();
+        save_json(path, object(json!({"id": 7}))).unwrap();
+        let original = fs::read_to_string(path).unwrap();
+
+        std::os::unix::fs::symlink("/dev/full", temp_path(Path::new(path))).unwrap();
+        let err = save_json(path, object(json!({"id": 8}))).unwrap_err();
+        assert!(matches!(err.kind(), JsonFileErrorKind::PartialWrite));
+        assert_eq!(fs::read_to_string(path).unwrap(), original);
+        assert!(leftovers(&dir).is_empty());
+    }
+
+    #[test]
+    fn failed_rename_keeps_the_original() {
+        let dir = TempDir::new("save_json_rename");
+        // el destino es un directorio con contenido: no se puede reemplazar
+        let path = dir.join("expenses.json");
+        fs::create_dir(&path).unwrap();
+        fs::write(path.join("dentro"), "original").unwrap();
+
+        let err = save_json(path.to_str().unwrap(), object(json!({}))).unwrap_err();
+        assert!(matches!(err.kind(), JsonFileErrorKind::WriteError));
+        assert_eq!(fs::read_to_string(path.join("dentro")).unwrap(), "original");
+        assert!(leftovers(&dir).is_empty());
+    }
+
+    #[test]
+    fn missing_directory_is_reported() {
+        let dir = TempDir::new("save_json_missing");
+        let path = dir.join("no_existe").join("ids.json");
+
+        let err = save_json(path.to_str().unwrap(), object(json!({}))).unwrap_err();
+        assert!(matches!(err.kind(), JsonFileErrorKind::FileNotFound));
+        assert!(!path.exists());
+    }
+}
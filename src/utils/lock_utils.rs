@@ -0,0 +1,290 @@
+use std::{
+    fs::{self, OpenOptions},
+    io::Write,
+    path::{Path, PathBuf},
+    process, thread,
+    time::{Duration, Instant},
+};
+
+use chrono::{DateTime, Utc};
+use serde_json::json;
+
+use crate::models::error::{JsonFileError, JsonFileErrorKind};
+
+// Cada cuanto se vuelve a intentar tomar el lock mientras se espera
+const RETRY_INTERVAL: Duration = Duration::from_millis(100);
+// Ninguna operacion tiene el lock tanto tiempo: pasado este plazo se supone
+// que el proceso murio aunque su PID siga en uso por otro programa
+const STALE_AFTER: Duration = Duration::from_secs(60 * 60);
+
+// Lock de archivo entre procesos. El lock es el propio archivo: se crea de forma
+// exclusiva con el PID y la hora del proceso que lo tiene, y se borra al soltarlo.
+// Es un lock consultivo, solo protege contra procesos que tambien lo usan.
+// Si el proceso que lo tiene ya no existe (se cayo sin soltarlo) el lock se
+// considera abandonado y se toma.
+#[derive(Debug)]
+pub struct FileLock {
+    path: PathBuf,
+}
+
+impl FileLock {
+    // Intenta tomar el lock. Con `timeout` en `None` falla enseguida si otro
+    // proceso lo tiene; si no, reintenta hasta que pase el tiempo indicado.
+    pub fn acquire(path: &str, timeout: Option<Duration>) -> Result<FileLock, JsonFileError> {
+        let path = Path::new(path);
+        let start = Instant::now();
+
+        loop {
+            match OpenOptions::new().write(true).create_new(true).open(path) {
+                Ok(mut file) => {
+                    let holder = json!({
+                        "pid": process::id(),
+                        "created_at": Utc::now().to_rfc3339(),
+                    });
+                    if let Err(err) = file.write_all(holder.to_string().as_bytes()) {
+                        let _ = fs::remove_file(path);
+                        return Err(JsonFileError::new(
+                            JsonFileErrorKind::WriteError,
+                            &format!("No se pudo escribir el archivo de lock: {}", err),
+                            path.to_string_lossy().as_ref(),
+                        ));
+                    }
+                    return Ok(FileLock {
+                        path: path.to_path_buf(),
+                    });
+                }
+                Err(err) if err.kind() == std::io::ErrorKind::AlreadyExists => {
+                    if remove_stale(path) {
+                        continue;
+                    }
+                    let waited_enough = match timeout {
+                        Some(timeout) => start.elapsed() >= timeout,
+                        None => true,
+                    };
+                    if waited_enough {
+                        return Err(JsonFileError::new(
+                            JsonFileErrorKind::Locked,
+                            &format!(
+                                "La base de datos esta bloqueada por un proceso en ejecucion ({}). Vuelva a intentarlo o use --lock-timeout para esperar a que la suelte",
+                                lock_holder(path)
+                            ),
+                            path.to_string_lossy().as_ref(),
+                        ));
+                    }
+                    thread::sleep(RETRY_INTERVAL);
+                }
+                Err(err) => {
+                    let kind = match err.kind() {
+                        std::io::ErrorKind::NotFound => JsonFileErrorKind::FileNotFound,
+                        std::io::ErrorKind::PermissionDenied => JsonFileErrorKind::PermissionDenied,
+                        _ => JsonFileErrorKind::CreateError,
+                    };
+                    return Err(JsonFileError::new(
+                        kind,
+                        &format!("No se pudo crear el archivo de lock: {}", err),
+                        path.to_string_lossy().as_ref(),
+                    ));
+                }
+            }
+        }
+    }
+}
+
+impl Drop for FileLock {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+// Borra el archivo de lock si lo dejo un proceso que ya no existe o si es mas
+// viejo que `STALE_AFTER`. Devuelve `true` si lo borro.
+fn remove_stale(path: &Path) -> bool {
+    let Ok(content) = fs::read_to_string(path) else {
+        return false;
+    };
+    let holder = serde_json::from_str::<serde_json::Value>(&content).ok();
+    let pid = holder
+        .as_ref()
+        .and_then(|holder| holder.get("pid"))
+        .and_then(|pid| pid.as_u64())
+        .and_then(|pid| u32::try_from(pid).ok());
+    let created_at = holder
+        .as_ref()
+        .and_then(|holder| holder.get("created_at"))
+        .and_then(|date| date.as_str())
+        .and_then(|date| DateTime::parse_from_rfc3339(date).ok())
+        .map(|date| date.with_timezone(&Utc));
+
+    // sin fecha (el archivo se esta escribiendo o esta danado) se usa la de
+    // la ultima modificacion
+    let age = match created_at {
+        Some(created_at) => (Utc::now() - created_at).to_std().unwrap_or_default(),
+        None => match fs::metadata(path).and_then(|metadata| metadata.modified()) {
+            Ok(modified) => modified.elapsed().unwrap_or_default(),
+            Err(_) => return false,
+        },
+    };
+    let dead = pid.is_some_and(|pid| !process_alive(pid));
+    if !dead && age < STALE_AFTER {
+        return false;
+    }
+
+    // otro proceso pudo tomar el lock abandonado mientras tanto: solo se
+    // borra si sigue siendo el mismo archivo
+    if fs::read_to_string(path).ok().as_deref() != Some(content.as_str()) {
+        return false;
+    }
+    fs::remove_file(path).is_ok()
+}
+
+// `true` si existe un proceso con ese PID. Con `kill` y la senal 0 no se
+// envia nada, solo se comprueba; EPERM significa que existe pero es de otro
+// usuario.
+#[cfg(unix)]
+fn process_alive(pid: u32) -> bool {
+    let Ok(pid) = libc::pid_t::try_from(pid) else {
+        return false;
+    };
+    if pid == 0 {
+        return false;
+    }
+    // SAFETY: kill con la senal 0 no tiene efectos, solo valida el PID
+    let result = unsafe { libc::kill(pid, 0) };
+    result == 0 || std::io::Error::last_os_error().raw_os_error() == Some(libc::EPERM)
+}
+
+// Fuera de unix no se puede consultar el PID; solo cuenta la antiguedad
+#[cfg(not(unix))]
+fn process_alive(_pid: u32) -> bool {
+    true
+}
+
+// Describe quien tiene el lock a partir del contenido del archivo
+fn lock_holder(path: &Path) -> String {
+    let content = match fs::read_to_string(path) {
+        Ok(content) if !content.trim().is_empty() => content,
+        _ => return "proceso desconocido".to_string(),
+    };
+
+    match serde_json::from_str::<serde_json::Value>(&content) {
+        Ok(holder) => format!(
+            "pid {}, desde {}",
+            holder
+                .get("pid")
+                .map(|pid| pid.to_string())
+                .unwrap_or_default(),
+            holder
+                .get("created_at")
+                .and_then(|date| date.as_str())
+                .unwrap_or("?")
+        ),
+        Err(_) => content.trim().to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::process::Command;
+
+    use super::*;
+    use crate::utils::test_utils::TempDir;
+
+    fn write_holder(path: &Path, pid: u32, created_at: DateTime<Utc>) {
+        let holder = json!({"pid": pid, "created_at": created_at.to_rfc3339()});
+        fs::write(path, holder.to_string()).unwrap();
+    }
+
+    fn holder_pid(path: &Path) -> Option<u64> {
+        let holder: serde_json::Value =
+            serde_json::from_str(&fs::read_to_string(path).unwrap()).unwrap();
+        holder.get("pid").and_then(|pid| pid.as_u64())
+    }
+
+    #[test]
+    fn acquire_writes_the_holder_and_release_removes_it() {
+        let dir = TempDir::new("lock");
+        let path = dir.join("expenses.lock");
+
+        let lock = FileLock::acquire(path.to_str().unwrap(), None).unwrap();
+        assert_eq!(holder_pid(&path), Some(process::id().into()));
+        drop(lock);
+        assert!(!path.exists());
+        FileLock::acquire(path.to_str().unwrap(), None).unwrap();
+    }
+
+    #[test]
+    fn lock_held_by_a_live_process_fails() {
+        let dir = TempDir::new("lock_held");
+        let path = dir.join("expenses.lock");
+        write_holder(&path, process::id(), Utc::now());
+
+        for timeout in [None, Some(Duration::ZERO)] {
+            let err = FileLock::acquire(path.to_str().unwrap(), timeout).unwrap_err();
+            assert!(matches!(err.kind(), JsonFileErrorKind::Locked));
+            assert!(err.to_string().contains(&format!("pid {}", process::id())));
+            assert!(err.to_string().contains("--lock-timeout"));
+        }
+        assert!(path.exists());
+    }
+
+    #[test]
+    fn timeout_waits_for_the_holder() {
+        let dir = TempDir::new("lock_timeout");
+        let path = dir.join("expenses.lock");
+        let path = path.to_str().unwrap();
+
+        let start = Instant::now();
+        let lock = FileLock::acquire(path, None).unwrap();
+        let err = FileLock::acquire(path, Some(Duration::from_millis(300))).unwrap_err();
+        assert!(matches!(err.kind(), JsonFileErrorKind::Locked));
+        assert!(start.elapsed() >= Duration::from_millis(300));
+
+        let holder = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(200));
+            drop(lock);
+        });
+        FileLock::acquire(path, Some(Duration::from_secs(5))).unwrap();
+        holder.join().unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn lock_of_a_dead_process_is_taken_over() {
+        let dir = TempDir::new("lock_dead");
+        let path = dir.join("expenses.lock");
+        // PID de un proceso que ya termino
+        let mut child = Command::new("true").spawn().unwrap();
+        let pid = child.id();
+        child.wait().unwrap();
+        write_holder(&path, pid, Utc::now());
+
+        let _lock = FileLock::acquire(path.to_str().unwrap(), None).unwrap();
+        assert_eq!(holder_pid(&path), Some(process::id().into()));
+    }
+
+    #[test]
+    fn expired_lock_is_taken_over() {
+        let dir = TempDir::new("lock_expired");
+        let path = dir.join("expenses.lock");
+        // el PID existe (es este proceso) pero el lock tiene mas de una hora
+        let created_at = Utc::now() - STALE_AFTER - chrono::Duration::minutes(1);
+        write_holder(&path, process::id(), created_at);
+
+        let _lock = FileLock::acquire(path.to_str().unwrap(), None).unwrap();
+        let content = fs::read_to_string(&path).unwrap();
+        assert!(!content.contains(&created_at.to_rfc3339()));
+    }
+
+    #[test]
+    fn unreadable_recent_lock_is_kept() {
+        let dir = TempDir::new("lock_garbage");
+        let path = dir.join("expenses.lock");
+        // se esta escribiendo: sin PID ni fecha cuenta la de modificacion
+        fs::write(&path, "").unwrap();
+
+        let err = FileLock::acquire(path.to_str().unwrap(), None).unwrap_err();
+        assert!(matches!(err.kind(), JsonFileErrorKind::Locked));
+        assert!(err.to_string().contains("proceso desconocido"));
+        assert!(path.exists());
+    }
+}
@@ -1,2 +1,6 @@
 pub mod io_utils;
-pub mod file_utils;
\ No newline at end of file
+pub mod file_utils;
+pub mod lock_utils;
+pub mod date_utils;
+#[cfg(test)]
+pub mod test_utils;